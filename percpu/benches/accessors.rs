@@ -0,0 +1,66 @@
+//! Benchmarks of the per-CPU data accessors in hosted mode, compared with the
+//! alternatives (a global atomic variable and a thread-local variable).
+//!
+//! Run with `cargo bench -p percpu`, and with `--features sp-naive` or
+//! `--features preempt` to compare the naive and preemptible configurations.
+//! See the `bench-cycles` feature for bare-metal kernels.
+
+use std::cell::Cell;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+#[percpu::def_percpu]
+static COUNTER: u64 = 0;
+
+#[percpu::def_percpu]
+static PAIR: (u64, u64) = (0, 0);
+
+static GLOBAL: AtomicU64 = AtomicU64::new(0);
+
+std::thread_local! {
+    static LOCAL: Cell<u64> = const { Cell::new(0) };
+}
+
+fn init() {
+    percpu::init(1);
+    percpu::set_local_thread_pointer(0);
+}
+
+fn bench_read(c: &mut Criterion) {
+    init();
+    let mut group = c.benchmark_group("read");
+    group.bench_function("read_current_raw", |b| {
+        b.iter(|| unsafe { COUNTER.read_current_raw() })
+    });
+    group.bench_function("read_current", |b| b.iter(|| COUNTER.read_current()));
+    group.bench_function("with_current", |b| b.iter(|| COUNTER.with_current(|v| *v)));
+    group.bench_function("read_current_copy", |b| b.iter(|| PAIR.read_current()));
+    group.bench_function("global_atomic", |b| {
+        b.iter(|| GLOBAL.load(Ordering::Relaxed))
+    });
+    group.bench_function("thread_local", |b| b.iter(|| LOCAL.with(Cell::get)));
+    group.finish();
+}
+
+fn bench_update(c: &mut Criterion) {
+    init();
+    let mut group = c.benchmark_group("update");
+    group.bench_function("add_current", |b| {
+        b.iter(|| COUNTER.add_current(black_box(1)))
+    });
+    group.bench_function("with_current", |b| {
+        b.iter(|| COUNTER.with_current(|v| *v += black_box(1)))
+    });
+    group.bench_function("global_atomic", |b| {
+        b.iter(|| GLOBAL.fetch_add(black_box(1), Ordering::Relaxed))
+    });
+    group.bench_function("thread_local", |b| {
+        b.iter(|| LOCAL.with(|v| v.set(v.get() + black_box(1))))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_read, bench_update);
+criterion_main!(benches);
@@ -1,9 +1,69 @@
 use std::path::Path;
 
+/// The architectures whose thread pointer register is used to hold the per-CPU
+/// data area base, which must be kept in sync with `percpu_macros`.
+const SUPPORTED_ARCHES: &[&str] = &[
+    "x86",
+    "x86_64",
+    "aarch64",
+    "arm",
+    "riscv32",
+    "riscv64",
+    "loongarch64",
+];
+
 fn main() {
-    if cfg!(target_os = "linux") && cfg!(not(feature = "sp-naive")) {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // The maximum number of CPUs, e.g., the SMP configuration of the kernel.
+    println!("cargo:rerun-if-env-changed=PERCPU_MAX_CPUS");
+    // It is passed to the crate as is, and thus also to nested builds (e.g., of
+    // the compile-fail tests), so the empty value means not set as well.
+    let max_cpus = match std::env::var("PERCPU_MAX_CPUS") {
+        Ok(val) if val.trim().is_empty() => String::new(),
+        Ok(val) => match val.trim().parse::<u32>() {
+            Ok(max_cpus) if max_cpus > 0 => max_cpus.to_string(),
+            _ => panic!("invalid PERCPU_MAX_CPUS: {:?}", val),
+        },
+        Err(_) => String::new(),
+    };
+    println!("cargo:rustc-env=PERCPU_MAX_CPUS={}", max_cpus);
+
+    // On other architectures, the per-CPU data is just global variables, as if
+    // the "sp-naive" feature is enabled.
+    let target_arch = std::env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    let unsupported_arch = !SUPPORTED_ARCHES.contains(&target_arch.as_str());
+
+    // The `target_thread_local` cfg is unstable, so the "tls-compat" feature
+    // checks this one instead.
+    let target_os = std::env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    if std::env::var_os("CARGO_CFG_TARGET_THREAD_LOCAL").is_some() && target_os != "windows" {
+        println!("cargo:rustc-cfg=elf_tls");
+    }
+
+    // The per-CPU data is just global variables under Miri, which are not
+    // placed by the linker script.
+    let miri = std::env::var_os("CARGO_CFG_MIRI").is_some();
+
+    // The crate gates the naive implementation (`naive.rs`) on this one cfg, so
+    // that a new platform only needs to be added here.
+    let sp_naive = std::env::var_os("CARGO_FEATURE_SP_NAIVE").is_some();
+    if sp_naive || target_os == "macos" || miri || unsupported_arch {
+        println!("cargo:rustc-cfg=percpu_naive");
+    }
+
+    if cfg!(any(target_os = "linux", target_os = "freebsd"))
+        && !sp_naive
+        && !miri
+        && !unsupported_arch
+    {
         let ld_script_path = Path::new(std::env!("CARGO_MANIFEST_DIR")).join("test_percpu.x");
         println!("cargo:rustc-link-arg-tests=-no-pie");
         println!("cargo:rustc-link-arg-tests=-T{}", ld_script_path.display());
+        println!("cargo:rustc-link-arg-benches=-no-pie");
+        println!(
+            "cargo:rustc-link-arg-benches=-T{}",
+            ld_script_path.display()
+        );
     }
 }
@@ -0,0 +1,49 @@
+//! AArch64 specific helpers for the `arm-el2-runtime` feature, where the thread
+//! ID register that holds the per-CPU data area base is selected at runtime.
+//!
+//! A hypervisor may run at EL2 with or without VHE (`HCR_EL2.E2H`), and decide
+//! which register to use at boot, e.g., `TPIDR_EL2` if the kernel itself runs
+//! at EL2, or `TPIDR_EL1` if it runs at EL1 and only the world switch code runs
+//! at EL2. The generated accessors (and
+//! [`percpu_asm_access!`](crate::percpu_asm_access)) load the selection before
+//! reading the register, which costs a load and a branch, but no indirect call.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// `1` if `TPIDR_EL2` holds the per-CPU data area base, or `0` for `TPIDR_EL1`.
+/// It is word-sized, so that the generated assembly can load it into the
+/// destination register by its symbol.
+#[no_mangle]
+static __PERCPU_ARM_EL2: AtomicUsize = AtomicUsize::new(cfg!(feature = "arm-el2") as usize);
+
+/// Selects `TPIDR_EL2` (if `el2` is true) or `TPIDR_EL1` to hold the per-CPU
+/// data area base on all CPUs.
+///
+/// It must be called on the boot CPU before
+/// [`set_local_thread_pointer`](crate::set_local_thread_pointer), and before
+/// any other CPU is started. The initial selection is `TPIDR_EL2` if the
+/// `arm-el2` feature is enabled, or `TPIDR_EL1` otherwise.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// percpu::aarch64::set_el2(percpu::aarch64::current_el() == 2);
+/// percpu::init(4);
+/// percpu::set_local_thread_pointer(0);
+/// ```
+pub fn set_el2(el2: bool) {
+    __PERCPU_ARM_EL2.store(el2 as usize, Ordering::Relaxed);
+}
+
+/// Returns whether `TPIDR_EL2` holds the per-CPU data area base, see
+/// [`set_el2`].
+pub fn is_el2() -> bool {
+    __PERCPU_ARM_EL2.load(Ordering::Relaxed) != 0
+}
+
+/// Returns the current exception level, read from `CurrentEL`.
+pub fn current_el() -> u8 {
+    let el: usize;
+    unsafe { core::arch::asm!("mrs {}, CurrentEL", out(reg) el, options(nomem, nostack)) };
+    ((el >> 2) & 0b11) as u8
+}
@@ -0,0 +1,101 @@
+use crate::PerCpuPtr;
+
+/// The common interface of per-CPU static variables of type `T`, which is
+/// implemented by the wrapper structs generated by
+/// [`def_percpu`](crate::def_percpu).
+///
+/// The wrapper structs are distinct types, so generic code can take any
+/// per-CPU static variable of type `T` by this trait. The inherent methods of
+/// the wrapper structs are preferred when the type is known, since some of
+/// them are faster (e.g., `read_current` of primitive integers). It can also
+/// be used as a trait object, e.g., `&dyn PerCpu<u64>`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use percpu::PerCpu;
+///
+/// #[percpu::def_percpu]
+/// static RX_BYTES: u64 = 0;
+///
+/// #[percpu::def_percpu]
+/// static TX_BYTES: u64 = 0;
+///
+/// fn log<P: PerCpu<u64>>(name: &str, var: &P) {
+///     println!("{name}: {}", var.read_current());
+/// }
+///
+/// percpu::init(4);
+/// percpu::set_local_thread_pointer(0);
+///
+/// log("rx", &RX_BYTES);
+/// log("tx", &TX_BYTES);
+/// ```
+pub trait PerCpu<T> {
+    /// Returns the offset relative to the per-CPU data area base.
+    fn offset(&self) -> usize;
+
+    /// Returns the raw pointer of this per-CPU static variable on the current
+    /// CPU.
+    ///
+    /// # Safety
+    ///
+    /// Caller must ensure that preemption is disabled on the current CPU.
+    unsafe fn current_ptr(&self) -> *const T;
+
+    /// Returns the raw pointer of this per-CPU static variable on the given
+    /// CPU.
+    ///
+    /// # Safety
+    ///
+    /// Caller must ensure that
+    /// - the CPU ID is valid, and
+    /// - data races will not happen.
+    unsafe fn remote_ptr(&self, cpu_id: usize) -> *const T;
+
+    /// Returns the handle of this per-CPU static variable.
+    #[inline]
+    fn percpu_ptr(&self) -> PerCpuPtr<T> {
+        unsafe { PerCpuPtr::from_offset(self.offset()) }
+    }
+
+    /// Returns a copy of the per-CPU data on the current CPU. Preemption will
+    /// be disabled during the call.
+    #[inline]
+    fn read_current(&self) -> T
+    where
+        T: Copy,
+    {
+        #[cfg(feature = "preempt-if")]
+        let _guard = crate::__priv::NoPreemptGuard::new();
+        unsafe { self.current_ptr().read() }
+    }
+
+    /// Set the per-CPU data on the current CPU. Preemption will be disabled
+    /// during the call.
+    #[inline]
+    fn write_current(&self, val: T)
+    where
+        T: Copy,
+    {
+        #[cfg(feature = "preempt-if")]
+        let _guard = crate::__priv::NoPreemptGuard::new();
+        unsafe { (self.current_ptr() as *mut T).write(val) }
+    }
+}
+
+/// The per-CPU static variables of type `T` that can be mutated on the current
+/// CPU in place.
+///
+/// It is implemented by the wrapper structs generated by
+/// [`def_percpu`](crate::def_percpu), except for atomic types and the types
+/// with interior mutability in this crate (e.g., [`PerCpuOnce`](crate::PerCpuOnce)),
+/// which are only accessed by shared references.
+pub trait PerCpuMut<T>: PerCpu<T> {
+    /// Manipulate the per-CPU data on the current CPU in the given closure.
+    /// Preemption will be disabled during the call.
+    fn with_current<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+        Self: Sized;
+}
@@ -0,0 +1,116 @@
+//! Boot-time code patching ("alternatives") of the per-CPU register accesses.
+//!
+//! With the `alternatives` feature, the generated accessors (and
+//! [`percpu_asm_access!`](crate::percpu_asm_access)) emit the default access
+//! sequence, and record the address and the kind of each patch site in the
+//! `percpu_alternatives` section. [`apply_alternatives`] rewrites the sites
+//! once at boot, so the hottest accessors have no runtime branches.
+//!
+//! Only AArch64 has patch sites for now, where the `mrs` instruction that reads
+//! the per-CPU data area base is rewritten to read the register selected by
+//! `aarch64::set_el2`. The accessors on x86 always use the `gs` segment, so
+//! there is nothing to patch there.
+
+/// Rewrites all patch sites of the per-CPU register accesses for the current
+/// configuration (e.g., `aarch64::set_el2`), and
+/// returns the number of sites that are changed.
+///
+/// It should be called once on the boot CPU, after the configuration is
+/// decided, and before [`set_local_thread_pointer`](crate::set_local_thread_pointer).
+/// Calling it again re-applies the current configuration. It does nothing on
+/// architectures without patch sites.
+///
+/// # Safety
+///
+/// The code must be writable, and no other CPU may run (or have run) the code
+/// being patched, since only the caches of the current CPU are maintained.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// percpu::aarch64::set_el2(percpu::aarch64::current_el() == 2);
+/// unsafe { percpu::apply_alternatives() };
+/// percpu::init(4);
+/// percpu::set_local_thread_pointer(0);
+/// ```
+pub unsafe fn apply_alternatives() -> usize {
+    cfg_if::cfg_if! {
+        if #[cfg(all(
+            target_arch = "aarch64",
+            not(feature = "arm-el3"),
+            not(percpu_naive)
+        ))] {
+            aarch64::apply()
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(all(target_arch = "aarch64", not(feature = "arm-el3"), not(percpu_naive)))]
+mod aarch64 {
+    /// A record of a patch site in the `percpu_alternatives` section, which
+    /// must be kept in sync with `percpu_macros`.
+    #[repr(C)]
+    struct AltSite {
+        /// The address of the instruction, relative to this field.
+        offset: i32,
+        /// What the site accesses, e.g., [`ALT_AARCH64_TPIDR`].
+        kind: u32,
+    }
+
+    /// The kind of sites that read `TPIDR_EL1` or `TPIDR_EL2` by `mrs`.
+    const ALT_AARCH64_TPIDR: u32 = 1;
+
+    extern "C" {
+        static __start_percpu_alternatives: AltSite;
+        static __stop_percpu_alternatives: AltSite;
+    }
+
+    pub unsafe fn apply() -> usize {
+        let start = core::ptr::addr_of!(__start_percpu_alternatives);
+        let stop = core::ptr::addr_of!(__stop_percpu_alternatives);
+        let num = (stop as usize - start as usize) / core::mem::size_of::<AltSite>();
+        let mut patched = 0;
+        for i in 0..num {
+            let site = start.add(i);
+            let insn = (core::ptr::addr_of!((*site).offset) as isize + (*site).offset as isize)
+                as *mut u32;
+            let old = insn.read_volatile();
+            let new = match (*site).kind {
+                ALT_AARCH64_TPIDR => patch_tpidr(old),
+                kind => panic!(
+                    "unknown percpu alternative kind {} at {:#x}",
+                    kind, insn as usize
+                ),
+            };
+            if new != old {
+                insn.write_volatile(new);
+                core::arch::asm!("dc cvau, {0}", "dsb ish", "ic ivau, {0}", in(reg) insn);
+                patched += 1;
+            }
+        }
+        core::arch::asm!("dsb ish", "isb");
+        patched
+    }
+
+    /// Returns the `mrs` instruction `insn` with the system register replaced
+    /// by the selected one, keeping the destination register.
+    fn patch_tpidr(insn: u32) -> u32 {
+        /// The `op0:op1:CRn:CRm:op2` field of `mrs`.
+        const SYSREG_MASK: u32 = 0x1f_ffe0;
+        const MRS_TPIDR_EL1: u32 = 0xd538_d080;
+        const MRS_TPIDR_EL2: u32 = 0xd53c_d040;
+        assert!(
+            matches!(insn & !0x1f, MRS_TPIDR_EL1 | MRS_TPIDR_EL2),
+            "percpu alternative is not `mrs` of `TPIDR_EL1` or `TPIDR_EL2`: {:#010x}",
+            insn
+        );
+        let mrs = if crate::aarch64::is_el2() {
+            MRS_TPIDR_EL2
+        } else {
+            MRS_TPIDR_EL1
+        };
+        (insn & !SYSREG_MASK) | (mrs & SYSREG_MASK)
+    }
+}
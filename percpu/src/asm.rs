@@ -0,0 +1,331 @@
+/// Expands to the instruction sequence (as a string literal) that loads or
+/// stores a word-sized per-CPU static variable on the current CPU, for
+/// assembly code in `global_asm!` or `naked_asm!`, e.g., trap entry stubs that
+/// switch stacks via the per-CPU data.
+///
+/// The per-CPU static variable is specified by its offset symbol, which is
+/// defined by the `offset_sym` argument of [`def_percpu`](crate::def_percpu).
+/// Registers are given as string literals in the syntax of the target
+/// architecture:
+///
+/// - `percpu_asm_access!(load "DST", "SYM", "TMP")` loads the variable into
+///   `DST`. `TMP` is clobbered on AArch64 and ARM, and unused on other
+///   architectures.
+/// - `percpu_asm_access!(store "SRC", "SYM", "TMP0", "TMP1")` stores `SRC` to
+///   the variable. Both `TMP0` and `TMP1` are clobbered on AArch64 and ARM,
+///   only `TMP0` is clobbered on RISC-V and LoongArch, and neither of them is
+///   used on x86.
+///
+/// With the `arm-el2-runtime` feature, the local labels `1` and `2` are also
+/// used on AArch64, to select the register at runtime.
+///
+/// The value is of `usize`, i.e., the width of general-purpose registers.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// #[percpu::def_percpu(offset_sym)]
+/// static KERNEL_STACK_TOP: usize = 0;
+///
+/// core::arch::global_asm!(
+///     "trap_entry:",
+///     percpu::percpu_asm_access!(load "rsp", "PERCPU_OFF_KERNEL_STACK_TOP", ""),
+///     // ...
+/// );
+/// ```
+#[doc(cfg(not(feature = "sp-naive")))]
+#[macro_export]
+macro_rules! percpu_asm_access {
+    (load $dst:literal, $sym:literal, $tmp:literal) => {
+        $crate::__percpu_asm_load!($dst, $sym, $tmp)
+    };
+    (store $src:literal, $sym:literal, $tmp0:literal, $tmp1:literal) => {
+        $crate::__percpu_asm_store!($src, $sym, $tmp0, $tmp1)
+    };
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_load {
+    ($dst:literal, $sym:literal, $tmp:literal) => {
+        concat!("mov ", $dst, ", gs:[", $sym, "]\n")
+    };
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_store {
+    ($src:literal, $sym:literal, $tmp0:literal, $tmp1:literal) => {
+        concat!("mov gs:[", $sym, "], ", $src, "\n")
+    };
+}
+
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_load {
+    ($dst:literal, $sym:literal, $tmp:literal) => {
+        concat!(
+            concat!("lui ", $dst, ", %hi(", $sym, ")\n"),
+            concat!(
+                "add ",
+                $dst,
+                ", ",
+                $dst,
+                ", ",
+                $crate::__percpu_asm_rv_reg!(),
+                "\n"
+            ),
+            concat!($crate::__percpu_asm_rv_op!(load), " ", $dst),
+            concat!(", %lo(", $sym, ")(", $dst, ")\n"),
+        )
+    };
+}
+
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_store {
+    ($src:literal, $sym:literal, $tmp0:literal, $tmp1:literal) => {
+        concat!(
+            concat!("lui ", $tmp0, ", %hi(", $sym, ")\n"),
+            concat!(
+                "add ",
+                $tmp0,
+                ", ",
+                $tmp0,
+                ", ",
+                $crate::__percpu_asm_rv_reg!(),
+                "\n"
+            ),
+            concat!($crate::__percpu_asm_rv_op!(store), " ", $src),
+            concat!(", %lo(", $sym, ")(", $tmp0, ")\n"),
+        )
+    };
+}
+
+#[cfg(all(
+    any(target_arch = "riscv32", target_arch = "riscv64"),
+    any(not(feature = "riscv-tp"), feature = "tls-compat")
+))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_rv_reg {
+    () => {
+        "gp"
+    };
+}
+
+#[cfg(all(
+    any(target_arch = "riscv32", target_arch = "riscv64"),
+    feature = "riscv-tp",
+    not(feature = "tls-compat")
+))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_rv_reg {
+    () => {
+        "tp"
+    };
+}
+
+#[cfg(target_arch = "riscv64")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_rv_op {
+    (load) => {
+        "ld"
+    };
+    (store) => {
+        "sd"
+    };
+}
+
+#[cfg(target_arch = "riscv32")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_rv_op {
+    (load) => {
+        "lw"
+    };
+    (store) => {
+        "sw"
+    };
+}
+
+#[cfg(target_arch = "aarch64")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_load {
+    ($dst:literal, $sym:literal, $tmp:literal) => {
+        concat!(
+            $crate::__percpu_asm_read_tpidr!($dst),
+            concat!("movz ", $tmp, ", #:abs_g1:", $sym, "\n"),
+            concat!("movk ", $tmp, ", #:abs_g0_nc:", $sym, "\n"),
+            concat!("ldr ", $dst, ", [", $dst, ", ", $tmp, "]\n"),
+        )
+    };
+}
+
+#[cfg(target_arch = "aarch64")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_store {
+    ($src:literal, $sym:literal, $tmp0:literal, $tmp1:literal) => {
+        concat!(
+            $crate::__percpu_asm_read_tpidr!($tmp0),
+            concat!("movz ", $tmp1, ", #:abs_g1:", $sym, "\n"),
+            concat!("movk ", $tmp1, ", #:abs_g0_nc:", $sym, "\n"),
+            concat!("str ", $src, ", [", $tmp0, ", ", $tmp1, "]\n"),
+        )
+    };
+}
+
+// `arm-el3` takes precedence over `arm-el2` if both are enabled.
+#[cfg(all(
+    target_arch = "aarch64",
+    not(any(feature = "arm-el2", feature = "arm-el3"))
+))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_tpidr {
+    () => {
+        "TPIDR_EL1"
+    };
+}
+
+#[cfg(all(target_arch = "aarch64", feature = "arm-el2", not(feature = "arm-el3")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_tpidr {
+    () => {
+        "TPIDR_EL2"
+    };
+}
+
+#[cfg(all(target_arch = "aarch64", feature = "arm-el3"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_tpidr {
+    () => {
+        "TPIDR_EL3"
+    };
+}
+
+#[cfg(all(
+    target_arch = "aarch64",
+    not(all(feature = "arm-el2-runtime", not(feature = "arm-el3")))
+))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_read_tpidr {
+    ($dst:literal) => {
+        concat!("mrs ", $dst, ", ", $crate::__percpu_asm_tpidr!(), "\n")
+    };
+}
+
+// With the `arm-el2-runtime` feature, the register is selected by
+// `__PERCPU_ARM_EL2`, which is defined in the `aarch64` module. `$dst` is used
+// to load it, and the local labels `1` and `2` are used.
+#[cfg(all(
+    target_arch = "aarch64",
+    feature = "arm-el2-runtime",
+    not(feature = "alternatives"),
+    not(feature = "arm-el3")
+))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_read_tpidr {
+    ($dst:literal) => {
+        concat!(
+            concat!("adrp ", $dst, ", __PERCPU_ARM_EL2\n"),
+            concat!("ldr ", $dst, ", [", $dst, ", :lo12:__PERCPU_ARM_EL2]\n"),
+            concat!("cbnz ", $dst, ", 1f\n"),
+            concat!("mrs ", $dst, ", TPIDR_EL1\n"),
+            "b 2f\n",
+            "1:\n",
+            concat!("mrs ", $dst, ", TPIDR_EL2\n"),
+            "2:\n",
+        )
+    };
+}
+
+// With the `alternatives` feature, the `mrs` instruction is recorded as a patch
+// site of the kind `1`, to be rewritten by `apply_alternatives`. The local label
+// `1` is used.
+#[cfg(all(
+    target_arch = "aarch64",
+    feature = "alternatives",
+    not(feature = "arm-el3")
+))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_read_tpidr {
+    ($dst:literal) => {
+        concat!(
+            "1:\n",
+            concat!("mrs ", $dst, ", ", $crate::__percpu_asm_tpidr!(), "\n"),
+            ".pushsection percpu_alternatives, \"aR\"\n",
+            ".balign 4\n",
+            ".word 1b - .\n",
+            ".word 1\n",
+            ".popsection\n",
+        )
+    };
+}
+
+#[cfg(target_arch = "arm")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_load {
+    ($dst:literal, $sym:literal, $tmp:literal) => {
+        concat!(
+            concat!("mrc p15, 0, ", $dst, ", c13, c0, 4\n"), // TPIDRPRW
+            concat!("movw ", $tmp, ", #:lower16:", $sym, "\n"),
+            concat!("movt ", $tmp, ", #:upper16:", $sym, "\n"),
+            concat!("ldr ", $dst, ", [", $dst, ", ", $tmp, "]\n"),
+        )
+    };
+}
+
+#[cfg(target_arch = "arm")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_store {
+    ($src:literal, $sym:literal, $tmp0:literal, $tmp1:literal) => {
+        concat!(
+            concat!("mrc p15, 0, ", $tmp0, ", c13, c0, 4\n"), // TPIDRPRW
+            concat!("movw ", $tmp1, ", #:lower16:", $sym, "\n"),
+            concat!("movt ", $tmp1, ", #:upper16:", $sym, "\n"),
+            concat!("str ", $src, ", [", $tmp0, ", ", $tmp1, "]\n"),
+        )
+    };
+}
+
+#[cfg(target_arch = "loongarch64")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_load {
+    ($dst:literal, $sym:literal, $tmp:literal) => {
+        concat!(
+            concat!("lu12i.w ", $dst, ", %abs_hi20(", $sym, ")\n"),
+            concat!("ori ", $dst, ", ", $dst, ", %abs_lo12(", $sym, ")\n"),
+            concat!("ldx.d ", $dst, ", ", $dst, ", $r21\n"),
+        )
+    };
+}
+
+#[cfg(target_arch = "loongarch64")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __percpu_asm_store {
+    ($src:literal, $sym:literal, $tmp0:literal, $tmp1:literal) => {
+        concat!(
+            concat!("lu12i.w ", $tmp0, ", %abs_hi20(", $sym, ")\n"),
+            concat!("ori ", $tmp0, ", ", $tmp0, ", %abs_lo12(", $sym, ")\n"),
+            concat!("stx.d ", $src, ", ", $tmp0, ", $r21\n"),
+        )
+    };
+}
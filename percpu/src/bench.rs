@@ -0,0 +1,93 @@
+//! Cycle-counter benchmarks of the per-CPU data accessors, for bare-metal
+//! kernels where a benchmark harness is not available.
+//!
+//! A kernel calls [`run`] on a CPU whose per-CPU data area is initialized, and
+//! prints the results, e.g., to catch performance regressions in the generated
+//! code, or to pick the accessor for a hot path. The hosted benchmarks are in
+//! the `benches` directory instead.
+
+use core::hint::black_box;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// To use `percpu::percpu_area_base()` and others in macro expansion.
+#[allow(unused_imports)]
+use crate as percpu;
+
+#[percpu_macros::def_percpu]
+static BENCH_COUNTER: u64 = 0;
+
+static BENCH_GLOBAL: AtomicU64 = AtomicU64::new(0);
+
+/// Reads the cycle counter of the current CPU.
+///
+/// It is the time-stamp counter on x86, the virtual counter (`CNTVCT_EL0`) on
+/// AArch64, and the `time` CSR (or stable counter) on RISC-V (or LoongArch),
+/// which may tick slower than the CPU clock. It is always `0` on other
+/// architectures.
+pub fn cycles() -> u64 {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            unsafe { core::arch::x86_64::_rdtsc() }
+        } else if #[cfg(target_arch = "x86")] {
+            unsafe { core::arch::x86::_rdtsc() }
+        } else if #[cfg(target_arch = "aarch64")] {
+            let cnt: u64;
+            unsafe { core::arch::asm!("mrs {}, cntvct_el0", out(reg) cnt) };
+            cnt
+        } else if #[cfg(target_arch = "riscv64")] {
+            let cnt: u64;
+            unsafe { core::arch::asm!("rdtime {}", out(reg) cnt) };
+            cnt
+        } else if #[cfg(target_arch = "loongarch64")] {
+            let cnt: u64;
+            unsafe { core::arch::asm!("rdtime.d {}, $zero", out(reg) cnt) };
+            cnt
+        } else {
+            0
+        }
+    }
+}
+
+/// Returns the average cycles of `iters` calls of `f`.
+fn measure<T>(iters: u64, mut f: impl FnMut() -> T) -> u64 {
+    let start = cycles();
+    for _ in 0..iters {
+        black_box(f());
+    }
+    (cycles() - start) / iters.max(1)
+}
+
+/// Runs the benchmarks of the accessors on the current CPU, each for `iters`
+/// iterations, and reports the name and the average cycles per iteration of
+/// each one to `report`.
+///
+/// The per-CPU data area must be initialized on the current CPU (by
+/// [`set_local_thread_pointer`](crate::set_local_thread_pointer)).
+pub fn run(iters: u64, mut report: impl FnMut(&'static str, u64)) {
+    report(
+        "read_current_raw",
+        measure(iters, || unsafe { BENCH_COUNTER.read_current_raw() }),
+    );
+    report(
+        "read_current",
+        measure(iters, || BENCH_COUNTER.read_current()),
+    );
+    report(
+        "with_current",
+        measure(iters, || BENCH_COUNTER.with_current(|v| *v)),
+    );
+    report(
+        "add_current",
+        measure(iters, || BENCH_COUNTER.add_current(black_box(1))),
+    );
+    report(
+        "global_atomic_load",
+        measure(iters, || BENCH_GLOBAL.load(Ordering::Relaxed)),
+    );
+    report(
+        "global_atomic_add",
+        measure(iters, || {
+            BENCH_GLOBAL.fetch_add(black_box(1), Ordering::Relaxed)
+        }),
+    );
+}
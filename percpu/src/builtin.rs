@@ -0,0 +1,67 @@
+use core::sync::atomic::*;
+
+use crate::{
+    PerCpuLazy, PerCpuOnce, PerCpuRefCell, PercpuCounter, PercpuCounterBatched, PercpuEpoch,
+    PercpuFlag, PercpuRef, PercpuRwLock, PercpuWorkQueue,
+};
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// The types whose special methods are generated by
+/// [`def_percpu`](crate::def_percpu), i.e., the atomic types and the types with
+/// interior mutability in this crate.
+///
+/// The macro recognizes them by name, so it asserts that the type implements
+/// this trait, instead of generating the methods for a user type with the same
+/// name (e.g., a `struct PercpuCounter(u8)`, which would be written as a
+/// `usize`). It is sealed, so it can not be implemented outside this crate.
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not the type of the same name in `percpu` or `core::sync::atomic`",
+    note = "`def_percpu` generates special methods for these types by name, rename the type to avoid them"
+)]
+pub trait Builtin: private::Sealed {}
+
+/// Asserts that `T` is a builtin type at compile time.
+#[doc(hidden)]
+pub const fn assert_builtin<T: Builtin + ?Sized>() {}
+
+macro_rules! impl_builtin {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl private::Sealed for $ty {}
+            impl Builtin for $ty {}
+        )*
+    };
+}
+
+impl_builtin!(
+    AtomicBool,
+    AtomicU8,
+    AtomicU16,
+    AtomicU32,
+    AtomicUsize,
+    AtomicI8,
+    AtomicI16,
+    AtomicI32,
+    AtomicIsize,
+    PercpuCounter,
+    PercpuCounterBatched,
+    PercpuEpoch,
+    PercpuFlag,
+    PercpuRef,
+    PercpuRwLock,
+    PercpuWorkQueue,
+);
+
+#[cfg(target_has_atomic = "64")]
+impl_builtin!(AtomicU64, AtomicI64);
+
+impl<T> private::Sealed for PerCpuLazy<T> {}
+impl<T> Builtin for PerCpuLazy<T> {}
+impl<T> private::Sealed for PerCpuOnce<T> {}
+impl<T> Builtin for PerCpuOnce<T> {}
+impl<T> private::Sealed for PerCpuRefCell<T> {}
+impl<T> Builtin for PerCpuRefCell<T> {}
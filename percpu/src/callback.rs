@@ -0,0 +1,119 @@
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+/// To use `percpu::percpu_area_base()` in macro expansion.
+#[allow(unused_imports)]
+use crate as percpu;
+
+/// The maximum number of callbacks that can be registered by
+/// [`register_cpu_init`].
+pub const MAX_CPU_INIT_CALLBACKS: usize = 32;
+
+static CPU_INIT_CALLBACKS: [AtomicPtr<()>; MAX_CPU_INIT_CALLBACKS] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; MAX_CPU_INIT_CALLBACKS];
+
+static CPU_INIT_CALLBACK_NUM: AtomicUsize = AtomicUsize::new(0);
+
+/// Registers a callback that is run by [`cpu_init`] on each CPU when it comes
+/// online, with the ID of the CPU as the argument.
+///
+/// It allows subsystems to construct non-trivial per-CPU data (e.g., that
+/// needs memory allocation) on each CPU. Callbacks are run in the order they
+/// are registered, and are not run on the CPUs that are already online.
+///
+/// # Panics
+///
+/// Panics if more than [`MAX_CPU_INIT_CALLBACKS`] callbacks are registered.
+pub fn register_cpu_init(callback: fn(usize)) {
+    let idx = CPU_INIT_CALLBACK_NUM.fetch_add(1, Ordering::AcqRel);
+    assert!(
+        idx < MAX_CPU_INIT_CALLBACKS,
+        "too many per-CPU init callbacks"
+    );
+    CPU_INIT_CALLBACKS[idx].store(callback as *mut (), Ordering::Release);
+}
+
+/// Sets the thread pointer register to the per-CPU data area of the given CPU
+/// by [`set_local_thread_pointer`](crate::set_local_thread_pointer), then
+/// runs the callbacks registered by [`register_cpu_init`] on the current CPU.
+///
+/// It should be called on each CPU when it comes online, instead of
+/// [`set_local_thread_pointer`](crate::set_local_thread_pointer).
+pub fn cpu_init(cpu_id: impl Into<crate::CpuId>) {
+    let cpu_id = cpu_id.into().get();
+    crate::set_local_thread_pointer(cpu_id);
+    let num = CPU_INIT_CALLBACK_NUM
+        .load(Ordering::Acquire)
+        .min(MAX_CPU_INIT_CALLBACKS);
+    for slot in &CPU_INIT_CALLBACKS[..num] {
+        // The slot may be being filled by a concurrent registration.
+        let callback = loop {
+            let ptr = slot.load(Ordering::Acquire);
+            if !ptr.is_null() {
+                break ptr;
+            }
+            core::hint::spin_loop();
+        };
+        // SAFETY: the pointer is only stored from a `fn(usize)` in `register_cpu_init`.
+        let callback: fn(usize) = unsafe { core::mem::transmute(callback) };
+        callback(cpu_id);
+    }
+}
+
+/// Whether the CPU has been brought online by [`cpu_online`], which is
+/// accessed as an [`AtomicBool`]. It is zero-initialized, so it is cleared
+/// again by [`reset_area`](crate::reset_area) when the CPU goes offline.
+#[percpu_macros::def_percpu]
+static CPU_ONLINE: bool = false;
+
+/// Brings the given CPU online on the current CPU: runs [`cpu_init`], then
+/// marks the CPU online with release ordering.
+///
+/// Everything initialized by the CPU before (e.g., in the callbacks registered
+/// by [`register_cpu_init`]) is visible to the CPUs that see it online, e.g.,
+/// after [`wait_for_cpus`] returns. It should be called on each CPU when it
+/// comes online, instead of [`cpu_init`].
+pub fn cpu_online(cpu_id: impl Into<crate::CpuId>) {
+    let cpu_id = cpu_id.into().get();
+    cpu_init(cpu_id);
+    #[cfg(feature = "preempt-if")]
+    let _guard = crate::__priv::NoPreemptGuard::new();
+    unsafe { AtomicBool::from_ptr(CPU_ONLINE.current_ptr() as *mut bool) }
+        .store(true, Ordering::Release);
+}
+
+/// Returns whether the given CPU has been brought online by [`cpu_online`].
+///
+/// Returns `false` if `cpu_id` is not less than
+/// [`percpu_area_num()`](crate::percpu_area_num).
+pub fn is_cpu_online(cpu_id: impl Into<crate::CpuId>) -> bool {
+    let cpu_id = cpu_id.into().get();
+    cpu_id < crate::percpu_area_num()
+        && unsafe { AtomicBool::from_ptr(CPU_ONLINE.remote_ptr(cpu_id) as *mut bool) }
+            .load(Ordering::Acquire)
+}
+
+/// Returns the number of CPUs that have been brought online by
+/// [`cpu_online`].
+pub fn online_cpus() -> usize {
+    (0..crate::percpu_area_num())
+        .filter(|&cpu_id| is_cpu_online(cpu_id))
+        .count()
+}
+
+/// Spins until at least `n` CPUs have been brought online by [`cpu_online`],
+/// e.g., on the boot CPU after starting the application processors.
+///
+/// # Panics
+///
+/// Panics if `n` is greater than [`percpu_area_num()`](crate::percpu_area_num).
+pub fn wait_for_cpus(n: usize) {
+    assert!(
+        n <= crate::percpu_area_num(),
+        "waiting for {} CPUs, but only {} per-CPU data areas",
+        n,
+        crate::percpu_area_num()
+    );
+    while online_cpus() < n {
+        core::hint::spin_loop();
+    }
+}
@@ -0,0 +1,118 @@
+//! Debug checks for per-CPU data accesses.
+
+#[cfg(feature = "debug-preempt-check")]
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+#[cfg(feature = "debug-preempt-check")]
+static PREEMPT_CHECK_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Registers a hook that returns whether preemption is disabled on the current CPU.
+///
+/// Once registered, the `_raw` accessors of per-CPU static variables (e.g., `current_ptr`, `read_current_raw`,
+/// `write_current_raw`) call the hook and panic if it returns `false`. No check is performed before a hook is
+/// registered.
+///
+/// The hook may be called very frequently, so it should be cheap, and it must not access per-CPU data itself.
+#[cfg(feature = "debug-preempt-check")]
+#[doc(cfg(feature = "debug-preempt-check"))]
+pub fn set_preempt_check_hook(hook: fn() -> bool) {
+    PREEMPT_CHECK_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+/// Panics if a preemption check hook is registered and it reports that preemption is enabled.
+#[cfg(feature = "debug-preempt-check")]
+#[doc(hidden)]
+#[inline]
+#[track_caller]
+pub fn assert_preempt_disabled() {
+    let hook = PREEMPT_CHECK_HOOK.load(Ordering::Acquire);
+    if !hook.is_null() {
+        // SAFETY: the pointer is only stored from a `fn() -> bool` in `set_preempt_check_hook`.
+        let hook: fn() -> bool = unsafe { core::mem::transmute(hook) };
+        assert!(hook(), "per-CPU data accessed with preemption enabled");
+    }
+}
+
+/// Marks a per-CPU static variable borrowed on the current CPU until it is
+/// dropped, like the borrow flag of `RefCell`.
+///
+/// The borrow state of each variable is a zero-initialized `isize` in the
+/// per-CPU data area (at `state_offset`), which is defined by
+/// [`def_percpu`](crate::def_percpu) with the `debug-borrow-check` feature. It
+/// is the number of shared borrows, or `-1` if it is mutably borrowed.
+#[cfg(feature = "debug-borrow-check")]
+#[doc(hidden)]
+pub struct BorrowGuard {
+    state: *mut isize,
+}
+
+#[cfg(feature = "debug-borrow-check")]
+impl BorrowGuard {
+    /// Marks the per-CPU static variable `name` mutably borrowed on the current
+    /// CPU.
+    ///
+    /// # Panics
+    ///
+    /// Panics if it is already borrowed on the current CPU, e.g., by a nested
+    /// `with_current` on the same variable, or by a live guard of `current()`.
+    #[inline]
+    #[track_caller]
+    pub fn new(state_offset: usize, name: &'static str) -> Self {
+        let state = (crate::get_local_thread_pointer() + state_offset) as *mut isize;
+        // SAFETY: the state is only accessed on the current CPU, with
+        // preemption disabled by the caller if necessary.
+        unsafe {
+            match state.read_volatile() {
+                0 => {}
+                -1 => panic!(
+                    "the per-CPU data `{}` is already mutably borrowed on the current CPU",
+                    name
+                ),
+                _ => panic!(
+                    "the per-CPU data `{}` is already borrowed on the current CPU",
+                    name
+                ),
+            }
+            state.write_volatile(-1);
+        }
+        Self { state }
+    }
+
+    /// Marks the per-CPU static variable `name` borrowed (shared) on the
+    /// current CPU.
+    ///
+    /// # Panics
+    ///
+    /// Panics if it is already mutably borrowed on the current CPU.
+    #[inline]
+    #[track_caller]
+    pub fn new_shared(state_offset: usize, name: &'static str) -> Self {
+        let state = (crate::get_local_thread_pointer() + state_offset) as *mut isize;
+        // SAFETY: the same as `new`.
+        unsafe {
+            let count = state.read_volatile();
+            if count < 0 {
+                panic!(
+                    "the per-CPU data `{}` is already mutably borrowed on the current CPU",
+                    name
+                );
+            }
+            state.write_volatile(count + 1);
+        }
+        Self { state }
+    }
+}
+
+#[cfg(feature = "debug-borrow-check")]
+impl Drop for BorrowGuard {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: the same state marked in `new` or `new_shared`. A mutable
+        // borrow is the only one while it is alive.
+        unsafe {
+            let count = self.state.read_volatile();
+            self.state
+                .write_volatile(if count < 0 { 0 } else { count - 1 });
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use crate::sync::{const_fn, AtomicI64, Ordering};
+
+/// A per-CPU counter, like the `percpu_counter` in Linux.
+///
+/// It must be used as the type of a per-CPU static variable defined by
+/// [`def_percpu`](crate::def_percpu). Each CPU only updates the counter in
+/// its own per-CPU data area, and the total value is obtained by summing up
+/// the counters on all CPUs.
+///
+/// The following methods are generated in the wrapper struct:
+///
+/// - `add_current(delta)`, `inc_current()`, `dec_current()`: update the
+///   counter on the current CPU.
+/// - `read_current()`: returns the counter value on the current CPU.
+/// - `sum()`: returns the sum of the counter values on all CPUs.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use percpu::PercpuCounter;
+///
+/// #[percpu::def_percpu]
+/// static NR_IRQS: PercpuCounter = PercpuCounter::new();
+///
+/// percpu::init(4);
+/// percpu::set_local_thread_pointer(0);
+///
+/// NR_IRQS.inc_current();
+/// NR_IRQS.add_current(2);
+/// assert_eq!(NR_IRQS.sum(), 3);
+/// ```
+#[repr(transparent)]
+pub struct PercpuCounter(usize);
+
+impl PercpuCounter {
+    /// Creates a new counter with the initial value `0` on all CPUs.
+    pub const fn new() -> Self {
+        Self(0)
+    }
+}
+
+impl Default for PercpuCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The default batch threshold of [`PercpuCounterBatched`].
+pub const DEFAULT_COUNTER_BATCH: i64 = 32;
+
+/// A per-CPU counter with a shared global count, like the `percpu_counter` in
+/// Linux with a batch.
+///
+/// It must be used as the type of a per-CPU static variable defined by
+/// [`def_percpu`](crate::def_percpu). Updates accumulate in the per-CPU data
+/// area of the current CPU, and are flushed into a shared atomic count when the
+/// absolute value of the local count reaches the batch threshold. So the shared
+/// count is a cheap approximation of the total value, which is off by less than
+/// `batch` per CPU.
+///
+/// The following methods are generated in the wrapper struct:
+///
+/// - `add_current(delta)`, `inc_current()`, `dec_current()`: update the
+///   counter on the current CPU, flushing it into the shared count if needed.
+/// - `approx_sum()`: returns the shared count, without touching any per-CPU
+///   data.
+/// - `precise_sum()`: returns the shared count plus the local counts on all
+///   CPUs.
+/// - `batch()`, `set_batch(batch)`: get and set the batch threshold, which is
+///   [`DEFAULT_COUNTER_BATCH`] by default.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use percpu::PercpuCounterBatched;
+///
+/// #[percpu::def_percpu]
+/// static NR_FREE_PAGES: PercpuCounterBatched = PercpuCounterBatched::new();
+///
+/// percpu::init(4);
+/// percpu::set_local_thread_pointer(0);
+///
+/// NR_FREE_PAGES.set_batch(4);
+/// NR_FREE_PAGES.add_current(3);
+/// assert_eq!(NR_FREE_PAGES.approx_sum(), 0);
+/// NR_FREE_PAGES.inc_current(); // flushed
+/// assert_eq!(NR_FREE_PAGES.approx_sum(), 4);
+/// assert_eq!(NR_FREE_PAGES.precise_sum(), 4);
+/// ```
+pub struct PercpuCounterBatched {
+    /// The count not flushed into the shared count yet on this CPU.
+    count: AtomicI64,
+}
+
+impl PercpuCounterBatched {
+    const_fn! {
+        /// Creates a new counter with the initial value `0`.
+        pub const fn new() -> Self {
+            Self {
+                count: AtomicI64::new(0),
+            }
+        }
+    }
+}
+
+impl Default for PercpuCounterBatched {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The state of a [`PercpuCounterBatched`] that is shared by all CPUs.
+#[doc(hidden)]
+pub struct PercpuCounterBatchedShared {
+    count: AtomicI64,
+    batch: AtomicI64,
+}
+
+impl Default for PercpuCounterBatchedShared {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PercpuCounterBatchedShared {
+    const_fn! {
+        pub const fn new() -> Self {
+            Self {
+                count: AtomicI64::new(0),
+                batch: AtomicI64::new(DEFAULT_COUNTER_BATCH),
+            }
+        }
+    }
+
+    pub fn batch(&self) -> i64 {
+        self.batch.load(Ordering::Relaxed)
+    }
+
+    pub fn set_batch(&self, batch: i64) {
+        assert!(batch > 0, "invalid counter batch: {}", batch);
+        self.batch.store(batch, Ordering::Relaxed);
+    }
+
+    /// Adds `delta` to the counter. `local` must be the
+    /// [`PercpuCounterBatched`] on the current CPU, and preemption must be
+    /// disabled.
+    pub fn add(&self, local: &PercpuCounterBatched, delta: i64) {
+        let count = local.count.fetch_add(delta, Ordering::Relaxed) + delta;
+        if count.abs() >= self.batch() {
+            // Flush by subtracting instead of clearing, so that updates from
+            // interrupt handlers in the meantime are kept locally.
+            self.count.fetch_add(count, Ordering::Relaxed);
+            local.count.fetch_sub(count, Ordering::Relaxed);
+        }
+    }
+
+    pub fn approx_sum(&self) -> i64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the shared count plus the counts in `locals`, which must yield
+    /// the [`PercpuCounterBatched`]s on all CPUs.
+    pub fn precise_sum<'a>(&self, locals: impl Iterator<Item = &'a PercpuCounterBatched>) -> i64 {
+        locals.fold(self.approx_sum(), |sum, local| {
+            sum + local.count.load(Ordering::Relaxed)
+        })
+    }
+}
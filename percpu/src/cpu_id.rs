@@ -0,0 +1,115 @@
+use core::fmt;
+
+/// The maximum number of CPUs, set by the `PERCPU_MAX_CPUS` environment
+/// variable at build time (e.g., from the SMP configuration of the kernel), or
+/// `usize::MAX` if it is not set.
+///
+/// The per-CPU data areas can not be initialized for more CPUs (see
+/// [`try_init`](crate::try_init)), and the CPU IDs passed to
+/// [`percpu_area_base`](crate::percpu_area_base) must be less than it. The
+/// linker script can reserve the matching region by
+/// [`PercpuSection::for_max_cpus`](crate::linker::PercpuSection::for_max_cpus).
+pub const MAX_CPUS: usize = match MAX_CPUS_LIMIT {
+    Some(max_cpus) => max_cpus,
+    None => usize::MAX,
+};
+
+/// The `PERCPU_MAX_CPUS` environment variable at build time, or `None` if it is
+/// not set.
+pub(crate) const MAX_CPUS_LIMIT: Option<usize> = parse_max_cpus(env!("PERCPU_MAX_CPUS"));
+
+/// Parses the decimal number validated by the build script, or returns `None`
+/// if it is empty.
+const fn parse_max_cpus(val: &str) -> Option<usize> {
+    let bytes = val.as_bytes();
+    if bytes.is_empty() {
+        return None;
+    }
+    let mut max_cpus = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        max_cpus = max_cpus * 10 + (bytes[i] - b'0') as usize;
+        i += 1;
+    }
+    Some(max_cpus)
+}
+
+/// A dense CPU index, i.e., the index of the per-CPU data area of a CPU, which
+/// is less than [`percpu_area_num()`](crate::percpu_area_num).
+///
+/// It is distinct from the other IDs of a CPU (e.g., the APIC ID on x86 or the
+/// MPIDR on AArch64), which may be sparse, and from the IDs of tasks. The
+/// functions that take a CPU ID (e.g., [`percpu_area_base`](crate::percpu_area_base)
+/// and the `remote_ptr` or `read_remote` methods of per-CPU static variables)
+/// accept both `CpuId` and `usize`, so the typed one can be adopted gradually.
+/// The exceptions are [`PerCpu::remote_ptr`](crate::PerCpu::remote_ptr), which
+/// keeps `usize` so that the trait can be used as a trait object, and the CPU
+/// IDs passed to callbacks or returned, which are plain `usize`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use percpu::CpuId;
+///
+/// #[percpu::def_percpu]
+/// static IRQS: usize = 0;
+///
+/// percpu::init(4);
+/// percpu::set_local_thread_pointer(0);
+///
+/// let total: usize = CpuId::all().map(|cpu| unsafe { *IRQS.remote_ptr(cpu) }).sum();
+/// assert_eq!(total, 0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[repr(transparent)]
+pub struct CpuId(usize);
+
+impl CpuId {
+    /// Creates a CPU ID from the dense CPU index, without checking it against
+    /// [`percpu_area_num()`](crate::percpu_area_num).
+    pub const fn new(id: usize) -> Self {
+        Self(id)
+    }
+
+    /// Creates a CPU ID from the dense CPU index, or returns `None` if it is not
+    /// less than [`percpu_area_num()`](crate::percpu_area_num).
+    pub fn try_new(id: usize) -> Option<Self> {
+        (id < crate::percpu_area_num()).then_some(Self(id))
+    }
+
+    /// Returns the dense CPU index.
+    pub const fn get(self) -> usize {
+        self.0
+    }
+
+    /// Returns the ID of the current CPU, see
+    /// [`current_cpu_id`](crate::current_cpu_id).
+    pub fn current() -> Self {
+        Self(crate::current_cpu_id())
+    }
+
+    /// Returns an iterator over the IDs of all CPUs that have a per-CPU data
+    /// area, i.e., from `0` to [`percpu_area_num()`](crate::percpu_area_num)
+    /// (exclusive).
+    pub fn all() -> impl ExactSizeIterator<Item = Self> + DoubleEndedIterator {
+        (0..crate::percpu_area_num()).map(Self)
+    }
+}
+
+impl From<usize> for CpuId {
+    fn from(id: usize) -> Self {
+        Self(id)
+    }
+}
+
+impl From<CpuId> for usize {
+    fn from(cpu_id: CpuId) -> Self {
+        cpu_id.0
+    }
+}
+
+impl fmt::Display for CpuId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
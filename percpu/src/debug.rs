@@ -0,0 +1,22 @@
+use core::fmt;
+
+/// Formats the per-CPU data on every CPU as a map, e.g., `{cpu0: 1, cpu1: 0}`,
+/// where `F` reads the per-CPU data on the given CPU.
+///
+/// It is used by the wrapper structs defined with `#[def_percpu(debug)]`.
+#[doc(hidden)]
+pub struct DebugRemote<F>(pub F);
+
+impl<F, T> fmt::Debug for DebugRemote<F>
+where
+    F: Fn(usize) -> T,
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for cpu_id in 0..crate::percpu_area_num() {
+            map.entry(&format_args!("cpu{}", cpu_id), &(self.0)(cpu_id));
+        }
+        map.finish()
+    }
+}
@@ -0,0 +1,59 @@
+/// An entry of the per-CPU destructor table, which drops one per-CPU static
+/// variable on the given CPU, or `None` if the variable does not need to be
+/// dropped.
+///
+/// The entries are generated by [`def_percpu`](crate::def_percpu) in the
+/// `percpu_dtors` section.
+#[doc(hidden)]
+pub type PercpuDtor = Option<unsafe fn(usize)>;
+
+/// Returns the per-CPU destructor table, i.e., the `percpu_dtors` section.
+#[cfg(not(any(target_os = "windows", target_family = "wasm", miri)))]
+fn dtor_table() -> &'static [PercpuDtor] {
+    // The linker defines `__start_<section>` and `__stop_<section>` for
+    // sections whose names are valid C identifiers.
+    #[cfg(not(target_os = "macos"))]
+    extern "C" {
+        fn __start_percpu_dtors();
+        fn __stop_percpu_dtors();
+    }
+    // The linker of macOS defines `section$start$<segment>$<section>` and
+    // `section$end$<segment>$<section>` instead.
+    #[cfg(target_os = "macos")]
+    extern "C" {
+        #[link_name = "\u{1}section$start$__DATA$__percpu_dtors"]
+        fn __start_percpu_dtors();
+        #[link_name = "\u{1}section$end$__DATA$__percpu_dtors"]
+        fn __stop_percpu_dtors();
+    }
+    let start = __start_percpu_dtors as *const () as usize;
+    let end = __stop_percpu_dtors as *const () as usize;
+    let len = (end - start) / core::mem::size_of::<PercpuDtor>();
+    unsafe { core::slice::from_raw_parts(start as *const PercpuDtor, len) }
+}
+
+/// The per-CPU destructor table is not collected on Windows and WebAssembly, and
+/// can not be found by Miri.
+#[cfg(any(target_os = "windows", target_family = "wasm", miri))]
+fn dtor_table() -> &'static [PercpuDtor] {
+    &[]
+}
+
+/// Drops all per-CPU static variables on the given CPU, e.g., the heap
+/// memory owned by `Vec` or `Box` is released.
+///
+/// It can be used when a CPU goes offline, or when the per-CPU data areas
+/// are going to be released. Per-CPU static variables that do not need to be
+/// dropped are left untouched.
+///
+/// # Safety
+///
+/// The per-CPU data on the given CPU must not be accessed after this call,
+/// until it is initialized again by [`init_area`](crate::init_area). It must
+/// not be called twice on the same CPU without initialization in between.
+pub unsafe fn deinit(cpu_id: impl Into<crate::CpuId>) {
+    let cpu_id = cpu_id.into().get();
+    for dtor in dtor_table().iter().flatten() {
+        dtor(cpu_id);
+    }
+}
@@ -0,0 +1,56 @@
+use core::fmt::{self, Write};
+
+use crate::{percpu_area_base, percpu_area_num, percpu_area_size};
+
+/// Number of bytes printed in each line of the hex dump.
+const BYTES_PER_LINE: usize = 16;
+
+/// Hex-dumps the per-CPU data area of the given CPU to `w`, e.g., to print
+/// the per-CPU state from a panic handler when debugging SMP crashes.
+///
+/// With the `introspect` feature, each per-CPU static variable is annotated
+/// with its name, type and size before the line where it starts.
+///
+/// # Panics
+///
+/// Panics if `cpu_id` is not less than [`percpu_area_num()`].
+#[doc(cfg(not(feature = "sp-naive")))]
+pub fn dump_area(cpu_id: impl Into<crate::CpuId>, w: &mut dyn Write) -> fmt::Result {
+    let cpu_id = cpu_id.into().get();
+    assert!(cpu_id < percpu_area_num(), "invalid CPU ID: {}", cpu_id);
+    let base = percpu_area_base(cpu_id);
+    let size = percpu_area_size();
+    writeln!(
+        w,
+        "per-CPU data area of CPU {} at {:#x} ({:#x} bytes):",
+        cpu_id, base, size
+    )?;
+
+    for line in (0..size).step_by(BYTES_PER_LINE) {
+        let line_end = (line + BYTES_PER_LINE).min(size);
+
+        #[cfg(feature = "introspect")]
+        for info in crate::layout() {
+            let offset = info.offset();
+            if (line..line_end).contains(&offset) {
+                writeln!(
+                    w,
+                    "        @{:#06x} {}: {} ({} bytes)",
+                    offset,
+                    info.name(),
+                    info.type_name(),
+                    info.size()
+                )?;
+            }
+        }
+
+        write!(w, "{:#06x}:", line)?;
+        for offset in line..line_end {
+            // SAFETY: the offset is within the per-CPU data area.
+            let byte = unsafe { ((base + offset) as *const u8).read_volatile() };
+            write!(w, " {:02x}", byte)?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use crate::sync::{const_fn, fence, spin_loop, AtomicUsize, Ordering};
+
+/// A per-CPU epoch counter for quiescent-state tracking, like a minimal RCU.
+///
+/// It must be used as the type of a per-CPU static variable defined by
+/// [`def_percpu`](crate::def_percpu). Each CPU bumps its own epoch at
+/// quiescent points, where it holds no reference to the shared data protected
+/// by the epoch (e.g., on context switches or returning to the user space).
+/// An updater unpublishes the old data, then waits for all other online CPUs
+/// to pass a quiescent point, after which no CPU can hold a reference to the
+/// old data, and it can be freed.
+///
+/// Only the CPUs brought online by [`cpu_online`](crate::cpu_online) are
+/// waited for, so a CPU should bump its epoch periodically (e.g., in the idle
+/// loop) while it is online.
+///
+/// The following methods are generated in the wrapper struct:
+///
+/// - `quiescent_current()`: bumps the epoch of the current CPU, which only
+///   writes the per-CPU data area of the current CPU.
+/// - `synchronize()`: spins until every other online CPU has bumped its epoch
+///   after the call begins.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use percpu::PercpuEpoch;
+///
+/// #[percpu::def_percpu]
+/// static RCU: PercpuEpoch = PercpuEpoch::new();
+///
+/// percpu::init(4);
+/// percpu::cpu_online(0);
+///
+/// // On each CPU, e.g., on context switches:
+/// RCU.quiescent_current();
+///
+/// // On the updater, after unpublishing the old data:
+/// RCU.synchronize();
+/// // The old data can be freed now.
+/// ```
+pub struct PercpuEpoch {
+    epoch: AtomicUsize,
+}
+
+impl PercpuEpoch {
+    const_fn! {
+        /// Creates a new epoch counter.
+        pub const fn new() -> Self {
+            Self {
+                epoch: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    /// Bumps the epoch, which marks a quiescent point of the CPU that owns it.
+    ///
+    /// The accesses before it are visible to the updaters that see the new
+    /// epoch, and the accesses after it see the data unpublished by the
+    /// updaters waiting for it.
+    pub fn quiescent(&self) {
+        // Only the owner CPU writes the epoch, so no read-modify-write is
+        // needed.
+        let epoch = self.epoch.load(Ordering::Relaxed);
+        self.epoch.store(epoch.wrapping_add(1), Ordering::Release);
+        fence(Ordering::SeqCst);
+    }
+
+    /// Returns the current epoch, with acquire ordering.
+    pub fn get(&self) -> usize {
+        self.epoch.load(Ordering::Acquire)
+    }
+}
+
+impl Default for PercpuEpoch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Spins until every online CPU other than `current_cpu` has passed a
+/// quiescent point, where `epoch_of` returns the [`PercpuEpoch`] of a CPU.
+#[doc(hidden)]
+pub fn synchronize<'a>(current_cpu: usize, epoch_of: impl Fn(usize) -> &'a PercpuEpoch) {
+    // Orders the unpublishing before reading the epochs, pairing with the fence
+    // in `PercpuEpoch::quiescent`.
+    fence(Ordering::SeqCst);
+    for cpu_id in 0..crate::percpu_area_num() {
+        if cpu_id == current_cpu || !crate::is_cpu_online(cpu_id) {
+            continue;
+        }
+        // The CPU may go offline while waiting, which is also quiescent.
+        let epoch = epoch_of(cpu_id);
+        let start = epoch.get();
+        while epoch.get() == start && crate::is_cpu_online(cpu_id) {
+            spin_loop();
+        }
+    }
+}
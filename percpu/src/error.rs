@@ -0,0 +1,40 @@
+use core::fmt;
+
+/// The error type of the fallible operations on the per-CPU data areas, e.g.,
+/// [`try_init`](crate::try_init) and
+/// [`try_percpu_area_base`](crate::try_percpu_area_base).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PercpuError {
+    /// The per-CPU data areas are not initialized, or the area of the CPU is
+    /// not registered in the base table mode.
+    NotInitialized,
+    /// The CPU ID is not less than the number of per-CPU data areas, or than
+    /// [`MAX_CPUS`](crate::MAX_CPUS).
+    InvalidCpuId(usize),
+    /// The number of CPUs exceeds [`MAX_CPUS`](crate::MAX_CPUS).
+    TooManyCpus(usize),
+    /// Failed to allocate the per-CPU data areas in hosted mode.
+    AllocFailed,
+    /// The `.percpu` section is missing or empty, e.g., it is discarded by the
+    /// linker script.
+    SectionMissing,
+}
+
+impl fmt::Display for PercpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotInitialized => write!(f, "per-CPU data areas are not initialized"),
+            Self::InvalidCpuId(cpu_id) => write!(f, "invalid CPU ID: {}", cpu_id),
+            Self::TooManyCpus(num) => write!(
+                f,
+                "too many CPUs: {} (PERCPU_MAX_CPUS is {})",
+                num,
+                crate::MAX_CPUS
+            ),
+            Self::AllocFailed => write!(f, "failed to allocate per-CPU data areas"),
+            Self::SectionMissing => write!(f, "per-CPU data section is missing or empty"),
+        }
+    }
+}
+
+impl core::error::Error for PercpuError {}
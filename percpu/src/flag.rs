@@ -0,0 +1,80 @@
+use crate::sync::{const_fn, spin_loop, AtomicBool, Ordering};
+
+/// A per-CPU flag for rendezvous of CPUs, e.g., waiting for all CPUs to boot,
+/// to enter the idle loop, or to acknowledge a TLB shootdown IPI.
+///
+/// It must be used as the type of a per-CPU static variable defined by
+/// [`def_percpu`](crate::def_percpu). Each CPU sets (or clears) its own flag
+/// with release ordering, and other CPUs observe it with acquire ordering, so
+/// everything written by a CPU before setting its flag is visible to the CPUs
+/// that see the flag set.
+///
+/// The following methods are generated in the wrapper struct:
+///
+/// - `set_current()`, `clear_current()`: sets (or clears) the flag on the
+///   current CPU.
+/// - `is_set_current()`: returns whether the flag on the current CPU is set.
+/// - `is_set_remote(cpu_id)`: returns whether the flag on the given CPU is set.
+/// - `wait_until_all_set(cpus)`: spins until the flags on all the given CPUs
+///   (e.g., a range of CPU IDs, or an iterator of [`CpuId`](crate::CpuId)) are
+///   set.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use percpu::PercpuFlag;
+///
+/// #[percpu::def_percpu]
+/// static CPU_BOOTED: PercpuFlag = PercpuFlag::new();
+///
+/// percpu::init(4);
+/// percpu::set_local_thread_pointer(0);
+///
+/// // On each CPU, after it is initialized:
+/// CPU_BOOTED.set_current();
+///
+/// // On the boot CPU:
+/// CPU_BOOTED.wait_until_all_set(0..4);
+/// ```
+pub struct PercpuFlag {
+    flag: AtomicBool,
+}
+
+impl PercpuFlag {
+    const_fn! {
+        /// Creates a new flag that is not set.
+        pub const fn new() -> Self {
+            Self {
+                flag: AtomicBool::new(false),
+            }
+        }
+    }
+
+    /// Sets the flag, with release ordering.
+    pub fn set(&self) {
+        self.flag.store(true, Ordering::Release);
+    }
+
+    /// Clears the flag, with release ordering.
+    pub fn clear(&self) {
+        self.flag.store(false, Ordering::Release);
+    }
+
+    /// Returns whether the flag is set, with acquire ordering.
+    pub fn is_set(&self) -> bool {
+        self.flag.load(Ordering::Acquire)
+    }
+
+    /// Spins until the flag is set.
+    pub fn wait(&self) {
+        while !self.is_set() {
+            spin_loop();
+        }
+    }
+}
+
+impl Default for PercpuFlag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
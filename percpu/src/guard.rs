@@ -0,0 +1,156 @@
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+
+#[cfg(feature = "preempt-if")]
+use crate::__priv::NoPreemptGuard;
+
+/// A shared reference to the per-CPU data on the current CPU.
+///
+/// It is returned by the `current()` method of per-CPU static variables.
+/// Preemption is disabled until the guard is dropped (if the `preempt` or
+/// `preempt-if` feature is enabled), so the reference always points to the data of the
+/// CPU it was obtained on.
+pub struct PerCpuRef<'a, T> {
+    value: &'a T,
+    // Released before preemption is enabled again.
+    #[cfg(feature = "debug-borrow-check")]
+    _borrow: Option<crate::__priv::BorrowGuard>,
+    #[cfg(feature = "preempt-if")]
+    _guard: NoPreemptGuard,
+    // Must not be sent to other CPUs.
+    _not_send: PhantomData<*const ()>,
+}
+
+/// A mutable reference to the per-CPU data on the current CPU.
+///
+/// It is returned by the `current_mut()` method of per-CPU static variables.
+/// Preemption is disabled until the guard is dropped (if the `preempt` or
+/// `preempt-if` feature is enabled), so the reference always points to the data of the
+/// CPU it was obtained on.
+pub struct PerCpuRefMut<'a, T> {
+    value: &'a mut T,
+    // Released before preemption is enabled again.
+    #[cfg(feature = "debug-borrow-check")]
+    _borrow: Option<crate::__priv::BorrowGuard>,
+    #[cfg(feature = "preempt-if")]
+    _guard: NoPreemptGuard,
+    // Must not be sent to other CPUs.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<'a, T> PerCpuRef<'a, T> {
+    /// Disables preemption, then creates the guard from the reference
+    /// returned by `f`.
+    ///
+    /// # Safety
+    ///
+    /// `f` must return the reference of the per-CPU data on the current CPU.
+    #[doc(hidden)]
+    #[inline]
+    pub unsafe fn new(f: impl FnOnce() -> &'a T) -> Self {
+        #[cfg(feature = "preempt-if")]
+        let guard = NoPreemptGuard::new();
+        Self {
+            value: f(),
+            #[cfg(feature = "debug-borrow-check")]
+            _borrow: None,
+            #[cfg(feature = "preempt-if")]
+            _guard: guard,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Like [`new`](Self::new), but also marks the per-CPU data borrowed by
+    /// `borrow` until the guard is dropped, with the `debug-borrow-check`
+    /// feature.
+    #[cfg(feature = "debug-borrow-check")]
+    #[doc(hidden)]
+    #[inline]
+    pub unsafe fn new_checked(
+        borrow: impl FnOnce() -> crate::__priv::BorrowGuard,
+        f: impl FnOnce() -> &'a T,
+    ) -> Self {
+        #[cfg(feature = "preempt-if")]
+        let guard = NoPreemptGuard::new();
+        let borrow = borrow();
+        Self {
+            value: f(),
+            _borrow: Some(borrow),
+            #[cfg(feature = "preempt-if")]
+            _guard: guard,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> PerCpuRefMut<'a, T> {
+    /// Disables preemption, then creates the guard from the mutable reference
+    /// returned by `f`.
+    ///
+    /// # Safety
+    ///
+    /// `f` must return the mutable reference of the per-CPU data on the
+    /// current CPU, and no other reference to the data may exist while the
+    /// guard is alive.
+    #[doc(hidden)]
+    #[inline]
+    pub unsafe fn new(f: impl FnOnce() -> &'a mut T) -> Self {
+        #[cfg(feature = "preempt-if")]
+        let guard = NoPreemptGuard::new();
+        Self {
+            value: f(),
+            #[cfg(feature = "debug-borrow-check")]
+            _borrow: None,
+            #[cfg(feature = "preempt-if")]
+            _guard: guard,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Like [`new`](Self::new), but also marks the per-CPU data borrowed by
+    /// `borrow` until the guard is dropped, with the `debug-borrow-check`
+    /// feature.
+    #[cfg(feature = "debug-borrow-check")]
+    #[doc(hidden)]
+    #[inline]
+    pub unsafe fn new_checked(
+        borrow: impl FnOnce() -> crate::__priv::BorrowGuard,
+        f: impl FnOnce() -> &'a mut T,
+    ) -> Self {
+        #[cfg(feature = "preempt-if")]
+        let guard = NoPreemptGuard::new();
+        let borrow = borrow();
+        Self {
+            value: f(),
+            _borrow: Some(borrow),
+            #[cfg(feature = "preempt-if")]
+            _guard: guard,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl<T> Deref for PerCpuRef<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T> Deref for PerCpuRefMut<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T> DerefMut for PerCpuRefMut<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
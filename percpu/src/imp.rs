@@ -3,80 +3,940 @@ const fn align_up_64(val: usize) -> usize {
     (val + SIZE_64BIT - 1) & !(SIZE_64BIT - 1)
 }
 
-#[cfg(not(target_os = "none"))]
-static PERCPU_AREA_BASE: spin::once::Once<usize> = spin::once::Once::new();
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use crate::cpu_id::MAX_CPUS_LIMIT;
+use crate::{CpuId, PercpuError};
+
+/// The base address of all per-CPU data areas, or `0` if it is not set yet.
+///
+/// On bare-metal, `0` means using the region reserved by the linker script
+/// (starts at `_percpu_start`).
+static PERCPU_AREA_BASE: AtomicUsize = AtomicUsize::new(0);
+
+static PERCPU_AREA_NUM: AtomicUsize = AtomicUsize::new(0);
+
+/// The size of the per-CPU data areas allocated by [`init`] in hosted mode, or
+/// `0` if they are not allocated.
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "windows"))]
+static PERCPU_AREA_ALLOC_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// The distance between the bases of adjacent per-CPU data areas, set by
+/// [`set_percpu_area_align`] or [`init_with_guard_pages`], or `0` to use the
+/// per-CPU data area size aligned up to 64 bytes.
+static PERCPU_AREA_STRIDE: AtomicUsize = AtomicUsize::new(0);
+
+/// The size of the stack reserved in each per-CPU data area, and of the gap
+/// between it and the per-CPU data, set by [`set_percpu_stack_size`].
+static PERCPU_STACK_SIZE: AtomicUsize = AtomicUsize::new(0);
+static PERCPU_STACK_GUARD_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// The table of the per-CPU data area bases of [`percpu_area_num()`] CPUs in the
+/// base table mode, set by [`init_with_table`], or null if the per-CPU data
+/// areas are contiguous.
+static PERCPU_BASE_TABLE: AtomicPtr<AtomicUsize> = AtomicPtr::new(core::ptr::null_mut());
+
+/// The offset added to the base addresses of all per-CPU data areas, set by
+/// [`set_base_offset`].
+static PERCPU_BASE_OFFSET: AtomicUsize = AtomicUsize::new(0);
+
+/// The size of the guard page after each per-CPU data area, set by
+/// [`init_with_guard_pages`], or `0` if there are no guard pages.
+static PERCPU_GUARD_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// The canary word right after the per-CPU data of each CPU.
+#[cfg(feature = "debug-canary")]
+const TAIL_CANARY: u64 = 0x5045_5243_5055_5441; // "PERCPUTA"
+
+/// The canary word right before the per-CPU data area of each CPU (except the
+/// first one).
+#[cfg(feature = "debug-canary")]
+const HEAD_CANARY: u64 = 0x5045_5243_5055_4844; // "PERCPUHD"
+
+/// Returns the number of per-CPU data areas, i.e., the `max_cpu_num` passed
+/// to [`init`].
+///
+/// Returns `0` if [`init`] has not been called.
+pub fn percpu_area_num() -> usize {
+    PERCPU_AREA_NUM.load(Ordering::Acquire)
+}
+
+/// Returns whether the per-CPU data areas have been initialized, by [`init`],
+/// [`init_with`] or [`init_with_base`].
+pub fn is_init() -> bool {
+    percpu_area_num() > 0
+}
+
+/// Resets the per-CPU data areas to the uninitialized state, so that they can
+/// be initialized again, e.g., by each test case in the same process.
+///
+/// The per-CPU data areas allocated by [`init`] in hosted mode are freed. The
+/// memory region given to [`init_with_base`] is left untouched, and the offset
+/// set by [`set_base_offset`] is cleared. Per-CPU static variables are not
+/// dropped, call [`deinit`](crate::deinit) before it if needed.
+///
+/// # Safety
+///
+/// The per-CPU data must not be accessed on any CPU (or thread in hosted
+/// mode) until the per-CPU data areas are initialized again, and
+/// [`set_local_thread_pointer`] is called again.
+#[cfg(feature = "test-util")]
+#[doc(cfg(feature = "test-util"))]
+pub unsafe fn reinit_for_test() {
+    PERCPU_AREA_NUM.store(0, Ordering::Release);
+    PERCPU_BASE_OFFSET.store(0, Ordering::Relaxed);
+    PERCPU_AREA_STRIDE.store(0, Ordering::Relaxed);
+    PERCPU_GUARD_SIZE.store(0, Ordering::Relaxed);
+    PERCPU_STACK_SIZE.store(0, Ordering::Relaxed);
+    PERCPU_STACK_GUARD_SIZE.store(0, Ordering::Relaxed);
+    PERCPU_BASE_TABLE.store(core::ptr::null_mut(), Ordering::Release);
+    let _base = PERCPU_AREA_BASE.swap(0, Ordering::AcqRel);
+    #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "windows"))]
+    {
+        let size = PERCPU_AREA_ALLOC_SIZE.swap(0, Ordering::AcqRel);
+        if size != 0 {
+            let layout = std::alloc::Layout::from_size_align(size, 0x1000).unwrap();
+            std::alloc::dealloc(_base as *mut u8, layout);
+        }
+    }
+}
 
 /// Returns the per-CPU data area size for one CPU.
 #[doc(cfg(not(feature = "sp-naive")))]
 pub fn percpu_area_size() -> usize {
-    extern "C" {
-        fn _percpu_load_start();
-        fn _percpu_load_end();
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "windows")] {
+            windows::load_end() - windows::load_start()
+        } else if #[cfg(any(feature = "pic", feature = "asm-free-offset"))] {
+            extern "C" {
+                fn _percpu_load_end();
+            }
+            _percpu_load_end as *const () as usize - pic::load_start()
+        } else {
+            extern "C" {
+                fn _percpu_load_start();
+                fn _percpu_load_end();
+            }
+            // It seems that `_percpu_load_start as usize - _percpu_load_end as usize` will result in more instructions.
+            use percpu_macros::percpu_symbol_offset;
+            percpu_symbol_offset!(_percpu_load_end) - percpu_symbol_offset!(_percpu_load_start)
+        }
     }
-    // It seems that `_percpu_load_start as usize - _percpu_load_end as usize` will result in more instructions.
-    use percpu_macros::percpu_symbol_offset;
-    percpu_symbol_offset!(_percpu_load_end) - percpu_symbol_offset!(_percpu_load_start)
 }
 
-/// Returns the base address of the per-CPU data area on the given CPU.
+/// Returns the size of the zero-initialized part (`.percpu.bss`) of the
+/// per-CPU data area for one CPU.
 ///
-/// if `cpu_id` is 0, it returns the base address of all per-CPU data areas.
+/// This part is placed at the beginning of each per-CPU data area, and is
+/// cleared instead of copied during initialization. It is `0` if the linker
+/// script does not define `_percpu_bss_end`, in which case the whole area is
+/// copied.
 #[doc(cfg(not(feature = "sp-naive")))]
-pub fn percpu_area_base(cpu_id: usize) -> usize {
+pub fn percpu_bss_size() -> usize {
     cfg_if::cfg_if! {
-        if #[cfg(target_os = "none")] {
+        if #[cfg(target_os = "windows")] {
+            windows::bss_end() - windows::load_start()
+        } else {
+            // A weak reference, since linker scripts written before the split
+            // of `.percpu.bss` do not define it.
             extern "C" {
-                fn _percpu_start();
+                #[linkage = "extern_weak"]
+                static _percpu_bss_end: *const u8;
+            }
+            let bss_end = unsafe { _percpu_bss_end } as usize;
+            if bss_end == 0 {
+                return 0;
+            }
+            #[cfg(any(feature = "pic", feature = "asm-free-offset"))]
+            {
+                bss_end - pic::load_start()
+            }
+            // `.percpu` is linked at address 0, so the address is the offset.
+            #[cfg(not(any(feature = "pic", feature = "asm-free-offset")))]
+            {
+                extern "C" {
+                    fn _percpu_load_start();
+                }
+                use percpu_macros::percpu_symbol_offset;
+                bss_end - percpu_symbol_offset!(_percpu_load_start)
             }
-            let base = _percpu_start as usize;
+        }
+    }
+}
+
+/// Returns the size of the initialized part (`.percpu` except `.percpu.bss`)
+/// of the per-CPU data area for one CPU.
+///
+/// This part follows the zero-initialized part, and is copied from the initial
+/// per-CPU data during initialization.
+#[doc(cfg(not(feature = "sp-naive")))]
+pub fn percpu_data_size() -> usize {
+    percpu_area_size() - percpu_bss_size()
+}
+
+/// Returns the per-CPU data area size for one CPU, including the room for the
+/// canary words with the `debug-canary` feature.
+fn percpu_area_size_padded() -> usize {
+    if cfg!(feature = "debug-canary") {
+        percpu_area_size().next_multiple_of(8) + 16
+    } else {
+        percpu_area_size()
+    }
+}
+
+/// Returns the size reserved for one CPU before aligning it to the stride,
+/// i.e., the padded per-CPU data area size, plus the stack and its guard gap
+/// set by [`set_percpu_stack_size`].
+fn percpu_area_size_reserved() -> usize {
+    percpu_area_size_padded()
+        + PERCPU_STACK_GUARD_SIZE.load(Ordering::Relaxed)
+        + PERCPU_STACK_SIZE.load(Ordering::Relaxed)
+}
+
+/// Returns the base address of the per-CPU data area on the given CPU, whose ID
+/// is either a [`CpuId`] or a `usize`.
+///
+/// if `cpu_id` is 0, it returns the base address of all per-CPU data areas,
+/// unless in the base table mode (see [`init_with_table`]).
+///
+/// `cpu_id` is not checked against [`percpu_area_num()`] (except in the base
+/// table mode), so an address past the per-CPU data areas may be returned. Use
+/// [`try_percpu_area_base`] to check it.
+///
+/// # Panics
+///
+/// Panics if the per-CPU data areas are not initialized in hosted mode, or if
+/// the area of the CPU is not registered in the base table mode.
+#[doc(cfg(not(feature = "sp-naive")))]
+pub fn percpu_area_base(cpu_id: impl Into<CpuId>) -> usize {
+    area_base(cpu_id.into().get()).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Returns the base address of the per-CPU data area on the given CPU, like
+/// [`percpu_area_base`], or an error instead of panicking.
+///
+/// Unlike [`percpu_area_base`], `cpu_id` is also checked against
+/// [`percpu_area_num()`] once the per-CPU data areas are initialized.
+#[doc(cfg(not(feature = "sp-naive")))]
+pub fn try_percpu_area_base(cpu_id: impl Into<CpuId>) -> Result<usize, PercpuError> {
+    let cpu_id = cpu_id.into().get();
+    if is_init() && cpu_id >= percpu_area_num() {
+        return Err(PercpuError::InvalidCpuId(cpu_id));
+    }
+    area_base(cpu_id)
+}
+
+/// Returns whether `num` CPUs exceed [`MAX_CPUS`](crate::MAX_CPUS).
+fn exceeds_max_cpus(num: usize) -> bool {
+    matches!(MAX_CPUS_LIMIT, Some(max_cpus) if num > max_cpus)
+}
+
+fn area_base(cpu_id: usize) -> Result<usize, PercpuError> {
+    if exceeds_max_cpus(cpu_id + 1) {
+        return Err(PercpuError::InvalidCpuId(cpu_id));
+    }
+    let table = PERCPU_BASE_TABLE.load(Ordering::Acquire);
+    if !table.is_null() {
+        return Ok(table_area_base(table, cpu_id)?
+            .wrapping_add(PERCPU_BASE_OFFSET.load(Ordering::Relaxed)));
+    }
+    let base = PERCPU_AREA_BASE.load(Ordering::Relaxed);
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "none")] {
+            let base = if base == 0 { percpu_template_base() } else { base };
         } else {
-            let base = *PERCPU_AREA_BASE.get().unwrap();
+            if base == 0 {
+                return Err(PercpuError::NotInitialized);
+            }
         }
     }
-    base + cpu_id * align_up_64(percpu_area_size())
+    let base = base.wrapping_add(PERCPU_BASE_OFFSET.load(Ordering::Relaxed));
+    Ok(base + cpu_id * percpu_area_stride())
+}
+
+/// Returns the distance between the base addresses of the per-CPU data areas
+/// on adjacent CPUs.
+///
+/// It is the per-CPU data area size aligned up to 64 bytes by default, or to
+/// the alignment set by [`set_percpu_area_align`].
+#[doc(cfg(not(feature = "sp-naive")))]
+pub fn percpu_area_stride() -> usize {
+    match PERCPU_AREA_STRIDE.load(Ordering::Relaxed) {
+        0 => align_up_64(percpu_area_size_reserved()),
+        stride => stride,
+    }
+}
+
+/// Sets the alignment of the per-CPU data area stride, instead of 64 bytes,
+/// e.g., 128 bytes to avoid false sharing on CPUs with 128-byte cache lines,
+/// or 4 KiB to place the per-CPU data area of each CPU in its own pages.
+///
+/// It must be called before the per-CPU data areas are initialized. On
+/// bare-metal, the region reserved by the linker script must be large enough,
+/// e.g., by [`PercpuSection::with_align`](crate::linker::PercpuSection::with_align).
+///
+/// # Panics
+///
+/// Panics if `align` is not a power of two, if it is less than 64, or if the
+/// per-CPU data areas have already been initialized.
+#[doc(cfg(not(feature = "sp-naive")))]
+pub fn set_percpu_area_align(align: usize) {
+    assert!(
+        align.is_power_of_two() && align >= 64,
+        "invalid per-CPU data area alignment: {:#x}",
+        align
+    );
+    assert!(!is_init(), "per-CPU data areas are already initialized");
+    let stride = percpu_area_size_reserved().next_multiple_of(align);
+    PERCPU_AREA_STRIDE.store(stride, Ordering::Relaxed);
+}
+
+/// Reserves a stack of `size` bytes at the end of each per-CPU data area, below
+/// a gap of `guard_size` bytes after the per-CPU data, so that the boot code of
+/// each CPU can find its stack by [`stack_top`].
+///
+/// The stride of the per-CPU data areas grows accordingly. It must be called
+/// before [`set_percpu_area_align`] or [`init_with_guard_pages`] (which also
+/// keep the stacks), and before the per-CPU data areas are initialized. On
+/// bare-metal, the region reserved by the linker script must be large enough,
+/// e.g., by [`PercpuSection::with_stack`](crate::linker::PercpuSection::with_stack).
+///
+/// # Panics
+///
+/// Panics if `size` or `guard_size` is not a multiple of 16 bytes, if the
+/// stride of the per-CPU data areas has already been set, or if the per-CPU
+/// data areas have already been initialized.
+#[doc(cfg(not(feature = "sp-naive")))]
+pub fn set_percpu_stack_size(size: usize, guard_size: usize) {
+    assert!(
+        size.is_multiple_of(16) && guard_size.is_multiple_of(16),
+        "per-CPU stack size {:#x} or guard size {:#x} is not 16-byte aligned",
+        size,
+        guard_size
+    );
+    assert!(!is_init(), "per-CPU data areas are already initialized");
+    assert!(
+        PERCPU_AREA_STRIDE.load(Ordering::Relaxed) == 0,
+        "per-CPU data area stride is already set"
+    );
+    PERCPU_STACK_SIZE.store(size, Ordering::Relaxed);
+    PERCPU_STACK_GUARD_SIZE.store(guard_size, Ordering::Relaxed);
+}
+
+/// Returns the top (initial stack pointer) of the stack reserved for the given
+/// CPU by [`set_percpu_stack_size`].
+///
+/// The stack ends at the end of the per-CPU data area of the CPU (or at its
+/// guard page with [`init_with_guard_pages`]), so the top is aligned to at
+/// least 64 bytes, or to the alignment set by [`set_percpu_area_align`]. It
+/// grows down towards the per-CPU data of the same CPU, from which it is
+/// separated by the guard gap, and holds at least the reserved size. `size`
+/// is the stack size the caller expects, which is checked against the
+/// reserved one.
+///
+/// In the base table mode (see [`init_with_table`]), each registered area
+/// must be [`percpu_area_stride()`] bytes to hold the stack.
+///
+/// # Panics
+///
+/// Panics if `size` is larger than the reserved stack size, or in the cases
+/// [`percpu_area_base`] panics.
+#[doc(cfg(not(feature = "sp-naive")))]
+pub fn stack_top(cpu_id: impl Into<CpuId>, size: usize) -> usize {
+    let reserved = PERCPU_STACK_SIZE.load(Ordering::Relaxed);
+    assert!(
+        size <= reserved,
+        "per-CPU stack of {:#x} bytes requested, but only {:#x} bytes reserved",
+        size,
+        reserved
+    );
+    percpu_area_base(cpu_id) + percpu_area_stride() - PERCPU_GUARD_SIZE.load(Ordering::Relaxed)
+}
+
+/// Sets the offset (wrapping around) added to the base addresses of all
+/// per-CPU data areas, for the per-CPU data areas mapped at a different
+/// virtual address than the one they are initialized at.
+///
+/// For example, if the per-CPU data areas are initialized at their physical
+/// addresses, it should be set to the distance between the virtual and
+/// physical addresses after paging is enabled. It applies to
+/// [`percpu_area_base`], and thus to the remote accessors and
+/// [`set_local_thread_pointer`]. The thread pointer register is not changed,
+/// so each CPU must call [`set_local_thread_pointer`] again after it.
+#[doc(cfg(not(feature = "sp-naive")))]
+pub fn set_base_offset(delta: usize) {
+    PERCPU_BASE_OFFSET.store(delta, Ordering::Relaxed);
+}
+
+/// Returns the offset set by [`set_base_offset`], or `0` if it is not set.
+#[doc(cfg(not(feature = "sp-naive")))]
+pub fn base_offset() -> usize {
+    PERCPU_BASE_OFFSET.load(Ordering::Relaxed)
+}
+
+/// Returns the address of the initial per-CPU data (loaded by the bootloader
+/// at `_percpu_start`).
+#[cfg(target_os = "none")]
+fn percpu_template_base() -> usize {
+    extern "C" {
+        fn _percpu_start();
+    }
+    _percpu_start as usize
+}
+
+/// Returns the address of the initial per-CPU data to copy from.
+fn template_base() -> usize {
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "none")] {
+            percpu_template_base()
+        } else if #[cfg(target_os = "windows")] {
+            // The `.percpu` section is loaded on Windows.
+            windows::load_start()
+        } else {
+            // The `.percpu` section is not loaded in hosted mode, use the
+            // per-CPU data of the primary CPU instead.
+            percpu_area_base(0)
+        }
+    }
+}
+
+/// Copies the initial per-CPU data to the per-CPU data area at `area_base`,
+/// and clears its zero-initialized part.
+fn copy_template_to(area_base: usize, template: usize) {
+    let bss_size = percpu_bss_size();
+    let data_size = percpu_data_size();
+    // The primary CPU may have accessed its per-CPU data already, keep it.
+    if area_base != template {
+        unsafe {
+            core::ptr::write_bytes(area_base as *mut u8, 0, bss_size);
+            core::ptr::copy_nonoverlapping(
+                (template + bss_size) as *const u8,
+                (area_base + bss_size) as *mut u8,
+                data_size,
+            );
+        }
+    }
+}
+
+/// Initializes the per-CPU data area at `area_base` outside the global per-CPU
+/// data areas (see [`PercpuRegion`](crate::PercpuRegion)), as the one of CPU
+/// `cpu_id`.
+///
+/// The initial per-CPU data is copied, and the bookkeeping per-CPU data written
+/// by [`set_local_thread_pointer`] is written in place, so that the area can be
+/// installed in the per-CPU register directly.
+pub(crate) fn init_area_at(area_base: usize, cpu_id: usize) {
+    copy_template_to(area_base, template_base());
+    unsafe {
+        #[cfg(feature = "debug-init-check")]
+        ((area_base + INIT_MAGIC.offset()) as *mut u32).write_volatile(INIT_MAGIC_VALUE);
+        #[cfg(all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            not(target_os = "windows")
+        ))]
+        ((area_base + SELF_PTR.offset()) as *mut usize).write(area_base);
+        ((area_base + CPU_ID.offset()) as *mut usize).write(cpu_id);
+    }
+}
+
+/// Returns whether the per-CPU data areas are registered in a base table by
+/// [`init_with_table`], where they are not evenly spaced.
+pub(crate) fn has_base_table() -> bool {
+    !PERCPU_BASE_TABLE.load(Ordering::Acquire).is_null()
+}
+
+/// Copies the initial per-CPU data to the first `num` per-CPU data areas, and
+/// clears their zero-initialized part.
+fn copy_template(num: usize) {
+    let template = template_base();
+    for i in 0..num {
+        copy_template_to(percpu_area_base(i), template);
+    }
+    #[cfg(feature = "debug-canary")]
+    for i in 0..num {
+        write_canaries(i);
+    }
+}
+
+/// Returns the addresses of the canary words before and after the per-CPU data
+/// area of the given CPU, or `None` if there is no canary word.
+///
+/// The head canary of a CPU is in the padding of the previous CPU's area, so
+/// there is no head canary for the first CPU, or if the areas are separated by
+/// guard pages or end with stacks.
+#[cfg(feature = "debug-canary")]
+fn canary_addrs(cpu_id: usize) -> (Option<usize>, usize) {
+    let base = percpu_area_base(cpu_id);
+    let tail = base + percpu_area_size().next_multiple_of(8);
+    let head = (cpu_id > 0
+        && PERCPU_GUARD_SIZE.load(Ordering::Relaxed) == 0
+        && PERCPU_STACK_SIZE.load(Ordering::Relaxed) == 0)
+        .then(|| base - 8);
+    (head, tail)
+}
+
+/// Writes the canary words around the per-CPU data area of the given CPU.
+#[cfg(feature = "debug-canary")]
+fn write_canaries(cpu_id: usize) {
+    let (head, tail) = canary_addrs(cpu_id);
+    unsafe {
+        if let Some(head) = head {
+            (head as *mut u64).write_volatile(HEAD_CANARY);
+        }
+        (tail as *mut u64).write_volatile(TAIL_CANARY);
+    }
+}
+
+/// Checks the canary words around the per-CPU data areas of all CPUs, and
+/// returns the ID of the first CPU whose area is found corrupted, or `None` if
+/// all canary words are intact.
+///
+/// The canary words are written right before and after the per-CPU data area
+/// of each CPU during initialization (by [`init`], [`init_with_base`] and
+/// [`init_area`]), in the padding between the areas. A corrupted canary word
+/// indicates a wild write across the boundary of an area, e.g., a stack
+/// overflow or an out-of-bounds access to the per-CPU data. The head canary of
+/// the first CPU, and those of all CPUs if the areas are separated by guard
+/// pages, are not available.
+///
+/// On bare-metal, the linker script must reserve 16 more bytes for each area
+/// (which [`PercpuSection`](crate::linker::PercpuSection) does with this
+/// feature). The canary words are not checked in the base table mode (see
+/// [`init_with_table`]).
+#[cfg(feature = "debug-canary")]
+#[doc(cfg(feature = "debug-canary"))]
+pub fn check_canaries() -> Option<usize> {
+    if !PERCPU_BASE_TABLE.load(Ordering::Acquire).is_null() {
+        return None;
+    }
+    (0..percpu_area_num()).find(|&cpu_id| {
+        let (head, tail) = canary_addrs(cpu_id);
+        unsafe {
+            head.is_some_and(|head| (head as *const u64).read_volatile() != HEAD_CANARY)
+                || (tail as *const u64).read_volatile() != TAIL_CANARY
+        }
+    })
+}
+
+/// Initializes the per-CPU data area of the given CPU, by copying the initial
+/// per-CPU data to it.
+///
+/// All per-CPU data areas are initialized by [`init`], so it is only needed
+/// when a CPU is brought online again (CPU hotplug), to start with fresh
+/// per-CPU data instead of the stale values left by the last time. It must be
+/// called before the CPU calls [`set_local_thread_pointer`] (or
+/// [`cpu_init`](crate::cpu_init), which also runs the registered per-CPU init
+/// callbacks).
+///
+/// The per-CPU data area that holds the initial per-CPU data (of CPU 0 in
+/// hosted mode, or if the linker-reserved region is used) is left untouched.
+/// The old per-CPU data is overwritten without being dropped, see
+/// [`reset_area`] or [`deinit`](crate::deinit).
+///
+/// # Safety
+///
+/// The given CPU must be offline: no reference to its per-CPU data may exist,
+/// and no other CPU may access it (e.g., by `remote_ptr`) during the call.
+///
+/// # Panics
+///
+/// Panics if `cpu_id` is not less than [`percpu_area_num()`].
+#[doc(cfg(not(feature = "sp-naive")))]
+pub unsafe fn init_area(cpu_id: impl Into<CpuId>) {
+    let cpu_id = cpu_id.into().get();
+    assert!(cpu_id < percpu_area_num(), "invalid CPU ID: {}", cpu_id);
+    copy_template_to(percpu_area_base(cpu_id), template_base());
+    #[cfg(feature = "debug-canary")]
+    if PERCPU_BASE_TABLE.load(Ordering::Acquire).is_null() {
+        write_canaries(cpu_id);
+    }
+}
+
+/// Resets the per-CPU data area of the given CPU to the initial per-CPU data,
+/// after dropping the per-CPU data on it (see [`deinit`](crate::deinit)).
+///
+/// It is called after a CPU goes offline (CPU hotplug), so that remote
+/// accesses do not observe the stale values of the offline CPU, and the memory
+/// owned by its per-CPU data (e.g., `Vec` or `Box`) is released. The CPU must
+/// not access its per-CPU data until it is brought online again.
+///
+/// The per-CPU data area that holds the initial per-CPU data is neither
+/// dropped nor reset, like in [`init_area`].
+///
+/// # Safety
+///
+/// The same as [`init_area`]. Besides, the per-CPU data on the given CPU must
+/// be initialized, i.e., not already dropped by [`deinit`](crate::deinit).
+///
+/// # Panics
+///
+/// Panics if `cpu_id` is not less than [`percpu_area_num()`].
+#[doc(cfg(not(feature = "sp-naive")))]
+pub unsafe fn reset_area(cpu_id: impl Into<CpuId>) {
+    let cpu_id = cpu_id.into().get();
+    assert!(cpu_id < percpu_area_num(), "invalid CPU ID: {}", cpu_id);
+    if percpu_area_base(cpu_id) != template_base() {
+        crate::deinit(cpu_id);
+    }
+    init_area(cpu_id)
+}
+
+/// Returns the raw byte view of the per-CPU data area of the given CPU, which
+/// is [`percpu_area_size()`] bytes long.
+///
+/// It allows to snapshot or restore the per-CPU data of a CPU wholesale, e.g.,
+/// to checkpoint or migrate a vCPU in hypervisors. Dereferencing it is unsafe,
+/// the caller must ensure that data races will not happen, e.g., the CPU is
+/// offline or stopped.
+///
+/// # Panics
+///
+/// Panics if `cpu_id` is not less than [`percpu_area_num()`].
+#[doc(cfg(not(feature = "sp-naive")))]
+pub fn area_bytes(cpu_id: impl Into<CpuId>) -> *mut [u8] {
+    let cpu_id = cpu_id.into().get();
+    assert!(cpu_id < percpu_area_num(), "invalid CPU ID: {}", cpu_id);
+    core::ptr::slice_from_raw_parts_mut(percpu_area_base(cpu_id) as *mut u8, percpu_area_size())
+}
+
+/// Copies the whole per-CPU data area of `src_cpu` to that of `dst_cpu`, e.g.,
+/// to clone the per-CPU state of a vCPU.
+///
+/// # Safety
+///
+/// The per-CPU data of both CPUs must not be accessed during the copy, and the
+/// per-CPU data must be valid to be duplicated bitwise (e.g., it owns no
+/// resources that would be released twice).
+///
+/// # Panics
+///
+/// Panics if `src_cpu` or `dst_cpu` is not less than [`percpu_area_num()`].
+#[doc(cfg(not(feature = "sp-naive")))]
+pub unsafe fn copy_area(src_cpu: impl Into<CpuId>, dst_cpu: impl Into<CpuId>) {
+    let src_cpu = src_cpu.into().get();
+    let dst_cpu = dst_cpu.into().get();
+    let src = area_bytes(src_cpu);
+    let dst = area_bytes(dst_cpu);
+    if src_cpu != dst_cpu {
+        core::ptr::copy_nonoverlapping(src as *const u8, dst as *mut u8, src.len());
+    }
+}
+
+/// Returns the total size of the per-CPU data areas for `num_cpus` CPUs.
+///
+/// It can be used to reserve the memory for per-CPU data areas from the
+/// kernel's own allocator.
+#[doc(cfg(not(feature = "sp-naive")))]
+pub fn init_area_size_for(num_cpus: usize) -> usize {
+    percpu_area_stride() * num_cpus
 }
 
 /// Initialize the per-CPU data area for `max_cpu_num` CPUs.
+///
+/// # Panics
+///
+/// Panics if [`try_init`] fails.
 pub fn init(max_cpu_num: usize) {
-    let size = percpu_area_size();
+    if let Err(err) = try_init(max_cpu_num) {
+        panic!("{}", err);
+    }
+}
 
-    #[cfg(target_os = "linux")]
-    {
+/// Initialize the per-CPU data area for `max_cpu_num` CPUs, like [`init`], or
+/// returns an error instead of panicking.
+///
+/// It fails with [`PercpuError::SectionMissing`] if there is no per-CPU data
+/// at all (the crate defines some itself, so the `.percpu` section must have
+/// been discarded), with [`PercpuError::TooManyCpus`] if `max_cpu_num` exceeds
+/// [`MAX_CPUS`](crate::MAX_CPUS), or with [`PercpuError::AllocFailed`] if the per-CPU data
+/// areas cannot be allocated in hosted mode, or the areas allocated by an
+/// earlier call are too small for `max_cpu_num` CPUs.
+pub fn try_init(max_cpu_num: usize) -> Result<(), PercpuError> {
+    if percpu_area_size() == 0 {
+        return Err(PercpuError::SectionMissing);
+    }
+    if exceeds_max_cpus(max_cpu_num) {
+        return Err(PercpuError::TooManyCpus(max_cpu_num));
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "windows"))]
+    if PERCPU_AREA_BASE.load(Ordering::Acquire) == 0 {
         // we not load the percpu section in ELF, allocate them here.
-        let total_size = align_up_64(size) * max_cpu_num;
-        let layout = std::alloc::Layout::from_size_align(total_size, 0x1000).unwrap();
-        PERCPU_AREA_BASE.call_once(|| unsafe { std::alloc::alloc(layout) as usize });
+        let total_size = init_area_size_for(max_cpu_num);
+        let layout = std::alloc::Layout::from_size_align(total_size, 0x1000)
+            .map_err(|_| PercpuError::AllocFailed)?;
+        if total_size == 0 {
+            return Err(PercpuError::AllocFailed);
+        }
+        let base = unsafe { std::alloc::alloc_zeroed(layout) as usize };
+        if base == 0 {
+            return Err(PercpuError::AllocFailed);
+        }
+        if PERCPU_AREA_BASE
+            .compare_exchange(0, base, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            PERCPU_AREA_ALLOC_SIZE.store(total_size, Ordering::Release);
+        } else {
+            unsafe { std::alloc::dealloc(base as *mut u8, layout) };
+        }
+    }
+    #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "windows"))]
+    {
+        // the areas allocated by an earlier call may be too small.
+        let alloc_size = PERCPU_AREA_ALLOC_SIZE.load(Ordering::Acquire);
+        if alloc_size != 0 && init_area_size_for(max_cpu_num) > alloc_size {
+            return Err(PercpuError::AllocFailed);
+        }
     }
 
-    let base = percpu_area_base(0);
-    for i in 1..max_cpu_num {
-        let secondary_base = percpu_area_base(i);
-        // copy per-cpu data of the primary CPU to other CPUs.
-        unsafe {
-            core::ptr::copy_nonoverlapping(base as *const u8, secondary_base as *mut u8, size);
+    copy_template(max_cpu_num);
+    PERCPU_AREA_NUM.store(max_cpu_num, Ordering::Release);
+    Ok(())
+}
+
+/// Initialize the per-CPU data areas in the caller-provided memory region
+/// `base..base + size`, instead of the region reserved by the linker script
+/// (or allocated in hosted mode).
+///
+/// The initial per-CPU data is copied to as many per-CPU data areas as fit in
+/// the region, but no more than [`MAX_CPUS`](crate::MAX_CPUS), and the number of areas is
+/// returned.
+///
+/// # Safety
+///
+/// The memory region must be valid for reads and writes, and must not be
+/// used for other purposes for the rest of the program.
+///
+/// # Panics
+///
+/// Panics if `base` is not aligned to 64 bytes, if the region is too small
+/// for even one per-CPU data area, or if the per-CPU data areas have already
+/// been placed somewhere else.
+pub unsafe fn init_with_base(base: usize, size: usize) -> usize {
+    assert!(
+        base.is_multiple_of(64),
+        "per-CPU region base {:#x} is not 64-byte aligned",
+        base
+    );
+    let mut num = size / init_area_size_for(1).max(1);
+    if let Some(max_cpus) = MAX_CPUS_LIMIT {
+        num = num.min(max_cpus);
+    }
+    assert!(num > 0, "per-CPU region too small: {:#x} bytes", size);
+    assert!(
+        PERCPU_AREA_BASE
+            .compare_exchange(0, base, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok(),
+        "per-CPU data areas are already initialized"
+    );
+
+    copy_template(num);
+    PERCPU_AREA_NUM.store(num, Ordering::Release);
+    num
+}
+
+/// Initialize the per-CPU data area for exactly `num_cpus` CPUs, after
+/// checking that the reserved memory region is large enough.
+///
+/// On bare-metal, the reserved region is `_percpu_start.._percpu_end` defined
+/// in the linker script, so the `_percpu_end` symbol is required to use this
+/// function. In hosted mode, the region is allocated for exactly `num_cpus`
+/// CPUs, so the check always passes.
+///
+/// # Panics
+///
+/// Panics if the reserved region is smaller than
+/// [`init_area_size_for(num_cpus)`](init_area_size_for).
+pub fn init_with(num_cpus: usize) {
+    #[cfg(target_os = "none")]
+    {
+        extern "C" {
+            fn _percpu_start();
+            fn _percpu_end();
         }
+        let reserved = _percpu_end as usize - _percpu_start as usize;
+        let required = init_area_size_for(num_cpus);
+        assert!(
+            required <= reserved,
+            "per-CPU region too small: {:#x} bytes required for {} CPUs, but only {:#x} bytes reserved",
+            required,
+            num_cpus,
+            reserved,
+        );
+    }
+    init(num_cpus)
+}
+
+/// Initialize the per-CPU data areas for `max_cpu_num` CPUs like
+/// [`init_with`], with a guard page after each area.
+///
+/// Each per-CPU data area is padded to a multiple of `page_size`, and followed
+/// by a guard page of `page_size` bytes. `guard` is called with the address of
+/// each guard page, and should unmap or poison it (e.g., clear the present bit
+/// of its page table entry), so that an out-of-bounds write to the per-CPU data
+/// faults immediately, instead of corrupting the area of the next CPU.
+///
+/// On bare-metal, the reserved region must be large enough, e.g., end it at
+/// `_percpu_load_start + (ALIGN(4K) + 4K) * CPU_NUM` in the linker script for
+/// 4 KiB pages.
+///
+/// # Panics
+///
+/// Panics if `page_size` is not a power of two, if the per-CPU data areas have
+/// already been initialized, if the per-CPU data areas do not start at a page
+/// boundary, or if the reserved region is too small.
+pub fn init_with_guard_pages<F>(max_cpu_num: usize, page_size: usize, mut guard: F)
+where
+    F: FnMut(usize),
+{
+    assert!(
+        page_size.is_power_of_two(),
+        "page size {:#x} is not a power of two",
+        page_size
+    );
+    assert!(!is_init(), "per-CPU data areas are already initialized");
+    let stride = percpu_area_size_reserved().next_multiple_of(page_size) + page_size;
+    PERCPU_AREA_STRIDE.store(stride, Ordering::Relaxed);
+    PERCPU_GUARD_SIZE.store(page_size, Ordering::Relaxed);
+    init_with(max_cpu_num);
+
+    let base = percpu_area_base(0);
+    assert!(
+        base.is_multiple_of(page_size),
+        "per-CPU data areas base {:#x} is not page-aligned",
+        base
+    );
+    for cpu_id in 0..max_cpu_num {
+        guard(percpu_area_base(cpu_id) + stride - page_size);
+    }
+}
+
+/// Switches to the base table mode for `table.len()` CPUs, where the per-CPU
+/// data area of each CPU is allocated individually, and registered by
+/// [`register_area`], instead of being placed in one contiguous region.
+///
+/// It allows to allocate the per-CPU data areas on the NUMA node of each CPU,
+/// or when a CPU is brought online. `table` holds the base addresses of the
+/// per-CPU data areas, which are `0` until registered.
+///
+/// # Panics
+///
+/// Panics if `table` is empty or longer than [`MAX_CPUS`](crate::MAX_CPUS), or if the per-CPU
+/// data areas have already been initialized.
+pub fn init_with_table(table: &'static [AtomicUsize]) {
+    assert!(!table.is_empty(), "empty per-CPU base table");
+    assert!(
+        !exceeds_max_cpus(table.len()),
+        "{}",
+        PercpuError::TooManyCpus(table.len())
+    );
+    assert!(!is_init(), "per-CPU data areas are already initialized");
+    for base in table {
+        base.store(0, Ordering::Relaxed);
+    }
+    PERCPU_BASE_TABLE.store(table.as_ptr() as *mut AtomicUsize, Ordering::Release);
+    PERCPU_AREA_NUM.store(table.len(), Ordering::Release);
+}
+
+/// Registers the per-CPU data area of the given CPU at `base` in the base
+/// table mode, and copies the initial per-CPU data to it.
+///
+/// The area must be at least [`percpu_area_size()`] bytes. In hosted mode, the
+/// initial per-CPU data is taken from the area of CPU 0, so CPU 0 must be
+/// registered first, and its area is cleared.
+///
+/// # Safety
+///
+/// The memory region must be valid for reads and writes, and must not be
+/// used for other purposes until the CPU goes offline.
+///
+/// # Panics
+///
+/// Panics if it is not in the base table mode, if `cpu_id` is not less than
+/// [`percpu_area_num()`], if `base` is not aligned to 64 bytes, or if the area
+/// of the CPU has already been registered.
+pub unsafe fn register_area(cpu_id: impl Into<CpuId>, base: usize) {
+    let cpu_id = cpu_id.into().get();
+    let table = PERCPU_BASE_TABLE.load(Ordering::Acquire);
+    assert!(!table.is_null(), "not in the per-CPU base table mode");
+    assert!(cpu_id < percpu_area_num(), "invalid CPU ID: {}", cpu_id);
+    assert!(
+        base != 0 && base.is_multiple_of(64),
+        "per-CPU data area base {:#x} is not 64-byte aligned",
+        base
+    );
+    #[cfg(not(target_os = "none"))]
+    if cpu_id == 0 {
+        core::ptr::write_bytes(base as *mut u8, 0, percpu_area_size());
+    }
+    let slot = &*table.add(cpu_id);
+    assert!(
+        slot.compare_exchange(0, base, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok(),
+        "per-CPU data area of CPU {} is already registered",
+        cpu_id
+    );
+    copy_template_to(percpu_area_base(cpu_id), template_base());
+}
+
+/// Returns whether `addr` is the base address of the per-CPU data area of some
+/// CPU.
+#[cfg(all(target_arch = "x86_64", not(target_os = "windows")))]
+pub(crate) fn is_area_base(addr: usize) -> bool {
+    let num = percpu_area_num();
+    if !PERCPU_BASE_TABLE.load(Ordering::Acquire).is_null() {
+        return (0..num).any(|cpu_id| try_percpu_area_base(cpu_id) == Ok(addr));
+    }
+    let offset = addr.wrapping_sub(percpu_area_base(0));
+    let stride = percpu_area_stride();
+    offset.is_multiple_of(stride) && offset / stride < num
+}
+
+/// Returns the registered base address of the per-CPU data area on the given
+/// CPU in the base table mode.
+fn table_area_base(table: *mut AtomicUsize, cpu_id: usize) -> Result<usize, PercpuError> {
+    if cpu_id >= percpu_area_num() {
+        return Err(PercpuError::InvalidCpuId(cpu_id));
+    }
+    match unsafe { &*table.add(cpu_id) }.load(Ordering::Acquire) {
+        0 => Err(PercpuError::NotInitialized),
+        base => Ok(base),
     }
 }
 
 /// Read the architecture-specific thread pointer register on the current CPU.
+#[cfg_attr(target_os = "windows", allow(unused_unsafe))]
 pub fn get_local_thread_pointer() -> usize {
     let tp;
     unsafe {
         cfg_if::cfg_if! {
-            if #[cfg(target_arch = "x86_64")] {
-                tp = if cfg!(target_os = "linux") {
+            if #[cfg(target_os = "windows")] {
+                tp = windows::thread_pointer();
+            } else if #[cfg(target_arch = "x86_64")] {
+                tp = if cfg!(any(target_os = "linux", target_os = "freebsd")) {
                     SELF_PTR.read_current_raw()
                 } else if cfg!(target_os = "none") {
-                    x86::msr::rdmsr(x86::msr::IA32_GS_BASE) as usize
+                    if cfg!(feature = "x86-fsgsbase") {
+                        x86::bits64::segmentation::rdgsbase() as usize
+                    } else {
+                        x86::msr::rdmsr(x86::msr::IA32_GS_BASE) as usize
+                    }
+                } else {
+                    unimplemented!()
+                };
+            } else if #[cfg(target_arch = "x86")] {
+                tp = if cfg!(target_os = "none") {
+                    SELF_PTR.read_current_raw()
                 } else {
                     unimplemented!()
                 };
             } else if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
-                core::arch::asm!("mv {}, gp", out(reg) tp)
-            } else if #[cfg(all(target_arch = "aarch64", not(feature = "arm-el2")))] {
-                core::arch::asm!("mrs {}, TPIDR_EL1", out(reg) tp)
-            } else if #[cfg(all(target_arch = "aarch64", feature = "arm-el2"))] {
-                core::arch::asm!("mrs {}, TPIDR_EL2", out(reg) tp)
+                core::arch::asm!(concat!("mv {}, ", crate::__percpu_asm_rv_reg!()), out(reg) tp);
+            } else if #[cfg(target_arch = "aarch64")] {
+                core::arch::asm!(crate::__percpu_asm_read_tpidr!("{0}"), out(reg) tp)
+            } else if #[cfg(target_arch = "arm")] {
+                core::arch::asm!("mrc p15, 0, {}, c13, c0, 4", out(reg) tp) // TPIDRPRW
             } else if #[cfg(target_arch = "loongarch64")] {
                 // Register Convention
                 // https://docs.kernel.org/arch/loongarch/introduction.html#gprs
@@ -87,39 +947,126 @@ pub fn get_local_thread_pointer() -> usize {
     tp
 }
 
+/// Returns the ID of the current CPU, i.e., the `cpu_id` passed to
+/// [`set_local_thread_pointer`] on this CPU.
+pub fn current_cpu_id() -> usize {
+    CPU_ID.read_current()
+}
+
 /// Set the architecture-specific thread pointer register to the per-CPU data
 /// area base on the current CPU.
 ///
-/// `cpu_id` indicates which per-CPU data area to use.
-pub fn set_local_thread_pointer(cpu_id: usize) {
+/// `cpu_id` indicates which per-CPU data area to use. It can be obtained later
+/// by [`current_cpu_id`].
+///
+/// In hosted mode on Linux and FreeBSD, the register is per-thread, so each
+/// thread can act as a CPU by calling it with its own `cpu_id`.
+pub fn set_local_thread_pointer(cpu_id: impl Into<CpuId>) {
+    let cpu_id = cpu_id.into().get();
     let tp = percpu_area_base(cpu_id);
+    // The accessors check it with the `debug-init-check` feature, so it must be
+    // written before any per-CPU data is accessed.
+    #[cfg(feature = "debug-init-check")]
     unsafe {
-        cfg_if::cfg_if! {
-            if #[cfg(target_arch = "x86_64")] {
-                if cfg!(target_os = "linux") {
-                    const ARCH_SET_GS: u32 = 0x1001;
-                    const SYS_ARCH_PRCTL: u32 = 158;
-                    core::arch::asm!(
-                        "syscall",
-                        in("eax") SYS_ARCH_PRCTL,
-                        in("edi") ARCH_SET_GS,
-                        in("rsi") tp,
-                    );
-                } else if cfg!(target_os = "none") {
-                    x86::msr::wrmsr(x86::msr::IA32_GS_BASE, tp as u64);
-                } else {
-                    unimplemented!()
-                }
-                SELF_PTR.write_current_raw(tp);
-            } else if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
-                core::arch::asm!("mv gp, {}", in(reg) tp)
-            } else if #[cfg(all(target_arch = "aarch64", not(feature = "arm-el2")))] {
-                core::arch::asm!("msr TPIDR_EL1, {}", in(reg) tp)
-            } else if #[cfg(all(target_arch = "aarch64", feature = "arm-el2"))] {
+        ((tp + INIT_MAGIC.offset()) as *mut u32).write_volatile(INIT_MAGIC_VALUE);
+    }
+    #[cfg(feature = "tls-compat")]
+    let tls_base = tls::probe();
+    #[cfg(all(
+        feature = "tls-compat",
+        any(target_arch = "riscv32", target_arch = "riscv64")
+    ))]
+    tls::check_global_pointer();
+    unsafe {
+        write_percpu_reg(tp);
+        #[cfg(feature = "tls-compat")]
+        assert_eq!(
+            tls::probe(),
+            tls_base,
+            "the per-CPU register is also the TLS base register"
+        );
+        #[cfg(all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            not(target_os = "windows")
+        ))]
+        write_self_ptr(tp);
+        CPU_ID.write_current_raw(cpu_id);
+    }
+}
+
+/// Writes the architecture-specific thread pointer register on the current CPU,
+/// without touching the per-CPU data.
+pub(crate) unsafe fn write_percpu_reg(tp: usize) {
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "windows")] {
+            windows::set_thread_pointer(tp);
+        } else if #[cfg(target_arch = "x86_64")] {
+            if cfg!(all(
+                feature = "x86-fsgsbase",
+                any(target_os = "linux", target_os = "none")
+            )) {
+                // Both Linux (>= 5.9) and bare-metal kernels that set `CR4.FSGSBASE` allow
+                // writing `GS_BASE` directly, without a syscall or `wrmsr`.
+                x86::bits64::segmentation::wrgsbase(tp as u64);
+            } else if cfg!(target_os = "linux") {
+                // `FS` is taken by the TLS of the C library and Rust std in hosted mode, so
+                // `GS` is the only segment register we can use.
+                const ARCH_SET_GS: u32 = 0x1001;
+                const SYS_ARCH_PRCTL: u32 = 158;
+                let ret: isize;
+                // `syscall` clobbers `rcx` and `r11`.
+                core::arch::asm!(
+                    "syscall",
+                    inlateout("rax") SYS_ARCH_PRCTL as isize => ret,
+                    in("rdi") ARCH_SET_GS,
+                    in("rsi") tp,
+                    lateout("rcx") _,
+                    lateout("r11") _,
+                    options(nostack),
+                );
+                assert!(ret == 0, "arch_prctl(ARCH_SET_GS) failed: {}", ret);
+            } else if cfg!(target_os = "freebsd") {
+                // Likewise, `FS` is taken by the TLS on FreeBSD, and `GS_BASE` is set by
+                // `sysarch(AMD64_SET_GSBASE, &tp)`, which returns the error number (and sets
+                // the carry flag) on failure.
+                const AMD64_SET_GSBASE: u32 = 131;
+                const SYS_SYSARCH: u32 = 165;
+                let ret: isize;
+                core::arch::asm!(
+                    "syscall",
+                    inlateout("rax") SYS_SYSARCH as isize => ret,
+                    in("rdi") AMD64_SET_GSBASE,
+                    in("rsi") &tp as *const usize,
+                    lateout("rcx") _,
+                    lateout("r11") _,
+                    options(nostack),
+                );
+                assert!(ret == 0, "sysarch(AMD64_SET_GSBASE) failed: {}", ret);
+            } else if cfg!(target_os = "none") {
+                x86::msr::wrmsr(x86::msr::IA32_GS_BASE, tp as u64);
+            } else {
+                unimplemented!()
+            }
+        } else if #[cfg(target_arch = "x86")] {
+            if cfg!(target_os = "none") {
+                x86_32::set_gs_base(tp);
+            } else {
+                unimplemented!()
+            }
+        } else if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+            core::arch::asm!(concat!("mv ", crate::__percpu_asm_rv_reg!(), ", {}"), in(reg) tp);
+        } else if #[cfg(all(target_arch = "aarch64", feature = "arm-el2-runtime", not(feature = "arm-el3")))] {
+            if crate::aarch64::is_el2() {
                 core::arch::asm!("msr TPIDR_EL2, {}", in(reg) tp)
-            } else if #[cfg(target_arch = "loongarch64")] {
-                core::arch::asm!("move $r21, {}", in(reg) tp)
+            } else {
+                core::arch::asm!("msr TPIDR_EL1, {}", in(reg) tp)
             }
+        } else if #[cfg(target_arch = "aarch64")] {
+            core::arch::asm!(concat!("msr ", crate::__percpu_asm_tpidr!(), ", {}"), in(reg) tp)
+        } else if #[cfg(target_arch = "arm")] {
+            core::arch::asm!("mcr p15, 0, {}, c13, c0, 4", in(reg) tp) // TPIDRPRW
+        } else if #[cfg(target_arch = "loongarch64")] {
+            core::arch::asm!("move $r21, {}", in(reg) tp)
         }
     }
 }
@@ -128,8 +1075,291 @@ pub fn set_local_thread_pointer(cpu_id: usize) {
 #[allow(unused_imports)]
 use crate as percpu;
 
+/// The ID of the CPU that the per-CPU data area belongs to.
+#[percpu_macros::def_percpu]
+static CPU_ID: usize = 0;
+
+/// The magic value written by [`set_local_thread_pointer`] to mark the per-CPU
+/// data area as initialized on the current CPU, with the `debug-init-check`
+/// feature. It is zero-initialized, so it is cleared again by [`reset_area`].
+#[cfg(feature = "debug-init-check")]
+#[percpu_macros::def_percpu]
+static INIT_MAGIC: u32 = 0;
+
+#[cfg(feature = "debug-init-check")]
+const INIT_MAGIC_VALUE: u32 = 0x5043_5055; // "PCPU"
+
+/// Reads the architecture-specific thread pointer register on the current CPU,
+/// without accessing the per-CPU data, which may not be initialized yet.
+pub(crate) fn read_percpu_reg() -> usize {
+    cfg_if::cfg_if! {
+        if #[cfg(all(target_arch = "x86_64", target_os = "linux"))] {
+            if cfg!(feature = "x86-fsgsbase") {
+                unsafe { x86::bits64::segmentation::rdgsbase() as usize }
+            } else {
+                const ARCH_GET_GS: u32 = 0x1004;
+                const SYS_ARCH_PRCTL: u32 = 158;
+                let mut tp: usize = 0;
+                unsafe {
+                    core::arch::asm!(
+                        "syscall",
+                        inlateout("rax") SYS_ARCH_PRCTL as isize => _,
+                        in("rdi") ARCH_GET_GS,
+                        in("rsi") &mut tp as *mut usize,
+                        lateout("rcx") _,
+                        lateout("r11") _,
+                        options(nostack),
+                    );
+                }
+                tp
+            }
+        } else if #[cfg(all(target_arch = "x86_64", target_os = "freebsd"))] {
+            const AMD64_GET_GSBASE: u32 = 130;
+            const SYS_SYSARCH: u32 = 165;
+            let mut tp: usize = 0;
+            unsafe {
+                core::arch::asm!(
+                    "syscall",
+                    inlateout("rax") SYS_SYSARCH as isize => _,
+                    in("rdi") AMD64_GET_GSBASE,
+                    in("rsi") &mut tp as *mut usize,
+                    lateout("rcx") _,
+                    lateout("r11") _,
+                    options(nostack),
+                );
+            }
+            tp
+        } else if #[cfg(all(target_arch = "x86", not(target_os = "windows")))] {
+            // The base of `GS` can only be read through `GS` itself.
+            let tp: usize;
+            unsafe { core::arch::asm!("mov {0}, gs:[{1}]", out(reg) tp, in(reg) SELF_PTR.offset()) };
+            tp
+        } else {
+            get_local_thread_pointer()
+        }
+    }
+}
+
+/// Panics if the per-CPU data area is not initialized on the current CPU, i.e.,
+/// [`set_local_thread_pointer`] has not been called on it since [`init`] (or
+/// since [`reset_area`]).
+#[cfg(feature = "debug-init-check")]
+#[doc(hidden)]
+#[track_caller]
+pub fn assert_init() {
+    let tp = read_percpu_reg();
+    let initialized = tp != 0
+        && is_init()
+        && unsafe { ((tp + INIT_MAGIC.offset()) as *const u32).read_volatile() }
+            == INIT_MAGIC_VALUE;
+    assert!(initialized, "percpu not initialized on this CPU");
+}
+
 /// On x86, we use `gs:SELF_PTR` to store the address of the per-CPU data area base.
-#[cfg(target_arch = "x86_64")]
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 #[no_mangle]
 #[percpu_macros::def_percpu]
 static SELF_PTR: usize = 0;
+
+#[cfg(target_arch = "x86")]
+pub use x86_32::set_gs_selector;
+
+/// Writes `SELF_PTR` on the current CPU, right after `GS_BASE` is set to `tp`.
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    not(target_os = "windows")
+))]
+unsafe fn write_self_ptr(tp: usize) {
+    if cfg!(feature = "pic") {
+        // The generated accessors read `SELF_PTR` to locate the per-CPU data
+        // with the `pic` feature, so it must be written `gs`-relatively.
+        core::arch::asm!("mov gs:[{0}], {1}", in(reg) SELF_PTR.offset(), in(reg) tp);
+    } else {
+        SELF_PTR.write_current_raw(tp);
+    }
+}
+
+/// The position-independent helpers used by the accessors generated with the
+/// `pic` feature, and by the offsets calculated with the `asm-free-offset`
+/// feature.
+///
+/// The `.percpu` section is not necessarily linked at address 0, since offsets
+/// are calculated by subtracting the address of `_percpu_load_start`.
+#[cfg(all(
+    any(feature = "pic", feature = "asm-free-offset"),
+    not(target_os = "windows")
+))]
+pub(crate) mod pic {
+    /// Returns the address of `_percpu_load_start`, i.e., the start of the
+    /// `.percpu` section.
+    pub fn load_start() -> usize {
+        extern "C" {
+            fn _percpu_load_start();
+        }
+        _percpu_load_start as *const () as usize
+    }
+
+    /// Returns the per-CPU data area base on the current CPU.
+    #[cfg(feature = "pic")]
+    pub fn thread_pointer() -> usize {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_arch = "x86", target_arch = "x86_64"))] {
+                let tp: usize;
+                unsafe {
+                    core::arch::asm!("mov {0}, gs:[{1}]", out(reg) tp, in(reg) super::SELF_PTR.offset())
+                };
+                tp
+            } else {
+                super::get_local_thread_pointer()
+            }
+        }
+    }
+}
+
+/// `GS` (or `TPIDR_EL0` on AArch64) is taken by the thread environment block on
+/// Windows, so the per-CPU data area base is stored in a thread-local variable
+/// instead. There is no linker script either, so the per-CPU data is collected
+/// in the grouped sections `.percpu$b` (zero-initialized) and `.percpu$d`,
+/// which are merged into `.percpu` in the order of their names, between the
+/// markers defined here.
+#[cfg(target_os = "windows")]
+pub(crate) mod windows {
+    use core::cell::Cell;
+
+    /// A zero-sized marker, aligned so that the per-CPU data area starts at
+    /// the maximum alignment of per-CPU data.
+    #[repr(C, align(64))]
+    struct Marker;
+
+    #[used]
+    #[link_section = ".percpu$a"]
+    static LOAD_START: Marker = Marker;
+
+    #[used]
+    #[link_section = ".percpu$c"]
+    static BSS_END: Marker = Marker;
+
+    #[used]
+    #[link_section = ".percpu$e"]
+    static LOAD_END: Marker = Marker;
+
+    std::thread_local! {
+        static THREAD_POINTER: Cell<usize> = const { Cell::new(0) };
+    }
+
+    pub fn load_start() -> usize {
+        &LOAD_START as *const Marker as usize
+    }
+
+    pub fn bss_end() -> usize {
+        &BSS_END as *const Marker as usize
+    }
+
+    pub fn load_end() -> usize {
+        &LOAD_END as *const Marker as usize
+    }
+
+    pub fn thread_pointer() -> usize {
+        THREAD_POINTER.with(Cell::get)
+    }
+
+    pub fn set_thread_pointer(tp: usize) {
+        THREAD_POINTER.with(|cell| cell.set(tp));
+    }
+}
+
+/// Checks of the `tls-compat` feature, run by [`set_local_thread_pointer`]
+/// before and after writing the per-CPU register.
+#[cfg(feature = "tls-compat")]
+mod tls {
+    #[cfg(elf_tls)]
+    #[thread_local]
+    static TLS_PROBE: u8 = 0;
+
+    /// Returns the address of a thread-local variable, i.e., the TLS base plus
+    /// a constant, which changes if the per-CPU register is the TLS base
+    /// register (e.g., `tp` on RISC-V, or `TPIDR_EL1` on AArch64 with the
+    /// `tpidr-el1` target feature). Returns `None` if the target has no ELF
+    /// TLS.
+    #[inline(never)]
+    pub fn probe() -> Option<usize> {
+        // Not to reuse the TLS base read before writing the per-CPU register.
+        #[cfg(elf_tls)]
+        return Some(core::hint::black_box(core::ptr::addr_of!(TLS_PROBE)) as usize);
+        #[cfg(not(elf_tls))]
+        None
+    }
+
+    /// Panics if `gp` holds `__global_pointer$`, i.e., the code relies on the
+    /// linker relaxation based on it, which breaks once `gp` is overwritten.
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    pub fn check_global_pointer() {
+        let gp: usize;
+        let global_pointer: usize;
+        // The address is `0` if the linker script does not define it. The load
+        // itself must not be relaxed to be relative to `gp`.
+        unsafe {
+            core::arch::asm!(
+                ".weak __global_pointer$",
+                ".option push",
+                ".option norelax",
+                "la {1}, __global_pointer$",
+                ".option pop",
+                "mv {0}, gp",
+                out(reg) gp,
+                out(reg) global_pointer,
+                options(nomem, nostack),
+            );
+        }
+        assert!(
+            global_pointer == 0 || gp != global_pointer,
+            "`gp` holds `__global_pointer$` for the linker relaxation, which can not coexist with TLS in `tp`"
+        );
+    }
+}
+
+/// 32-bit x86 has no `GS_BASE` MSR, so the base of `GS` is set through a
+/// segment descriptor in the GDT.
+#[cfg(target_arch = "x86")]
+mod x86_32 {
+    use core::sync::atomic::{AtomicU16, Ordering};
+
+    static GS_SELECTOR: AtomicU16 = AtomicU16::new(0);
+
+    /// Sets the segment selector to load into `GS` by
+    /// [`set_local_thread_pointer`](super::set_local_thread_pointer) on 32-bit
+    /// x86.
+    ///
+    /// The selector must refer to a writable data segment descriptor in the
+    /// GDT. Its base address will be overwritten with the per-CPU data area
+    /// base, so each CPU must have its own GDT (or at least its own copy of
+    /// this descriptor).
+    pub fn set_gs_selector(selector: u16) {
+        GS_SELECTOR.store(selector, Ordering::Relaxed);
+    }
+
+    /// Writes `base` into the descriptor selected by the GS selector in the
+    /// current GDT, then reloads `GS` to make it take effect.
+    pub(super) unsafe fn set_gs_base(base: usize) {
+        let selector = GS_SELECTOR.load(Ordering::Relaxed);
+        assert!(
+            selector & !0x7 != 0,
+            "GS selector is not set, call `percpu::set_gs_selector` first"
+        );
+
+        // GDTR: 16-bit limit followed by 32-bit base.
+        let mut gdtr = [0u16; 3];
+        core::arch::asm!("sgdt [{}]", in(reg) gdtr.as_mut_ptr(), options(nostack));
+        let gdt_base = gdtr[1] as usize | ((gdtr[2] as usize) << 16);
+
+        // Base address is in bits 16..40 and 56..64 of the descriptor.
+        let desc = (gdt_base + (selector & !0x7) as usize) as *mut u64;
+        let base = base as u64;
+        let mut val = desc.read_volatile();
+        val &= !0xff00_00ff_ffff_0000;
+        val |= ((base & 0xff_ffff) << 16) | ((base >> 24) << 56);
+        desc.write_volatile(val);
+
+        core::arch::asm!("mov gs, {0:x}", in(reg) selector, options(nostack, preserves_flags));
+    }
+}
@@ -0,0 +1,101 @@
+/// The information of a per-CPU static variable, which is recorded by
+/// [`def_percpu`](crate::def_percpu) in the `percpu_layout` section.
+pub struct PercpuVarInfo {
+    name: &'static str,
+    type_name: &'static str,
+    size: usize,
+    offset: fn() -> usize,
+}
+
+impl PercpuVarInfo {
+    #[doc(hidden)]
+    pub const fn new(
+        name: &'static str,
+        type_name: &'static str,
+        size: usize,
+        offset: fn() -> usize,
+    ) -> Self {
+        Self {
+            name,
+            type_name,
+            size,
+            offset,
+        }
+    }
+
+    /// Returns the name of the per-CPU static variable.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the type of the per-CPU static variable, as written in its
+    /// definition.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// Returns the size of the per-CPU static variable in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the offset of the per-CPU static variable relative to the
+    /// per-CPU data area base.
+    pub fn offset(&self) -> usize {
+        (self.offset)()
+    }
+}
+
+impl core::fmt::Debug for PercpuVarInfo {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PercpuVarInfo")
+            .field("name", &self.name)
+            .field("type_name", &self.type_name)
+            .field("size", &self.size)
+            .field("offset", &self.offset())
+            .finish()
+    }
+}
+
+/// Returns the per-CPU variable information table, i.e., the `percpu_layout`
+/// section.
+#[cfg(not(any(target_os = "windows", target_family = "wasm", miri)))]
+fn info_table() -> &'static [PercpuVarInfo] {
+    // The linker defines `__start_<section>` and `__stop_<section>` for
+    // sections whose names are valid C identifiers.
+    #[cfg(not(target_os = "macos"))]
+    extern "C" {
+        fn __start_percpu_layout();
+        fn __stop_percpu_layout();
+    }
+    // The linker of macOS defines `section$start$<segment>$<section>` and
+    // `section$end$<segment>$<section>` instead.
+    #[cfg(target_os = "macos")]
+    extern "C" {
+        #[link_name = "\u{1}section$start$__DATA$__percpu_layout"]
+        fn __start_percpu_layout();
+        #[link_name = "\u{1}section$end$__DATA$__percpu_layout"]
+        fn __stop_percpu_layout();
+    }
+    let start = __start_percpu_layout as *const () as usize;
+    let end = __stop_percpu_layout as *const () as usize;
+    let len = (end - start) / core::mem::size_of::<PercpuVarInfo>();
+    unsafe { core::slice::from_raw_parts(start as *const PercpuVarInfo, len) }
+}
+
+/// The per-CPU variable information table is not collected on Windows and WebAssembly, and can not
+/// be found by Miri.
+#[cfg(any(target_os = "windows", target_family = "wasm", miri))]
+fn info_table() -> &'static [PercpuVarInfo] {
+    &[]
+}
+
+/// Returns an iterator over all per-CPU static variables defined by
+/// [`def_percpu`](crate::def_percpu), in no particular order.
+///
+/// It is useful for debuggers, panic dumps, and auditing the layout of the
+/// per-CPU data area.
+#[doc(cfg(feature = "introspect"))]
+pub fn layout() -> impl Iterator<Item = &'static PercpuVarInfo> {
+    info_table().iter()
+}
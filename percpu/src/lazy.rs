@@ -0,0 +1,149 @@
+use core::cell::{Cell, UnsafeCell};
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut};
+
+const UNINIT: u8 = 0;
+const RUNNING: u8 = 1;
+const INITED: u8 = 2;
+
+/// A per-CPU value which is initialized on the first access on each CPU.
+///
+/// It is used as the storage of per-CPU static variables defined with
+/// `#[def_percpu(lazy)]`, whose initialization expression does not need to be
+/// `const`. It can also be used directly as the type of a per-CPU static
+/// variable.
+///
+/// It dereferences to the value on the current CPU, and runs the initializer
+/// first if the value has not been initialized on that CPU. The per-CPU data
+/// is only accessed by shared references (e.g., by `current()`), so a value
+/// that changes after initialization needs interior mutability, like atomics.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// #[percpu::def_percpu(lazy)]
+/// static RUN_QUEUE: Vec<usize> = Vec::with_capacity(16);
+///
+/// percpu::init(4);
+/// percpu::set_local_thread_pointer(0);
+///
+/// assert_eq!(RUN_QUEUE.current().capacity(), 16);
+/// ```
+pub struct PerCpuLazy<T> {
+    state: Cell<u8>,
+    value: UnsafeCell<MaybeUninit<T>>,
+    init_fn: fn() -> T,
+}
+
+impl<T> PerCpuLazy<T> {
+    /// Creates a new lazy value with the given initializer.
+    pub const fn new(init_fn: fn() -> T) -> Self {
+        Self {
+            state: Cell::new(UNINIT),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+            init_fn,
+        }
+    }
+
+    /// Returns whether the value has been initialized.
+    #[inline]
+    pub fn is_init(&self) -> bool {
+        self.state.get() == INITED
+    }
+
+    /// Returns the reference of the value, or `None` if it has not been
+    /// initialized.
+    #[inline]
+    pub fn get(&self) -> Option<&T> {
+        if self.is_init() {
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the mutable reference of the value, or `None` if it has not
+    /// been initialized.
+    #[inline]
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if self.is_init() {
+            Some(unsafe { self.value.get_mut().assume_init_mut() })
+        } else {
+            None
+        }
+    }
+
+    /// Initializes the value with `f` instead of the initializer given in
+    /// [`new`](Self::new), and returns the reference of it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value has already been initialized, or is being
+    /// initialized (i.e., `f` accesses this value recursively).
+    pub fn init<F: FnOnce() -> T>(&self, f: F) -> &T {
+        match self.state.get() {
+            UNINIT => {}
+            RUNNING => panic!("per-CPU lazy value is initialized recursively"),
+            _ => panic!("per-CPU lazy value is already initialized"),
+        }
+        self.state.set(RUNNING);
+        let value = f();
+        unsafe { (*self.value.get()).write(value) };
+        self.state.set(INITED);
+        self.get().unwrap()
+    }
+
+    /// Returns the reference of the value, initializing it with the
+    /// initializer first if needed.
+    #[inline]
+    pub fn force(&self) -> &T {
+        match self.get() {
+            Some(value) => value,
+            None => self.init(self.init_fn),
+        }
+    }
+
+    /// Returns the mutable reference of the value, initializing it with the
+    /// initializer first if needed.
+    #[inline]
+    pub fn force_mut(&mut self) -> &mut T {
+        self.force();
+        self.get_mut().unwrap()
+    }
+}
+
+impl<T> Deref for PerCpuLazy<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.force()
+    }
+}
+
+impl<T> DerefMut for PerCpuLazy<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        self.force_mut()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for PerCpuLazy<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_tuple("PerCpuLazy");
+        match self.get() {
+            Some(value) => d.field(value),
+            None => d.field(&format_args!("<uninit>")),
+        };
+        d.finish()
+    }
+}
+
+impl<T> Drop for PerCpuLazy<T> {
+    fn drop(&mut self) {
+        if self.is_init() {
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
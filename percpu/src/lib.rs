@@ -1,23 +1,169 @@
 #![cfg_attr(target_os = "none", no_std)]
 #![feature(doc_cfg)]
+#![cfg_attr(
+    all(feature = "tls-compat", elf_tls, not(percpu_naive)),
+    feature(thread_local)
+)]
+#![cfg_attr(not(any(percpu_naive, target_os = "windows")), feature(linkage))]
 #![doc = include_str!("../README.md")]
 
 extern crate percpu_macros;
 
-#[cfg_attr(feature = "sp-naive", path = "naive.rs")]
+// There is no usable thread pointer register on macOS, so the per-CPU data is
+// just global variables like the "sp-naive" feature. So is it under Miri, which
+// can not interpret inline assembly, and on architectures without a supported
+// thread pointer register. The build script sets `percpu_naive` in all of these
+// cases.
+#[cfg_attr(percpu_naive, path = "naive.rs")]
 mod imp;
 
+#[cfg(all(
+    target_arch = "aarch64",
+    feature = "arm-el2-runtime",
+    not(feature = "arm-el3"),
+    not(percpu_naive)
+))]
+pub mod aarch64;
+mod access;
+#[cfg(feature = "alternatives")]
+mod alternatives;
+#[cfg(not(any(percpu_naive, target_os = "windows")))]
+mod asm;
+#[cfg(feature = "bench-cycles")]
+#[doc(cfg(feature = "bench-cycles"))]
+pub mod bench;
+mod builtin;
+mod callback;
+#[cfg(any(feature = "debug-preempt-check", feature = "debug-borrow-check"))]
+mod check;
+mod counter;
+mod cpu_id;
+mod debug;
+mod dtor;
+#[cfg(not(percpu_naive))]
+mod dump;
+mod epoch;
+mod error;
+mod flag;
+mod guard;
+#[cfg(feature = "introspect")]
+mod layout;
+mod lazy;
+pub mod linker;
+mod once;
+mod park;
+#[cfg(feature = "preempt-if")]
+mod preempt;
+#[cfg(feature = "profile")]
+mod profile;
+mod ptr;
+mod refcell;
+mod refcount;
+#[cfg(not(percpu_naive))]
+mod reg;
+#[cfg(not(percpu_naive))]
+mod region;
+mod rwlock;
+mod storage;
+mod subsection;
+mod sync;
+mod workqueue;
+#[cfg(all(target_arch = "x86_64", not(any(percpu_naive, target_os = "windows"))))]
+pub mod x86;
+
+pub use self::access::{PerCpu, PerCpuMut};
+#[cfg(feature = "alternatives")]
+pub use self::alternatives::apply_alternatives;
+pub use self::callback::{
+    cpu_init, cpu_online, is_cpu_online, online_cpus, register_cpu_init, wait_for_cpus,
+    MAX_CPU_INIT_CALLBACKS,
+};
+#[cfg(feature = "debug-preempt-check")]
+pub use self::check::set_preempt_check_hook;
+pub use self::counter::{PercpuCounter, PercpuCounterBatched, DEFAULT_COUNTER_BATCH};
+pub use self::cpu_id::{CpuId, MAX_CPUS};
+pub use self::dtor::deinit;
+#[cfg(not(percpu_naive))]
+pub use self::dump::dump_area;
+pub use self::epoch::PercpuEpoch;
+pub use self::error::PercpuError;
+pub use self::flag::PercpuFlag;
+pub use self::guard::{PerCpuRef, PerCpuRefMut};
 pub use self::imp::*;
-pub use percpu_macros::def_percpu;
+#[cfg(feature = "introspect")]
+pub use self::layout::{layout, PercpuVarInfo};
+pub use self::lazy::PerCpuLazy;
+pub use self::once::PerCpuOnce;
+pub use self::park::{with_all_cpus_parked, CpuParker, ParkedCpus};
+#[cfg(feature = "preempt-if")]
+pub use self::preempt::PreemptGuardIf;
+#[cfg(feature = "profile")]
+pub use self::profile::{profile_report, PercpuProfile};
+pub use self::ptr::PerCpuPtr;
+pub use self::refcell::{PerCpuBorrow, PerCpuBorrowMut, PerCpuRefCell};
+pub use self::refcount::PercpuRef;
+#[cfg(not(percpu_naive))]
+pub use self::reg::{scoped_reg_save, swap_percpu_reg, PercpuRegGuard};
+#[cfg(not(percpu_naive))]
+pub use self::region::{PercpuRegion, PercpuRegionBase};
+pub use self::rwlock::PercpuRwLock;
+pub use self::subsection::{percpu_area_range_of, percpu_area_size_of};
+pub use self::workqueue::{PercpuWorkQueue, WORK_QUEUE_CAPACITY};
+pub use percpu_macros::{def_percpu, def_percpu_group};
 
 #[doc(hidden)]
 pub mod __priv {
+    pub use crate::builtin::{assert_builtin, Builtin};
+    pub use crate::counter::PercpuCounterBatchedShared;
+    pub use crate::debug::DebugRemote;
+    pub use crate::dtor::PercpuDtor;
+    pub use crate::epoch::synchronize as epoch_synchronize;
+    pub use crate::refcount::PercpuRefShared;
+    pub use crate::rwlock::PercpuRwLockShared;
+    pub use crate::storage::PercpuStorage;
+    pub use crate::subsection::PercpuSubsectionVar;
+
     #[cfg(feature = "preempt")]
     pub use kernel_guard::NoPreempt as NoPreemptGuard;
+
+    #[cfg(all(feature = "preempt-if", not(feature = "preempt")))]
+    pub use crate::preempt::NoPreempt as NoPreemptGuard;
+
+    #[cfg(feature = "irq")]
+    pub use kernel_guard::IrqSave as IrqSaveGuard;
+
+    #[cfg(feature = "debug-preempt-check")]
+    pub use crate::check::assert_preempt_disabled;
+
+    #[cfg(feature = "debug-borrow-check")]
+    pub use crate::check::BorrowGuard;
+
+    #[cfg(feature = "debug-init-check")]
+    pub use crate::imp::assert_init;
+
+    #[cfg(feature = "profile")]
+    pub use crate::profile::profile_hit;
+
+    #[cfg(all(feature = "sp-naive", not(target_os = "none")))]
+    pub use std::thread_local;
+
+    #[cfg(all(target_os = "windows", not(percpu_naive)))]
+    pub use crate::imp::windows::{load_start as percpu_section_start, thread_pointer};
+
+    #[cfg(all(
+        any(feature = "pic", feature = "asm-free-offset"),
+        not(any(percpu_naive, target_os = "windows"))
+    ))]
+    pub use crate::imp::pic::load_start as percpu_section_start;
+
+    #[cfg(all(feature = "pic", not(any(percpu_naive, target_os = "windows"))))]
+    pub use crate::imp::pic::thread_pointer;
 }
 
 cfg_if::cfg_if! {
     if #[cfg(doc)] {
+        use crate as percpu;
+
         /// Example per-CPU data for documentation only.
         #[doc(cfg(doc))]
         #[def_percpu]
@@ -0,0 +1,162 @@
+//! Helpers to generate the linker script for the per-CPU data.
+//!
+//! Instead of copying the snippet in the crate documentation, a kernel can
+//! generate it in the build script, and include the generated file in its
+//! linker script (e.g., by `INCLUDE` in GNU ld):
+//!
+//! ```rust,no_run
+//! // build.rs
+//! let out_dir = std::env::var("OUT_DIR").unwrap();
+//! let fragment = percpu::linker::PercpuSection::new(4).to_string();
+//! std::fs::write(format!("{out_dir}/percpu.ld"), fragment).unwrap();
+//! ```
+
+use core::fmt;
+
+/// The `.percpu` output section definition of the linker script, with the
+/// `_percpu_start`, `_percpu_end`, `_percpu_load_start`, `_percpu_bss_end` and
+/// `_percpu_load_end` symbols, and the assertion that the per-CPU data does not
+/// exceed the limit of the offset encoding.
+///
+/// It is rendered by the [`Display`](fmt::Display) implementation, and should
+/// be placed in the `SECTIONS` command. The reserved region is large enough
+/// for `cpu_num` CPUs.
+#[derive(Debug, Clone, Copy)]
+pub struct PercpuSection {
+    cpu_num: usize,
+    align: usize,
+    stack: usize,
+    subsections: &'static [&'static str],
+}
+
+impl PercpuSection {
+    /// Creates the section definition that reserves per-CPU data areas for
+    /// `cpu_num` CPUs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cpu_num` is zero.
+    pub const fn new(cpu_num: usize) -> Self {
+        assert!(cpu_num > 0, "the number of CPUs must be positive");
+        Self {
+            cpu_num,
+            align: 64,
+            stack: 0,
+            subsections: &[],
+        }
+    }
+
+    /// Creates the section definition that reserves per-CPU data areas for
+    /// [`MAX_CPUS`](crate::MAX_CPUS) CPUs, i.e., the `PERCPU_MAX_CPUS`
+    /// environment variable at build time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `PERCPU_MAX_CPUS` is not set.
+    pub const fn for_max_cpus() -> Self {
+        match crate::cpu_id::MAX_CPUS_LIMIT {
+            Some(max_cpus) => Self::new(max_cpus),
+            None => panic!("PERCPU_MAX_CPUS is not set"),
+        }
+    }
+
+    /// Reserves the per-CPU data areas aligned to `align` bytes instead of 64
+    /// bytes, which should be the same as the one passed to
+    /// [`set_percpu_area_align`](crate::set_percpu_area_align).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a power of two, or if it is less than 64.
+    pub const fn with_align(self, align: usize) -> Self {
+        assert!(
+            align.is_power_of_two() && align >= 64,
+            "invalid per-CPU data area alignment"
+        );
+        Self { align, ..self }
+    }
+
+    /// Reserves a stack of `size` bytes and a gap of `guard_size` bytes in each
+    /// per-CPU data area, which should be the same as the ones passed to
+    /// [`set_percpu_stack_size`](crate::set_percpu_stack_size).
+    pub const fn with_stack(self, size: usize, guard_size: usize) -> Self {
+        Self {
+            stack: size + guard_size,
+            ..self
+        }
+    }
+
+    /// Places each of the given user-named subsections (e.g., `".percpu.vm"`,
+    /// see `#[def_percpu(section = "...")]`) on its own, aligned to the
+    /// alignment of the per-CPU data areas on both ends, so that it can be
+    /// managed separately, e.g., unmapped page by page if the areas are
+    /// aligned to pages by [`with_align`](Self::with_align).
+    pub const fn with_subsections(self, subsections: &'static [&'static str]) -> Self {
+        Self {
+            subsections,
+            ..self
+        }
+    }
+
+    /// Returns the number of CPUs to reserve per-CPU data areas for.
+    pub const fn cpu_num(&self) -> usize {
+        self.cpu_num
+    }
+
+    /// Returns the alignment of the per-CPU data areas.
+    pub const fn align(&self) -> usize {
+        self.align
+    }
+}
+
+impl fmt::Display for PercpuSection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, ". = ALIGN(4K);")?;
+        writeln!(f, "_percpu_start = .;")?;
+        writeln!(f, ".percpu 0x0 (NOLOAD) : AT(_percpu_start) {{")?;
+        writeln!(f, "    _percpu_load_start = .;")?;
+        writeln!(f, "    *(SORT_BY_ALIGNMENT(.percpu.bss.hot*))")?;
+        writeln!(
+            f,
+            "    *(SORT_BY_ALIGNMENT(.percpu.bss) SORT_BY_ALIGNMENT(.percpu.bss.align*))"
+        )?;
+        writeln!(f, "    . = ALIGN(64);")?;
+        writeln!(f, "    *(SORT_BY_ALIGNMENT(.percpu.bss.read_mostly*))")?;
+        writeln!(f, "    . = ALIGN(64);")?;
+        writeln!(f, "    *(SORT_BY_ALIGNMENT(.percpu.bss*))")?;
+        writeln!(f, "    _percpu_bss_end = .;")?;
+        writeln!(f, "    *(SORT_BY_ALIGNMENT(.percpu.hot*))")?;
+        writeln!(
+            f,
+            "    *(SORT_BY_ALIGNMENT(.percpu) SORT_BY_ALIGNMENT(.percpu.align*))"
+        )?;
+        writeln!(f, "    . = ALIGN(64);")?;
+        writeln!(f, "    *(SORT_BY_ALIGNMENT(.percpu.read_mostly*))")?;
+        writeln!(f, "    . = ALIGN(64);")?;
+        for subsection in self.subsections {
+            writeln!(f, "    . = ALIGN({});", self.align)?;
+            writeln!(f, "    *({subsection})")?;
+            writeln!(f, "    . = ALIGN({});", self.align)?;
+        }
+        writeln!(f, "    *(SORT_BY_ALIGNMENT(.percpu*))")?;
+        writeln!(f, "    _percpu_load_end = .;")?;
+        if cfg!(feature = "debug-canary") {
+            // Room for the canary words after each area.
+            writeln!(f, "    . = ALIGN(8) + 16;")?;
+        }
+        if self.stack > 0 {
+            writeln!(f, "    . += {:#x};", self.stack)?;
+        }
+        writeln!(
+            f,
+            "    . = _percpu_load_start + ALIGN({}) * {};",
+            self.align, self.cpu_num
+        )?;
+        writeln!(f, "}}")?;
+        writeln!(f, ". = _percpu_start + SIZEOF(.percpu);")?;
+        writeln!(f, "_percpu_end = .;")?;
+        writeln!(
+            f,
+            "ASSERT(_percpu_load_end - _percpu_load_start < 0x80000000, \"per-CPU data exceeds 2 GiB\")"
+        )
+    }
+}
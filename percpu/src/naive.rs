@@ -1,16 +1,133 @@
 /// No effect for "sp-naive" use.
 pub fn init(_max_cpu_num: usize) {}
 
+/// No effect for "sp-naive" use, always returns `Ok(())`.
+pub fn try_init(_max_cpu_num: usize) -> Result<(), crate::PercpuError> {
+    Ok(())
+}
+
+/// No effect for "sp-naive" use.
+pub fn init_with(_num_cpus: usize) {}
+
+/// No effect for "sp-naive" use, `guard` is never called.
+pub fn init_with_guard_pages<F>(_max_cpu_num: usize, _page_size: usize, _guard: F)
+where
+    F: FnMut(usize),
+{
+}
+
+/// No effect for "sp-naive" use, always returns `1`.
+///
+/// # Safety
+///
+/// Always safe for "sp-naive" use.
+pub unsafe fn init_with_base(_base: usize, _size: usize) -> usize {
+    1
+}
+
+/// No effect for "sp-naive" use.
+pub fn init_with_table(_table: &'static [core::sync::atomic::AtomicUsize]) {}
+
+/// No effect for "sp-naive" use.
+///
+/// # Safety
+///
+/// Always safe for "sp-naive" use.
+pub unsafe fn register_area(_cpu_id: impl Into<crate::CpuId>, _base: usize) {}
+
+/// No effect for "sp-naive" use.
+///
+/// # Safety
+///
+/// Always safe for "sp-naive" use.
+pub unsafe fn init_area(_cpu_id: impl Into<crate::CpuId>) {}
+
+/// No effect for "sp-naive" use.
+///
+/// # Safety
+///
+/// Always safe for "sp-naive" use.
+pub unsafe fn reset_area(_cpu_id: impl Into<crate::CpuId>) {}
+
+/// Always returns `0` for "sp-naive" use.
+pub fn init_area_size_for(_num_cpus: usize) -> usize {
+    0
+}
+
 /// Always returns `0` for "sp-naive" use.
 pub fn get_local_thread_pointer() -> usize {
     0
 }
 
+/// Always returns `0` for "sp-naive" use.
+pub fn current_cpu_id() -> usize {
+    0
+}
+
 /// No effect for "sp-naive" use.
-pub fn set_local_thread_pointer(_cpu_id: usize) {}
+pub fn set_local_thread_pointer(_cpu_id: impl Into<crate::CpuId>) {}
+
+/// Always returns `1` for "sp-naive" use.
+pub fn percpu_area_num() -> usize {
+    1
+}
+
+/// Always returns `true` for "sp-naive" use.
+pub fn is_init() -> bool {
+    true
+}
+
+/// No effect for "sp-naive" use.
+///
+/// # Safety
+///
+/// Always safe for "sp-naive" use.
+#[cfg(feature = "test-util")]
+pub unsafe fn reinit_for_test() {}
 
 /// Returns the base address of the per-CPU data area on the given CPU.
 /// Always returns `0` for "sp-naive" use.
-pub fn percpu_area_base(_cpu_id: usize) -> usize {
+pub fn percpu_area_base(_cpu_id: impl Into<crate::CpuId>) -> usize {
+    0
+}
+
+/// Always returns `Ok(0)` for "sp-naive" use.
+pub fn try_percpu_area_base(_cpu_id: impl Into<crate::CpuId>) -> Result<usize, crate::PercpuError> {
+    Ok(0)
+}
+
+/// No effect for "sp-naive" use.
+pub fn set_base_offset(_delta: usize) {}
+
+/// Always returns `0` for "sp-naive" use.
+pub fn percpu_area_stride() -> usize {
+    0
+}
+
+/// No effect for "sp-naive" use.
+pub fn set_percpu_area_align(_align: usize) {}
+
+/// No effect for "sp-naive" use.
+pub fn set_percpu_stack_size(_size: usize, _guard_size: usize) {}
+
+/// Always returns `0` for "sp-naive" use.
+pub fn stack_top(_cpu_id: impl Into<crate::CpuId>, _size: usize) -> usize {
     0
 }
+
+/// Always returns `0` for "sp-naive" use.
+pub fn base_offset() -> usize {
+    0
+}
+
+/// No check for "sp-naive" use, the per-CPU data is always accessible.
+#[cfg(feature = "debug-init-check")]
+#[doc(hidden)]
+#[inline]
+pub fn assert_init() {}
+
+/// Always returns `None` for "sp-naive" use.
+#[cfg(feature = "debug-canary")]
+pub fn check_canaries() -> Option<usize> {
+    None
+}
@@ -0,0 +1,150 @@
+use core::cell::{Cell, UnsafeCell};
+use core::fmt;
+use core::mem::MaybeUninit;
+
+const UNSET: u8 = 0;
+const RUNNING: u8 = 1;
+const SET: u8 = 2;
+
+/// A per-CPU value which can be written only once on each CPU, like a
+/// `OnceCell` per CPU.
+///
+/// It must be used as the type of a per-CPU static variable defined by
+/// [`def_percpu`](crate::def_percpu). It is suitable for per-CPU resources
+/// which are set up exactly once on each CPU during bring-up, e.g., timers or
+/// idle tasks. Unlike [`PerCpuLazy`](crate::PerCpuLazy), there is no default
+/// initializer, so the value stays unset until it is set explicitly.
+///
+/// The following methods are generated in the wrapper struct:
+///
+/// - `set_current(val)`: sets the value on the current CPU, returns `Err(val)`
+///   if it has already been set.
+/// - `get_current()`: returns the reference of the value on the current CPU,
+///   or `None` if it has not been set.
+/// - `get_or_init_current(f)`: returns the reference of the value on the
+///   current CPU, setting it with `f` first if needed.
+/// - `is_set_current()`: returns whether the value on the current CPU has been
+///   set.
+///
+/// The accessors that hand out `&mut PerCpuOnce<T>` (e.g., `with_current`
+/// and `replace_current`) are not generated for it, so the value can not be
+/// changed or dropped after it is set, except by the unsafe
+/// [`deinit`](crate::deinit). Thus the references are not guarded. The value
+/// type must be [`Sync`] to get the references, since they may be used on
+/// another CPU if the caller is migrated.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use percpu::PerCpuOnce;
+///
+/// #[percpu::def_percpu]
+/// static IDLE_TASK_ID: PerCpuOnce<usize> = PerCpuOnce::new();
+///
+/// percpu::init(4);
+/// percpu::set_local_thread_pointer(0);
+///
+/// assert_eq!(IDLE_TASK_ID.get_current(), None);
+/// assert_eq!(IDLE_TASK_ID.set_current(1), Ok(()));
+/// assert_eq!(IDLE_TASK_ID.set_current(2), Err(2));
+/// assert_eq!(IDLE_TASK_ID.get_current(), Some(&1));
+/// ```
+pub struct PerCpuOnce<T> {
+    state: Cell<u8>,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> PerCpuOnce<T> {
+    /// Creates a new unset value.
+    pub const fn new() -> Self {
+        Self {
+            state: Cell::new(UNSET),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Returns whether the value has been set.
+    #[inline]
+    pub fn is_set(&self) -> bool {
+        self.state.get() == SET
+    }
+
+    /// Returns the reference of the value, or `None` if it has not been set.
+    #[inline]
+    pub fn get(&self) -> Option<&T> {
+        if self.is_set() {
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the mutable reference of the value, or `None` if it has not
+    /// been set.
+    #[inline]
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if self.is_set() {
+            Some(unsafe { self.value.get_mut().assume_init_mut() })
+        } else {
+            None
+        }
+    }
+
+    /// Sets the value, returns `Err(value)` if it has already been set, or is
+    /// being set (e.g., by [`get_or_init`](Self::get_or_init) interrupted on
+    /// the same CPU).
+    pub fn set(&self, value: T) -> Result<(), T> {
+        if self.state.get() != UNSET {
+            return Err(value);
+        }
+        self.state.set(RUNNING);
+        unsafe { (*self.value.get()).write(value) };
+        self.state.set(SET);
+        Ok(())
+    }
+
+    /// Returns the reference of the value, setting it with `f` first if it
+    /// has not been set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is being set (i.e., `f` accesses this value
+    /// recursively).
+    pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+        match self.state.get() {
+            UNSET => {}
+            RUNNING => panic!("per-CPU once value is initialized recursively"),
+            _ => return self.get().unwrap(),
+        }
+        self.state.set(RUNNING);
+        let value = f();
+        unsafe { (*self.value.get()).write(value) };
+        self.state.set(SET);
+        self.get().unwrap()
+    }
+}
+
+impl<T> Default for PerCpuOnce<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for PerCpuOnce<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_tuple("PerCpuOnce");
+        match self.get() {
+            Some(value) => d.field(value),
+            None => d.field(&format_args!("<uninit>")),
+        };
+        d.finish()
+    }
+}
+
+impl<T> Drop for PerCpuOnce<T> {
+    fn drop(&mut self) {
+        if self.is_set() {
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A way to park all CPUs but the current one, e.g., by IPIs that make them
+/// spin with IRQs disabled, which is implemented by the kernel for
+/// [`with_all_cpus_parked`].
+///
+/// # Safety
+///
+/// After [`park_others`](Self::park_others) returns, and until
+/// [`unpark_others`](Self::unpark_others) is called, no other CPU (or thread
+/// in hosted mode) may access any per-CPU data, and the current task must not
+/// be migrated to another CPU.
+pub unsafe trait CpuParker {
+    /// Parks all other CPUs, and returns when they are parked, e.g., halted in
+    /// the debugger or in the suspend path.
+    fn park_others(&mut self);
+
+    /// Resumes the CPUs parked by [`park_others`](Self::park_others).
+    fn unpark_others(&mut self);
+}
+
+/// The proof that all CPUs but the current one are parked, which is only
+/// available in the closure passed to [`with_all_cpus_parked`].
+///
+/// It allows to mutate the per-CPU data of any other CPU safely, by the
+/// `remote_mut(token, cpu_id)` method of per-CPU static variables defined by
+/// [`def_percpu`](crate::def_percpu). Each mutable reference borrows the token
+/// mutably, so at most one of them is alive at a time. Only one token exists
+/// at a time, since [`with_all_cpus_parked`] can not be nested.
+pub struct ParkedCpus {
+    // Not `Send` or `Sync`, since the other CPUs are only parked for the
+    // current one.
+    _marker: PhantomData<*mut ()>,
+}
+
+/// Whether a [`ParkedCpus`] token is alive, to reject the nested
+/// [`with_all_cpus_parked`] calls, which would mint a second token.
+static PARKED: AtomicBool = AtomicBool::new(false);
+
+/// Unparks the other CPUs when dropped, even if the closure panics.
+struct Unpark<'a, P: CpuParker>(&'a mut P);
+
+impl<P: CpuParker> Drop for Unpark<'_, P> {
+    fn drop(&mut self) {
+        self.0.unpark_others();
+        PARKED.store(false, Ordering::Release);
+    }
+}
+
+/// Parks all other CPUs by `parker`, and runs `f` with the [`ParkedCpus`]
+/// token, by which the per-CPU data of all CPUs can be mutated safely, e.g., to
+/// fix up the whole-system state in the debugger or the suspend path. The other
+/// CPUs are resumed after `f` returns.
+///
+/// The per-CPU data of the current CPU can not be mutated by `remote_mut`,
+/// since it may be borrowed by the current task (e.g., in `with_current`),
+/// so it must be accessed by the accessors of the current CPU instead.
+///
+/// # Panics
+///
+/// Panics if it is called in `f` (or by another CPU while the token is
+/// alive), which would allow two mutable references to the same data.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use percpu::{CpuId, CpuParker};
+///
+/// #[percpu::def_percpu]
+/// static RUN_QUEUE_LEN: usize = 0;
+///
+/// struct IpiParker;
+///
+/// unsafe impl CpuParker for IpiParker {
+///     fn park_others(&mut self) {
+///         // Send the park IPI to other CPUs and wait for their ACKs.
+///     }
+///
+///     fn unpark_others(&mut self) {
+///         // Release the parked CPUs.
+///     }
+/// }
+///
+/// percpu::init(4);
+/// percpu::set_local_thread_pointer(0);
+///
+/// percpu::with_all_cpus_parked(&mut IpiParker, |token| {
+///     for cpu in CpuId::all() {
+///         if cpu.get() == percpu::current_cpu_id() {
+///             RUN_QUEUE_LEN.write_current(0);
+///         } else {
+///             *RUN_QUEUE_LEN.remote_mut(token, cpu) = 0;
+///         }
+///     }
+/// });
+/// ```
+pub fn with_all_cpus_parked<P, F, R>(parker: &mut P, f: F) -> R
+where
+    P: CpuParker,
+    F: FnOnce(&mut ParkedCpus) -> R,
+{
+    assert!(
+        !PARKED.swap(true, Ordering::Acquire),
+        "the other CPUs are already parked"
+    );
+    parker.park_others();
+    let _unpark = Unpark(parker);
+    f(&mut ParkedCpus {
+        _marker: PhantomData,
+    })
+}
@@ -0,0 +1,39 @@
+//! Preemption control for per-CPU data accesses without `kernel_guard`.
+
+/// Low-level preemption control used to protect per-CPU data accesses when the `preempt-if` feature is enabled.
+///
+/// The kernel must implement this trait with [`crate_interface::impl_interface`], unless the `preempt` feature is
+/// also enabled, in which case [`kernel_guard::NoPreempt`] is used instead and the implementation is ignored.
+///
+/// [`crate_interface::impl_interface`]: https://docs.rs/crate_interface/latest/crate_interface/attr.impl_interface.html
+/// [`kernel_guard::NoPreempt`]: https://docs.rs/kernel_guard/latest/kernel_guard/struct.NoPreempt.html
+#[crate_interface::def_interface]
+pub trait PreemptGuardIf {
+    /// Disables kernel preemption on the current CPU.
+    fn disable_preempt();
+
+    /// Enables kernel preemption on the current CPU.
+    fn enable_preempt();
+}
+
+/// A RAII guard that disables preemption through [`PreemptGuardIf`], and enables it again when dropped.
+#[cfg(not(feature = "preempt"))]
+pub struct NoPreempt(());
+
+#[cfg(not(feature = "preempt"))]
+impl NoPreempt {
+    /// Creates a new guard and disables preemption.
+    #[inline]
+    pub fn new() -> Self {
+        crate_interface::call_interface!(PreemptGuardIf::disable_preempt);
+        Self(())
+    }
+}
+
+#[cfg(not(feature = "preempt"))]
+impl Drop for NoPreempt {
+    #[inline]
+    fn drop(&mut self) {
+        crate_interface::call_interface!(PreemptGuardIf::enable_preempt);
+    }
+}
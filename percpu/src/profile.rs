@@ -0,0 +1,122 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The hit counters of a per-CPU static variable, which are recorded by
+/// [`def_percpu`](crate::def_percpu) in the `percpu_profile` section.
+///
+/// The counter of each CPU is per-CPU data itself, and is bumped by the
+/// accessors of the current CPU (e.g., `read_current`, `with_current` and
+/// `current_ptr`) with a relaxed atomic add, so it stays in the cache of the
+/// owner CPU.
+pub struct PercpuProfile {
+    name: &'static str,
+    hits_offset: fn() -> usize,
+}
+
+impl PercpuProfile {
+    #[doc(hidden)]
+    pub const fn new(name: &'static str, hits_offset: fn() -> usize) -> Self {
+        Self { name, hits_offset }
+    }
+
+    /// Returns the name of the per-CPU static variable.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the number of accesses of the per-CPU static variable on the
+    /// given CPU.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cpu_id` is not less than [`percpu_area_num()`](crate::percpu_area_num).
+    pub fn hits(&self, cpu_id: impl Into<crate::CpuId>) -> usize {
+        let cpu_id = cpu_id.into().get();
+        self.counter(cpu_id).load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of accesses of the per-CPU static variable on all
+    /// CPUs.
+    pub fn total_hits(&self) -> usize {
+        (0..crate::percpu_area_num()).fold(0, |sum, cpu_id| sum.wrapping_add(self.hits(cpu_id)))
+    }
+
+    /// Clears the hit counters of the per-CPU static variable on all CPUs, e.g.,
+    /// to profile a specific workload.
+    pub fn reset(&self) {
+        for cpu_id in 0..crate::percpu_area_num() {
+            self.counter(cpu_id).store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn counter(&self, cpu_id: usize) -> &AtomicUsize {
+        assert!(
+            cpu_id < crate::percpu_area_num(),
+            "invalid CPU ID: {}",
+            cpu_id
+        );
+        let addr = crate::percpu_area_base(cpu_id) + (self.hits_offset)();
+        unsafe { AtomicUsize::from_ptr(addr as *mut usize) }
+    }
+}
+
+impl core::fmt::Debug for PercpuProfile {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PercpuProfile")
+            .field("name", &self.name)
+            .field("total_hits", &self.total_hits())
+            .finish()
+    }
+}
+
+/// Bumps the hit counter at `offset` of the current CPU, which is called by
+/// the accessors generated by [`def_percpu`](crate::def_percpu).
+#[doc(hidden)]
+#[inline]
+pub fn profile_hit(offset: usize) {
+    let addr = crate::get_local_thread_pointer() + offset;
+    unsafe { AtomicUsize::from_ptr(addr as *mut usize) }.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns the hit counter table, i.e., the `percpu_profile` section.
+#[cfg(not(any(target_os = "windows", target_family = "wasm", miri)))]
+fn profile_table() -> &'static [PercpuProfile] {
+    // The linker defines `__start_<section>` and `__stop_<section>` for
+    // sections whose names are valid C identifiers.
+    #[cfg(not(target_os = "macos"))]
+    extern "C" {
+        fn __start_percpu_profile();
+        fn __stop_percpu_profile();
+    }
+    // The linker of macOS defines `section$start$<segment>$<section>` and
+    // `section$end$<segment>$<section>` instead.
+    #[cfg(target_os = "macos")]
+    extern "C" {
+        #[link_name = "\u{1}section$start$__DATA$__percpu_profile"]
+        fn __start_percpu_profile();
+        #[link_name = "\u{1}section$end$__DATA$__percpu_profile"]
+        fn __stop_percpu_profile();
+    }
+    let start = __start_percpu_profile as *const () as usize;
+    let end = __stop_percpu_profile as *const () as usize;
+    let len = (end - start) / core::mem::size_of::<PercpuProfile>();
+    unsafe { core::slice::from_raw_parts(start as *const PercpuProfile, len) }
+}
+
+/// The hit counter table is not collected on Windows and WebAssembly, and can not
+/// be found by Miri.
+#[cfg(any(target_os = "windows", target_family = "wasm", miri))]
+fn profile_table() -> &'static [PercpuProfile] {
+    &[]
+}
+
+/// Returns an iterator over the hit counters of all per-CPU static variables
+/// defined by [`def_percpu`](crate::def_percpu), in no particular order.
+///
+/// The hottest per-CPU variables are good candidates to be placed together at
+/// the beginning of the area (with `#[def_percpu(hot)]`), or to be aligned to
+/// cache lines if other CPUs also access them. The internal per-CPU data of
+/// this crate is not counted.
+#[doc(cfg(feature = "profile"))]
+pub fn profile_report() -> impl Iterator<Item = &'static PercpuProfile> {
+    profile_table().iter()
+}
@@ -0,0 +1,111 @@
+use core::fmt;
+use core::marker::PhantomData;
+
+/// A handle of a per-CPU slot of type `T`, i.e., the offset of a per-CPU
+/// static variable relative to the per-CPU data area base.
+///
+/// It is returned by the `percpu_ptr()` method of per-CPU static variables
+/// defined by [`def_percpu`](crate::def_percpu). Unlike the generated wrapper
+/// structs, it is a concrete type that only depends on `T`, so generic code
+/// (e.g., schedulers and allocators) can store and pass around per-CPU slots
+/// of the same type, and resolve them on any CPU.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use percpu::PerCpuPtr;
+///
+/// #[percpu::def_percpu]
+/// static NR_RUNNING: usize = 0;
+///
+/// fn inc(slot: PerCpuPtr<usize>) {
+///     // Preemption must be disabled while accessing the current CPU's slot.
+///     unsafe { *slot.resolve_current() += 1 };
+/// }
+///
+/// percpu::init(4);
+/// percpu::set_local_thread_pointer(0);
+///
+/// inc(NR_RUNNING.percpu_ptr());
+/// assert_eq!(NR_RUNNING.read_current(), 1);
+/// ```
+pub struct PerCpuPtr<T> {
+    offset: usize,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> PerCpuPtr<T> {
+    /// Creates a handle from the offset relative to the per-CPU data area
+    /// base.
+    ///
+    /// # Safety
+    ///
+    /// `offset` must be the offset of a per-CPU static variable of type `T`,
+    /// e.g., returned by its `offset()` method.
+    #[inline]
+    pub const unsafe fn from_offset(offset: usize) -> Self {
+        Self {
+            offset,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns the offset relative to the per-CPU data area base.
+    #[inline]
+    pub const fn offset(self) -> usize {
+        self.offset
+    }
+
+    /// Returns the raw pointer of the slot on the given CPU.
+    ///
+    /// Dereferencing it is unsafe, the caller must ensure that data races
+    /// will not happen.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cpu_id` is not less than
+    /// [`percpu_area_num()`](crate::percpu_area_num).
+    #[inline]
+    pub fn resolve_on(self, cpu_id: impl Into<crate::CpuId>) -> *mut T {
+        let cpu_id = cpu_id.into().get();
+        assert!(
+            cpu_id < crate::percpu_area_num(),
+            "invalid CPU ID: {}",
+            cpu_id
+        );
+        (crate::percpu_area_base(cpu_id) + self.offset) as *mut T
+    }
+
+    /// Returns the raw pointer of the slot on the current CPU.
+    ///
+    /// Dereferencing it is unsafe, the caller must ensure that preemption is
+    /// disabled, so that it is still the current CPU's slot when accessed.
+    #[inline]
+    pub fn resolve_current(self) -> *mut T {
+        (crate::get_local_thread_pointer() + self.offset) as *mut T
+    }
+}
+
+impl<T> Clone for PerCpuPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for PerCpuPtr<T> {}
+
+impl<T> PartialEq for PerCpuPtr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.offset == other.offset
+    }
+}
+
+impl<T> Eq for PerCpuPtr<T> {}
+
+impl<T> fmt::Debug for PerCpuPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PerCpuPtr")
+            .field("offset", &format_args!("{:#x}", self.offset))
+            .finish()
+    }
+}
@@ -0,0 +1,235 @@
+use core::cell::{Cell, UnsafeCell};
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+
+#[cfg(feature = "preempt-if")]
+use crate::__priv::NoPreemptGuard;
+
+/// The borrow state of a [`PerCpuRefCell`]: the number of shared borrows, or
+/// `-1` if it is mutably borrowed.
+type BorrowState = isize;
+
+const UNUSED: BorrowState = 0;
+const WRITING: BorrowState = -1;
+
+/// A per-CPU value with dynamically checked borrow rules, like a `RefCell` per
+/// CPU.
+///
+/// It must be used as the type of a per-CPU static variable defined by
+/// [`def_percpu`](crate::def_percpu). It is for subsystems that can not prove
+/// the borrow discipline statically, e.g., where a callee may access the same
+/// per-CPU data again: instead of the undefined behavior of nested
+/// `with_current`, a conflicting borrow panics (or fails with the `try_`
+/// methods). The cost is a borrow counter in each per-CPU data area, which is
+/// updated on every borrow.
+///
+/// The following methods are generated in the wrapper struct:
+///
+/// - `borrow_current()`: returns a guard that dereferences to the value on the
+///   current CPU, panics if it is mutably borrowed.
+/// - `borrow_mut_current()`: returns a guard that mutably dereferences to the
+///   value on the current CPU, panics if it is borrowed.
+/// - `try_borrow_current()` and `try_borrow_mut_current()`: like the above, but
+///   return `None` instead of panicking.
+///
+/// The other accessors of the current CPU (e.g., `current()`, `with_current`
+/// or `replace_current`) are not generated, since they would bypass the borrow
+/// state.
+///
+/// Preemption is disabled until the guards are dropped (if the `preempt` or
+/// `preempt-if` feature is enabled), so a guard always refers to the value of
+/// the CPU it was obtained on.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use percpu::PerCpuRefCell;
+///
+/// #[percpu::def_percpu]
+/// static TIMERS: PerCpuRefCell<[u64; 4]> = PerCpuRefCell::new([0; 4]);
+///
+/// percpu::init(4);
+/// percpu::set_local_thread_pointer(0);
+///
+/// let mut timers = TIMERS.borrow_mut_current();
+/// timers[0] = 100;
+/// assert!(TIMERS.try_borrow_current().is_none());
+/// drop(timers);
+/// assert_eq!(TIMERS.borrow_current()[0], 100);
+/// ```
+pub struct PerCpuRefCell<T> {
+    borrow: Cell<BorrowState>,
+    value: UnsafeCell<T>,
+}
+
+impl<T> PerCpuRefCell<T> {
+    /// Creates a new cell containing `value`.
+    pub const fn new(value: T) -> Self {
+        Self {
+            borrow: Cell::new(UNUSED),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Returns whether the value is mutably borrowed.
+    #[inline]
+    pub fn is_borrowed_mut(&self) -> bool {
+        self.borrow.get() == WRITING
+    }
+
+    /// Returns the number of shared borrows of the value.
+    #[inline]
+    pub fn borrow_count(&self) -> usize {
+        self.borrow.get().max(0) as usize
+    }
+
+    /// Returns the mutable reference of the value, which needs no check since
+    /// the cell is borrowed mutably.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    /// Consumes the cell, returning the value.
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: Default> Default for PerCpuRefCell<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for PerCpuRefCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_tuple("PerCpuRefCell");
+        if self.is_borrowed_mut() {
+            d.field(&format_args!("<borrowed>"));
+        } else {
+            d.field(unsafe { &*self.value.get() });
+        }
+        d.finish()
+    }
+}
+
+/// A shared borrow of a [`PerCpuRefCell`] on the current CPU.
+///
+/// It is returned by the `borrow_current()` method of per-CPU static
+/// variables, and keeps preemption disabled until it is dropped (if the
+/// `preempt` or `preempt-if` feature is enabled).
+pub struct PerCpuBorrow<'a, T> {
+    cell: &'a PerCpuRefCell<T>,
+    #[cfg(feature = "preempt-if")]
+    _guard: NoPreemptGuard,
+    // Must not be sent to other CPUs.
+    _not_send: PhantomData<*const ()>,
+}
+
+/// A mutable borrow of a [`PerCpuRefCell`] on the current CPU.
+///
+/// It is returned by the `borrow_mut_current()` method of per-CPU static
+/// variables, and keeps preemption disabled until it is dropped (if the
+/// `preempt` or `preempt-if` feature is enabled).
+pub struct PerCpuBorrowMut<'a, T> {
+    cell: &'a PerCpuRefCell<T>,
+    #[cfg(feature = "preempt-if")]
+    _guard: NoPreemptGuard,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<'a, T> PerCpuBorrow<'a, T> {
+    /// Disables preemption, then borrows the cell returned by `f`, or returns
+    /// `None` if it is mutably borrowed.
+    ///
+    /// # Safety
+    ///
+    /// `f` must return the cell of the current CPU, which is only accessed on
+    /// that CPU.
+    #[doc(hidden)]
+    #[inline]
+    pub unsafe fn new(f: impl FnOnce() -> &'a PerCpuRefCell<T>) -> Option<Self> {
+        #[cfg(feature = "preempt-if")]
+        let guard = NoPreemptGuard::new();
+        let cell = f();
+        let borrow = cell.borrow.get();
+        if borrow == WRITING {
+            return None;
+        }
+        cell.borrow.set(borrow + 1);
+        Some(Self {
+            cell,
+            #[cfg(feature = "preempt-if")]
+            _guard: guard,
+            _not_send: PhantomData,
+        })
+    }
+}
+
+impl<'a, T> PerCpuBorrowMut<'a, T> {
+    /// Disables preemption, then borrows the cell returned by `f` mutably, or
+    /// returns `None` if it is borrowed.
+    ///
+    /// # Safety
+    ///
+    /// `f` must return the cell of the current CPU, which is only accessed on
+    /// that CPU.
+    #[doc(hidden)]
+    #[inline]
+    pub unsafe fn new(f: impl FnOnce() -> &'a PerCpuRefCell<T>) -> Option<Self> {
+        #[cfg(feature = "preempt-if")]
+        let guard = NoPreemptGuard::new();
+        let cell = f();
+        if cell.borrow.get() != UNUSED {
+            return None;
+        }
+        cell.borrow.set(WRITING);
+        Some(Self {
+            cell,
+            #[cfg(feature = "preempt-if")]
+            _guard: guard,
+            _not_send: PhantomData,
+        })
+    }
+}
+
+impl<T> Deref for PerCpuBorrow<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        unsafe { &*self.cell.value.get() }
+    }
+}
+
+impl<T> Deref for PerCpuBorrowMut<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        unsafe { &*self.cell.value.get() }
+    }
+}
+
+impl<T> DerefMut for PerCpuBorrowMut<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.cell.value.get() }
+    }
+}
+
+impl<T> Drop for PerCpuBorrow<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.cell.borrow.set(self.cell.borrow.get() - 1);
+    }
+}
+
+impl<T> Drop for PerCpuBorrowMut<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.cell.borrow.set(UNUSED);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::sync::{const_fn, fence, spin_loop, AtomicBool, AtomicIsize, AtomicUsize, Ordering};
+
+/// A per-CPU reference counter, like the `percpu_ref` in Linux.
+///
+/// It must be used as the type of a per-CPU static variable defined by
+/// [`def_percpu`](crate::def_percpu). Not to be confused with
+/// [`PerCpuRef`](crate::PerCpuRef), which is a guard of the per-CPU data.
+///
+/// The counter starts in the *per-CPU mode* with one initial reference. In
+/// this mode, acquiring and releasing references only touch the per-CPU data
+/// area of the current CPU. When the object is going to be torn down, the
+/// counter is *killed*: the per-CPU counts are collected into a shared atomic
+/// count, and the initial reference is dropped. After that, all references
+/// are counted in the shared count, and the release of the last reference is
+/// reported to the caller.
+///
+/// The following methods are generated in the wrapper struct:
+///
+/// - `get()`: acquires a reference. The caller must already hold one (e.g.,
+///   the initial reference), just like cloning an [`Arc`].
+/// - `put()`: releases a reference, returns `true` if it was the last one.
+/// - `kill()`: switches to the shared atomic mode and drops the initial
+///   reference, returns `true` if it was the last one.
+/// - `is_killed()`: returns whether the counter has been killed.
+///
+/// [`Arc`]: https://doc.rust-lang.org/alloc/sync/struct.Arc.html
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use percpu::PercpuRef;
+///
+/// #[percpu::def_percpu]
+/// static DEV_REF: PercpuRef = PercpuRef::new();
+///
+/// percpu::init(4);
+/// percpu::set_local_thread_pointer(0);
+///
+/// DEV_REF.get();
+/// assert!(!DEV_REF.kill()); // one reference is still held
+/// assert!(DEV_REF.put()); // the last reference is released
+/// ```
+pub struct PercpuRef {
+    /// References acquired on this CPU minus references released on this
+    /// CPU, in the per-CPU mode.
+    count: AtomicIsize,
+    /// Number of `get`/`put` in progress on this CPU (they may be nested by
+    /// interrupt handlers), which `kill` waits for.
+    busy: AtomicUsize,
+}
+
+impl PercpuRef {
+    const_fn! {
+        /// Creates a new reference counter in the per-CPU mode.
+        pub const fn new() -> Self {
+            Self {
+                count: AtomicIsize::new(0),
+                busy: AtomicUsize::new(0),
+            }
+        }
+    }
+}
+
+impl Default for PercpuRef {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A bias added to the shared count until the per-CPU counts are collected,
+/// so that releasing references in the meantime never drops it to zero.
+const COUNT_BIAS: isize = 1 << (isize::BITS - 2);
+
+/// The state of a [`PercpuRef`] that is shared by all CPUs.
+#[doc(hidden)]
+pub struct PercpuRefShared {
+    /// References counted in the shared atomic mode, including the initial
+    /// reference and the [`COUNT_BIAS`] before the per-CPU counts are
+    /// collected.
+    count: AtomicIsize,
+    killed: AtomicBool,
+}
+
+impl Default for PercpuRefShared {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PercpuRefShared {
+    const_fn! {
+        pub const fn new() -> Self {
+            Self {
+                count: AtomicIsize::new(1 + COUNT_BIAS),
+                killed: AtomicBool::new(false),
+            }
+        }
+    }
+
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::Acquire)
+    }
+
+    /// Acquires a reference. `local` must be the [`PercpuRef`] on the
+    /// current CPU, and preemption must be disabled.
+    pub fn get(&self, local: &PercpuRef) {
+        // Pairs with the fence in `kill`: either we see `killed`, or `kill`
+        // sees we are busy and waits for us.
+        local.busy.fetch_add(1, Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        if !self.killed.load(Ordering::Relaxed) {
+            local.count.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.count.fetch_add(1, Ordering::Relaxed);
+        }
+        local.busy.fetch_sub(1, Ordering::Release);
+    }
+
+    /// Releases a reference, returns `true` if it was the last one. `local`
+    /// must be the [`PercpuRef`] on the current CPU, and preemption must be
+    /// disabled.
+    pub fn put(&self, local: &PercpuRef) -> bool {
+        local.busy.fetch_add(1, Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        let last = if !self.killed.load(Ordering::Relaxed) {
+            // The initial reference is held in the per-CPU mode, so it can
+            // not be the last one.
+            local.count.fetch_sub(1, Ordering::Relaxed);
+            false
+        } else {
+            self.count.fetch_sub(1, Ordering::AcqRel) == 1
+        };
+        local.busy.fetch_sub(1, Ordering::Release);
+        last
+    }
+
+    /// Switches to the shared atomic mode and drops the initial reference,
+    /// returns `true` if it was the last one.
+    ///
+    /// `locals` must yield the [`PercpuRef`]s on all CPUs.
+    pub fn kill<'a>(&self, locals: impl Iterator<Item = &'a PercpuRef>) -> bool {
+        assert!(
+            !self.killed.swap(true, Ordering::Relaxed),
+            "PercpuRef killed twice"
+        );
+        fence(Ordering::SeqCst);
+        let mut sum = 0isize;
+        for local in locals {
+            // Wait for the `get`/`put` that did not see `killed`. The per-CPU
+            // count is never touched after that.
+            while local.busy.load(Ordering::Acquire) != 0 {
+                spin_loop();
+            }
+            sum = sum.wrapping_add(local.count.swap(0, Ordering::Relaxed));
+        }
+        self.count
+            .fetch_add(sum.wrapping_sub(COUNT_BIAS), Ordering::Relaxed);
+        self.count.fetch_sub(1, Ordering::AcqRel) == 1
+    }
+}
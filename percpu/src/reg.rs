@@ -0,0 +1,86 @@
+//! Helpers to switch the per-CPU register, e.g., for vCPU context switches in
+//! hypervisors.
+
+use core::marker::PhantomData;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use crate::imp::{read_percpu_reg, write_percpu_reg};
+
+/// Writes `new` to the architecture-specific thread pointer register on the
+/// current CPU (e.g., `GS_BASE` on x86_64, `TPIDR_EL1` or `TPIDR_EL2` on
+/// AArch64), and returns the old value.
+///
+/// It is used by hypervisors to load the guest's value when entering a guest,
+/// and restore the host's value on VM-exit. Unlike
+/// [`set_local_thread_pointer`](crate::set_local_thread_pointer), the per-CPU
+/// data is neither accessed nor updated. Accesses to per-CPU data are not
+/// reordered across it.
+///
+/// # Safety
+///
+/// Per-CPU data must not be accessed on the current CPU until the register is
+/// restored, unless `new` is the base of a per-CPU data area that has been set
+/// up by [`set_local_thread_pointer`](crate::set_local_thread_pointer) before
+/// (on x86, the area records its own base). Preemption (and IRQs, if they
+/// access per-CPU data) must be disabled meanwhile.
+pub unsafe fn swap_percpu_reg(new: usize) -> usize {
+    compiler_fence(Ordering::SeqCst);
+    let old = read_percpu_reg();
+    write_percpu_reg(new);
+    #[cfg(target_arch = "aarch64")]
+    core::arch::asm!("isb");
+    compiler_fence(Ordering::SeqCst);
+    old
+}
+
+/// Saves the architecture-specific thread pointer register on the current
+/// CPU, and restores it when the returned guard is dropped.
+///
+/// The register can be changed freely (e.g., by [`swap_percpu_reg`]) while
+/// the guard is alive, so that the restore on every exit path can not be
+/// forgotten.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// percpu::init(4);
+/// percpu::set_local_thread_pointer(0);
+///
+/// let host = percpu::get_local_thread_pointer();
+/// {
+///     let _saved = percpu::scoped_reg_save();
+///     // Enter the guest with its own per-CPU register value.
+///     unsafe { percpu::swap_percpu_reg(0x8000_0000) };
+/// }
+/// assert_eq!(percpu::get_local_thread_pointer(), host);
+/// ```
+pub fn scoped_reg_save() -> PercpuRegGuard {
+    PercpuRegGuard {
+        saved: read_percpu_reg(),
+        _not_send: PhantomData,
+    }
+}
+
+/// The guard returned by [`scoped_reg_save`], which restores the saved
+/// per-CPU register value on drop.
+///
+/// It can not be sent to other threads, since the register belongs to the
+/// current CPU.
+#[must_use = "the register is restored when the guard is dropped"]
+pub struct PercpuRegGuard {
+    saved: usize,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl PercpuRegGuard {
+    /// Returns the saved register value.
+    pub fn saved(&self) -> usize {
+        self.saved
+    }
+}
+
+impl Drop for PercpuRegGuard {
+    fn drop(&mut self) {
+        unsafe { swap_percpu_reg(self.saved) };
+    }
+}
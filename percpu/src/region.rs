@@ -0,0 +1,210 @@
+//! Independent sets of per-CPU data areas, e.g., the per-vCPU shadow data of
+//! a hypervisor besides the per-CPU data of the host.
+
+use crate::PerCpu;
+
+/// How a [`PercpuRegion`] finds the per-CPU data area of the current CPU.
+#[doc(cfg(not(feature = "sp-naive")))]
+#[derive(Debug, Clone, Copy)]
+pub enum PercpuRegionBase {
+    /// The area the per-CPU register points to, i.e., the one installed by
+    /// [`set_local_thread_pointer`](crate::set_local_thread_pointer) for the
+    /// global region, or by [`swap_percpu_reg`](crate::swap_percpu_reg) with
+    /// [`PercpuRegion::area_base`] for other regions.
+    Register,
+    /// The area of the index returned by the function, e.g., the ID of the vCPU
+    /// running on the current CPU, without touching the per-CPU register.
+    Index(fn() -> usize),
+}
+
+/// A set of evenly spaced per-CPU data areas, which all have the layout of the
+/// `.percpu` section, so every per-CPU static variable defined by
+/// [`def_percpu`](crate::def_percpu) has a copy in each of them, at the same
+/// offset as in the global per-CPU data areas.
+///
+/// The global per-CPU data areas are one region ([`PercpuRegion::global`]),
+/// and more can be set up in the memory managed by the kernel, e.g., a VMM can
+/// maintain both the per-CPU data of the host and the per-vCPU shadow data with
+/// the same machinery. The region is selected explicitly at access time.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use percpu::{PercpuRegion, PercpuRegionBase};
+///
+/// #[percpu::def_percpu]
+/// static EXITS: usize = 0;
+///
+/// fn current_vcpu_id() -> usize {
+///     0 // e.g., read from the host per-CPU data
+/// }
+///
+/// percpu::init(4);
+/// percpu::set_local_thread_pointer(0);
+///
+/// // Per-vCPU data areas for 8 vCPUs.
+/// let stride = percpu::percpu_area_stride();
+/// let layout = std::alloc::Layout::from_size_align(stride * 8, 64).unwrap();
+/// let start = unsafe { std::alloc::alloc(layout) } as usize;
+/// let vcpus =
+///     unsafe { PercpuRegion::new(start, 8, stride, PercpuRegionBase::Index(current_vcpu_id)) };
+/// vcpus.init();
+///
+/// unsafe { *(vcpus.current_ptr(&EXITS) as *mut usize) += 1 };
+/// assert_eq!(unsafe { *vcpus.remote_ptr(&EXITS, 0) }, 1);
+/// assert_eq!(EXITS.read_current(), 0); // the host per-CPU data is untouched
+/// ```
+#[doc(cfg(not(feature = "sp-naive")))]
+#[derive(Debug, Clone, Copy)]
+pub struct PercpuRegion {
+    start: usize,
+    num: usize,
+    stride: usize,
+    base: PercpuRegionBase,
+}
+
+impl PercpuRegion {
+    /// Returns the region of the global per-CPU data areas, initialized by
+    /// [`init`](crate::init) or its variants, which finds the area of the
+    /// current CPU by the per-CPU register.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the global per-CPU data areas are not initialized, or if they
+    /// are registered in a base table by
+    /// [`init_with_table`](crate::init_with_table), where they are not evenly
+    /// spaced.
+    pub fn global() -> Self {
+        assert!(crate::is_init(), "per-CPU data areas are not initialized");
+        assert!(
+            !crate::imp::has_base_table(),
+            "per-CPU data areas in a base table are not a region"
+        );
+        Self {
+            start: crate::percpu_area_base(0),
+            num: crate::percpu_area_num(),
+            stride: crate::percpu_area_stride(),
+            base: PercpuRegionBase::Register,
+        }
+    }
+
+    /// Creates a region of `num` per-CPU data areas, starting at `start` and
+    /// separated by `stride` bytes, which finds the area of the current CPU by
+    /// `base`.
+    ///
+    /// The areas are not initialized, call [`init`](Self::init) or
+    /// [`init_area`](Self::init_area) before accessing them.
+    ///
+    /// # Safety
+    ///
+    /// The memory `[start, start + num * stride)` must be valid, aligned to 64
+    /// bytes (or the alignment of the per-CPU data areas), and not used for
+    /// anything else while the region is in use.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `stride` is less than [`percpu_area_size`](crate::percpu_area_size),
+    /// or if it is not a multiple of 64 bytes.
+    pub unsafe fn new(start: usize, num: usize, stride: usize, base: PercpuRegionBase) -> Self {
+        assert!(
+            stride >= crate::percpu_area_size() && stride.is_multiple_of(64),
+            "invalid per-CPU data area stride: {:#x}",
+            stride
+        );
+        Self {
+            start,
+            num,
+            stride,
+            base,
+        }
+    }
+
+    /// Returns the base address of the first area.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Returns the number of areas.
+    pub fn num(&self) -> usize {
+        self.num
+    }
+
+    /// Returns the distance between the base addresses of adjacent areas.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Returns the total size of the areas.
+    pub fn size(&self) -> usize {
+        self.num * self.stride
+    }
+
+    /// Returns how the area of the current CPU is found.
+    pub fn base(&self) -> PercpuRegionBase {
+        self.base
+    }
+
+    /// Returns the base address of the area at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not less than [`num`](Self::num).
+    pub fn area_base(&self, index: usize) -> usize {
+        assert!(index < self.num, "invalid area index: {}", index);
+        self.start + index * self.stride
+    }
+
+    /// Returns the base address of the area of the current CPU.
+    pub fn current_area_base(&self) -> usize {
+        match self.base {
+            PercpuRegionBase::Register => crate::get_local_thread_pointer(),
+            PercpuRegionBase::Index(index) => self.area_base(index()),
+        }
+    }
+
+    /// Initializes the area at `index` with the initial per-CPU data, as the
+    /// one of CPU `index`, so that [`current_cpu_id`](crate::current_cpu_id)
+    /// returns `index` while the area is installed in the per-CPU register.
+    ///
+    /// The global per-CPU data areas must be initialized first, since they
+    /// hold the initial per-CPU data in hosted mode. The area of the global
+    /// region that holds the initial per-CPU data is left untouched.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not less than [`num`](Self::num).
+    pub fn init_area(&self, index: usize) {
+        crate::imp::init_area_at(self.area_base(index), index);
+    }
+
+    /// Initializes all areas, like [`init_area`](Self::init_area).
+    pub fn init(&self) {
+        for index in 0..self.num {
+            self.init_area(index);
+        }
+    }
+
+    /// Returns the raw pointer of the per-CPU static variable `var` in the area
+    /// at `index`.
+    ///
+    /// # Safety
+    ///
+    /// Caller must ensure that data races will not happen.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not less than [`num`](Self::num).
+    pub unsafe fn remote_ptr<T>(&self, var: &impl PerCpu<T>, index: usize) -> *const T {
+        (self.area_base(index) + var.offset()) as *const T
+    }
+
+    /// Returns the raw pointer of the per-CPU static variable `var` in the area
+    /// of the current CPU.
+    ///
+    /// # Safety
+    ///
+    /// Caller must ensure that preemption is disabled on the current CPU.
+    pub unsafe fn current_ptr<T>(&self, var: &impl PerCpu<T>) -> *const T {
+        (self.current_area_base() + var.offset()) as *const T
+    }
+}
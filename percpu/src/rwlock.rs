@@ -0,0 +1,145 @@
+use crate::sync::{const_fn, fence, spin_loop, AtomicBool, AtomicIsize, Ordering};
+
+/// A per-CPU reader-writer lock, like the `percpu_rw_semaphore` in Linux.
+///
+/// It must be used as the type of a per-CPU static variable defined by
+/// [`def_percpu`](crate::def_percpu). Readers only update the reader count in
+/// the per-CPU data area of the current CPU, so taking the read lock is cheap
+/// and scalable. Writers close a global gate to keep new readers out, and
+/// then wait for the sum of the reader counts on all CPUs to drop to zero,
+/// so taking the write lock is expensive. It is suitable for data that is
+/// read very frequently but rarely written.
+///
+/// Both readers and writers spin while waiting, and a waiting writer keeps
+/// new readers out.
+///
+/// The following methods are generated in the wrapper struct:
+///
+/// - `read_lock()`, `read_unlock()`: acquires and releases the read lock.
+/// - `write_lock()`, `write_unlock()`: acquires and releases the write lock.
+/// - `read(f)`, `write(f)`: calls `f` with the read (or write) lock held.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use percpu::PercpuRwLock;
+///
+/// #[percpu::def_percpu]
+/// static CONFIG_LOCK: PercpuRwLock = PercpuRwLock::new();
+///
+/// percpu::init(4);
+/// percpu::set_local_thread_pointer(0);
+///
+/// CONFIG_LOCK.read(|| { /* read the configuration */ });
+/// CONFIG_LOCK.write(|| { /* update the configuration */ });
+/// ```
+pub struct PercpuRwLock {
+    /// Read locks acquired on this CPU minus read locks released on this CPU.
+    /// It may be negative if a reader migrates to another CPU.
+    readers: AtomicIsize,
+}
+
+impl PercpuRwLock {
+    const_fn! {
+        /// Creates a new unlocked reader-writer lock.
+        pub const fn new() -> Self {
+            Self {
+                readers: AtomicIsize::new(0),
+            }
+        }
+    }
+}
+
+impl Default for PercpuRwLock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The state of a [`PercpuRwLock`] that is shared by all CPUs.
+#[doc(hidden)]
+pub struct PercpuRwLockShared {
+    /// Whether a writer holds (or is waiting for) the lock.
+    gate: AtomicBool,
+}
+
+impl Default for PercpuRwLockShared {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PercpuRwLockShared {
+    const_fn! {
+        pub const fn new() -> Self {
+            Self {
+                gate: AtomicBool::new(false),
+            }
+        }
+    }
+
+    /// Tries to acquire the read lock, returns `false` if the gate is closed
+    /// by a writer. `local` must be the [`PercpuRwLock`] on the current CPU,
+    /// and preemption must be disabled.
+    pub fn try_read_lock(&self, local: &PercpuRwLock) -> bool {
+        // Pairs with the fence in `write_lock`: either we see the gate
+        // closed, or the writer sees our reader count.
+        local.readers.fetch_add(1, Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        if self.gate.load(Ordering::Acquire) {
+            local.readers.fetch_sub(1, Ordering::Release);
+            false
+        } else {
+            true
+        }
+    }
+
+    /// Waits until the gate is opened.
+    pub fn wait_for_writer(&self) {
+        while self.gate.load(Ordering::Relaxed) {
+            spin_loop();
+        }
+    }
+
+    /// Releases the read lock. `local` must be the [`PercpuRwLock`] on the
+    /// current CPU, and preemption must be disabled.
+    pub fn read_unlock(&self, local: &PercpuRwLock) {
+        local.readers.fetch_sub(1, Ordering::Release);
+    }
+
+    /// Acquires the write lock.
+    ///
+    /// `locals` must return the [`PercpuRwLock`]s on all CPUs.
+    pub fn write_lock<'a, I>(&self, locals: impl Fn() -> I)
+    where
+        I: Iterator<Item = &'a PercpuRwLock>,
+    {
+        while self
+            .gate
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            self.wait_for_writer();
+        }
+        // Pairs with the fence in `try_read_lock`.
+        fence(Ordering::SeqCst);
+        // The sum is never less than the number of readers holding the lock,
+        // even if the reader counts are not read at the same time, since the
+        // count is always increased on a CPU before it is decreased on
+        // another one.
+        loop {
+            let sum = locals().fold(0isize, |sum, local| {
+                sum.wrapping_add(local.readers.load(Ordering::Acquire))
+            });
+            if sum == 0 {
+                break;
+            }
+            spin_loop();
+        }
+    }
+
+    /// Releases the write lock.
+    pub fn write_unlock(&self) {
+        self.gate.store(false, Ordering::Release);
+    }
+}
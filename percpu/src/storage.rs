@@ -0,0 +1,24 @@
+use core::cell::UnsafeCell;
+
+/// The storage of the per-CPU data generated by [`def_percpu`](crate::def_percpu).
+///
+/// The per-CPU data is mutated through the generated accessors, so it needs
+/// interior mutability. It is not a `static mut`, whose references are denied
+/// by the `static_mut_refs` lint.
+///
+/// It has the same layout as `T`, so the address of the inner symbol is the
+/// address of the per-CPU data.
+#[doc(hidden)]
+#[repr(transparent)]
+pub struct PercpuStorage<T>(UnsafeCell<T>);
+
+// SAFETY: The data is never accessed through the storage directly. The
+// generated accessors access the data in the per-CPU data areas instead, and
+// the caller is responsible for avoiding data races between CPUs.
+unsafe impl<T> Sync for PercpuStorage<T> {}
+
+impl<T> PercpuStorage<T> {
+    pub const fn new(value: T) -> Self {
+        Self(UnsafeCell::new(value))
+    }
+}
@@ -0,0 +1,91 @@
+use core::ops::Range;
+
+/// An entry of the subsection table, which records a per-CPU static variable
+/// defined with `#[def_percpu(section = "...")]`.
+///
+/// The entries are generated by [`def_percpu`](crate::def_percpu) in the
+/// `percpu_subsections` section.
+#[doc(hidden)]
+pub struct PercpuSubsectionVar {
+    section: &'static str,
+    size: usize,
+    offset: fn() -> usize,
+}
+
+impl PercpuSubsectionVar {
+    pub const fn new(section: &'static str, size: usize, offset: fn() -> usize) -> Self {
+        Self {
+            section,
+            size,
+            offset,
+        }
+    }
+}
+
+/// Returns the subsection table, i.e., the `percpu_subsections` section.
+#[cfg(not(any(target_os = "windows", target_family = "wasm", miri)))]
+fn subsection_table() -> &'static [PercpuSubsectionVar] {
+    // The linker defines `__start_<section>` and `__stop_<section>` for
+    // sections whose names are valid C identifiers.
+    #[cfg(not(target_os = "macos"))]
+    extern "C" {
+        fn __start_percpu_subsections();
+        fn __stop_percpu_subsections();
+    }
+    // The linker of macOS defines `section$start$<segment>$<section>` and
+    // `section$end$<segment>$<section>` instead.
+    #[cfg(target_os = "macos")]
+    extern "C" {
+        #[link_name = "\u{1}section$start$__DATA$__percpu_subsec"]
+        fn __start_percpu_subsections();
+        #[link_name = "\u{1}section$end$__DATA$__percpu_subsec"]
+        fn __stop_percpu_subsections();
+    }
+    let start = __start_percpu_subsections as *const () as usize;
+    let end = __stop_percpu_subsections as *const () as usize;
+    let len = (end - start) / core::mem::size_of::<PercpuSubsectionVar>();
+    unsafe { core::slice::from_raw_parts(start as *const PercpuSubsectionVar, len) }
+}
+
+/// The subsection table is not collected on Windows and WebAssembly, and can
+/// not be found by Miri.
+#[cfg(any(target_os = "windows", target_family = "wasm", miri))]
+fn subsection_table() -> &'static [PercpuSubsectionVar] {
+    &[]
+}
+
+/// Returns the range of offsets (relative to the per-CPU data area base)
+/// covered by the per-CPU static variables defined with
+/// `#[def_percpu(section = "...")]` in the given subsection, e.g.,
+/// `".percpu.vm"`, or `None` if there is no such variable.
+///
+/// The range is from the first byte of the lowest variable to the last byte of
+/// the highest one. It is only exactly the subsection if the linker script
+/// places the subsection on its own, e.g., with
+/// [`PercpuSection::with_subsections`](crate::linker::PercpuSection::with_subsections),
+/// since the catch-all `.percpu*` pattern may interleave it with other
+/// subsections.
+///
+/// It always returns `None` on Windows, WebAssembly and under Miri, where the
+/// variables are not recorded.
+pub fn percpu_area_range_of(section: &str) -> Option<Range<usize>> {
+    subsection_table()
+        .iter()
+        .filter(|var| var.section == section)
+        .map(|var| {
+            let offset = (var.offset)();
+            offset..offset + var.size
+        })
+        .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end))
+}
+
+/// Returns the size of the given subsection of the per-CPU data area, e.g.,
+/// `".percpu.vm"`, or `0` if there is no per-CPU static variable defined in
+/// it.
+///
+/// Together with [`percpu_area_range_of`], it allows managing the subsection
+/// of each per-CPU data area separately, e.g., unmapping a hypervisor-only
+/// region while running guests.
+pub fn percpu_area_size_of(section: &str) -> usize {
+    percpu_area_range_of(section).map_or(0, |range| range.len())
+}
@@ -0,0 +1,34 @@
+//! Synchronization primitives of the per-CPU utilities (e.g., [`PercpuRef`]),
+//! which are replaced by the ones of [loom] with `--cfg loom`, so that the
+//! memory orderings between local updates and remote reads can be checked
+//! exhaustively.
+//!
+//! [`PercpuRef`]: crate::PercpuRef
+//! [loom]: https://docs.rs/loom
+
+#[cfg(not(loom))]
+pub(crate) use core::{
+    hint::spin_loop,
+    sync::atomic::{fence, AtomicBool, AtomicI64, AtomicIsize, AtomicUsize},
+};
+#[cfg(loom)]
+pub(crate) use loom::{
+    hint::spin_loop,
+    sync::atomic::{fence, AtomicBool, AtomicI64, AtomicIsize, AtomicUsize},
+};
+
+pub(crate) use core::sync::atomic::Ordering;
+
+/// Defines a `const fn`, which is a plain function with `--cfg loom`, since
+/// the atomics of loom can not be created in const contexts.
+macro_rules! const_fn {
+    ($(#[$attr:meta])* $vis:vis const fn $($rest:tt)*) => {
+        #[cfg(not(loom))]
+        $(#[$attr])* $vis const fn $($rest)*
+
+        #[cfg(loom)]
+        $(#[$attr])* $vis fn $($rest)*
+    };
+}
+
+pub(crate) use const_fn;
@@ -0,0 +1,208 @@
+use crate::sync::{const_fn, spin_loop, AtomicUsize, Ordering};
+
+/// The number of works that can be pending in a [`PercpuWorkQueue`] of each
+/// CPU.
+pub const WORK_QUEUE_CAPACITY: usize = 32;
+
+/// A per-CPU queue of deferred works, like the `irq_work` in Linux, e.g., to
+/// run callbacks on a CPU after an IPI, or to defer works from the interrupt
+/// context to the end of the interrupt handler.
+///
+/// It must be used as the type of a per-CPU static variable defined by
+/// [`def_percpu`](crate::def_percpu). A work is a plain `fn()`, which is pushed
+/// to the queue of the current CPU (or any other CPU) without locks, and is run
+/// on the CPU that owns the queue when it drains the queue. Works are run in the
+/// order they are pushed, and everything written before pushing a work is
+/// visible to it.
+///
+/// At most [`WORK_QUEUE_CAPACITY`] works can be pending on each CPU, pushing
+/// more fails and returns the work back.
+///
+/// The following methods are generated in the wrapper struct:
+///
+/// - `push_current(work)`: pushes a work to the queue of the current CPU.
+/// - `push_remote(cpu_id, work)`: pushes a work to the queue of the given CPU,
+///   which should be notified (e.g., by an IPI) to drain it.
+/// - `drain_current()`: runs the works pending on the current CPU, returns the
+///   number of them.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use percpu::PercpuWorkQueue;
+///
+/// #[percpu::def_percpu]
+/// static DEFERRED: PercpuWorkQueue = PercpuWorkQueue::new();
+///
+/// fn flush_tlb() {}
+///
+/// percpu::init(4);
+/// percpu::set_local_thread_pointer(0);
+///
+/// DEFERRED.push_remote(1, flush_tlb).unwrap();
+/// // Send an IPI to CPU 1, whose handler calls `DEFERRED.drain_current()`.
+/// ```
+pub struct PercpuWorkQueue {
+    /// Position of the next work to run.
+    head: AtomicUsize,
+    /// Position of the next work to push.
+    tail: AtomicUsize,
+    slots: [Slot; WORK_QUEUE_CAPACITY],
+}
+
+/// A slot of the queue, which holds the work at positions `i`,
+/// `i + WORK_QUEUE_CAPACITY`, etc.
+struct Slot {
+    /// `lap` if the slot is free for the position in the lap starting at the
+    /// position `lap`, or `lap + 1` if the work at that position is ready. The
+    /// positions wrap around, so the capacity must be a power of two.
+    stamp: AtomicUsize,
+    work: AtomicUsize,
+}
+
+impl Slot {
+    const_fn! {
+        const fn new() -> Self {
+            Self {
+                stamp: AtomicUsize::new(0),
+                work: AtomicUsize::new(0),
+            }
+        }
+    }
+}
+
+#[cfg(not(loom))]
+const fn new_slots() -> [Slot; WORK_QUEUE_CAPACITY] {
+    [const { Slot::new() }; WORK_QUEUE_CAPACITY]
+}
+
+#[cfg(loom)]
+fn new_slots() -> [Slot; WORK_QUEUE_CAPACITY] {
+    core::array::from_fn(|_| Slot::new())
+}
+
+impl PercpuWorkQueue {
+    const_fn! {
+        /// Creates a new empty queue.
+        pub const fn new() -> Self {
+            Self {
+                head: AtomicUsize::new(0),
+                tail: AtomicUsize::new(0),
+                slots: new_slots(),
+            }
+        }
+    }
+
+    /// Pushes `work` to the queue, with release ordering. Returns `Err(work)`
+    /// if the queue is full.
+    ///
+    /// It can be called on any CPU.
+    pub fn push(&self, work: fn()) -> Result<(), fn()> {
+        let mut tail = self.tail.load(Ordering::Relaxed);
+        loop {
+            let (slot, lap) = self.slot(tail);
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            match (stamp.wrapping_sub(lap) as isize).signum() {
+                0 => match self.tail.compare_exchange_weak(
+                    tail,
+                    tail.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        slot.work.store(work as usize, Ordering::Relaxed);
+                        slot.stamp.store(lap.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => tail = current,
+                },
+                // The work of the previous lap has not been run yet.
+                -1 => return Err(work),
+                // Another CPU has pushed to the slot, retry with the new tail.
+                _ => {
+                    spin_loop();
+                    tail = self.tail.load(Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// Pops the earliest work from the queue, with acquire ordering, or returns
+    /// `None` if the queue is empty (or the earliest work is still being
+    /// pushed).
+    ///
+    /// It should only be called on the CPU that owns the queue, but popping
+    /// concurrently (e.g., in a nested interrupt handler) is still safe.
+    pub fn pop(&self) -> Option<fn()> {
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            let (slot, lap) = self.slot(head);
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            match (stamp.wrapping_sub(lap.wrapping_add(1)) as isize).signum() {
+                0 => match self.head.compare_exchange_weak(
+                    head,
+                    head.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let work = slot.work.load(Ordering::Relaxed);
+                        slot.stamp
+                            .store(lap.wrapping_add(WORK_QUEUE_CAPACITY), Ordering::Release);
+                        // SAFETY: only `fn()` pointers are stored by `push`.
+                        return Some(unsafe { core::mem::transmute::<usize, fn()>(work) });
+                    }
+                    Err(current) => head = current,
+                },
+                -1 => return None,
+                // Another pop has taken the work, retry with the new head.
+                _ => head = self.head.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Runs the works in the queue, in the order they are pushed, and returns
+    /// the number of them.
+    ///
+    /// Works pushed during the call (e.g., by the works themselves) are left
+    /// for the next call, so a work that pushes itself again does not run
+    /// forever.
+    pub fn drain(&self) -> usize {
+        let pending = self.len();
+        let mut count = 0;
+        while count < pending {
+            match self.pop() {
+                Some(work) => work(),
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// Returns the number of works in the queue, including the ones still
+    /// being pushed.
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Relaxed);
+        tail.wrapping_sub(head)
+    }
+
+    /// Returns whether the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the slot of the position `pos`, and the first position of its
+    /// lap.
+    fn slot(&self, pos: usize) -> (&Slot, usize) {
+        let index = pos % WORK_QUEUE_CAPACITY;
+        (&self.slots[index], pos - index)
+    }
+}
+
+impl Default for PercpuWorkQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,72 @@
+//! x86_64 specific helpers for kernels that also run user code with its own
+//! `GS` base.
+//!
+//! The per-CPU data area base is kept in `GS_BASE` in the kernel, and the user
+//! `GS` base in `KERNEL_GS_BASE` while in the kernel (they are exchanged by
+//! `swapgs`). Trap entry code must execute `swapgs` if and only if it is
+//! entered from user mode, before accessing any per-CPU data, which is what
+//! [`percpu_swapgs_if_needed!`](crate::percpu_swapgs_if_needed) expands to.
+//!
+//! The per-CPU static variable `SELF_PTR` of this crate holds the per-CPU data
+//! area base of each CPU, and its inner symbol is `__PERCPU_percpu_SELF_PTR`,
+//! so `gs:[__PERCPU_percpu_SELF_PTR]` is the per-CPU data area base after
+//! `swapgs` (see [`percpu_load_base!`](crate::percpu_load_base)). Like
+//! [`percpu_asm_access!`](crate::percpu_asm_access), the symbol is the offset
+//! only without the `pic` feature, where the `.percpu` section is linked at
+//! address 0.
+
+/// Returns whether `GS_BASE` holds the per-CPU data area base of some CPU,
+/// i.e., the per-CPU data is accessible on the current CPU, rather than the
+/// user `GS` base.
+///
+/// It is useful in the paths that may be entered with either `GS` base (e.g.,
+/// NMI or machine check handlers interrupting the kernel entry code), to
+/// decide whether `swapgs` is needed.
+pub fn is_kernel_gs() -> bool {
+    crate::is_init() && crate::imp::is_area_base(crate::imp::read_percpu_reg())
+}
+
+/// Expands to the instruction sequence (as a string literal) that executes
+/// `swapgs` if the trap is taken from user mode, for trap entry (or exit) stubs
+/// in `global_asm!` or `naked_asm!`.
+///
+/// The argument is the offset of the saved `CS` in the trap frame relative to
+/// `rsp`, e.g., `8` at the entry of a trap without an error code, or `16` with
+/// an error code. The local label `2` is used.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// core::arch::global_asm!(
+///     "page_fault_entry:",
+///     percpu::percpu_swapgs_if_needed!(16),
+///     percpu::percpu_load_base!("rax"),
+///     // ...
+/// );
+/// ```
+#[doc(cfg(target_arch = "x86_64"))]
+#[macro_export]
+macro_rules! percpu_swapgs_if_needed {
+    ($cs_offset:literal) => {
+        concat!(
+            "test byte ptr [rsp + ",
+            $cs_offset,
+            "], 3\n",
+            "jz 2f\n",
+            "swapgs\n",
+            "2:\n"
+        )
+    };
+}
+
+/// Expands to the instruction (as a string literal) that loads the per-CPU
+/// data area base of the current CPU (i.e., `gs:[__PERCPU_percpu_SELF_PTR]`)
+/// into the given register, for assembly code in `global_asm!` or
+/// `naked_asm!`.
+#[doc(cfg(target_arch = "x86_64"))]
+#[macro_export]
+macro_rules! percpu_load_base {
+    ($dst:literal) => {
+        concat!("mov ", $dst, ", gs:[offset __PERCPU_percpu_SELF_PTR]\n")
+    };
+}
@@ -6,10 +6,24 @@ SECTIONS
     _percpu_start = .;
     .percpu 0x0 (NOLOAD) : AT(_percpu_start) {
         _percpu_load_start = .;
-        *(.percpu .percpu.*)
+        *(SORT_BY_ALIGNMENT(.percpu.bss.hot*))
+        *(SORT_BY_ALIGNMENT(.percpu.bss) SORT_BY_ALIGNMENT(.percpu.bss.align*))
+        . = ALIGN(64);
+        *(SORT_BY_ALIGNMENT(.percpu.bss.read_mostly*))
+        . = ALIGN(64);
+        *(SORT_BY_ALIGNMENT(.percpu.bss*))
+        _percpu_bss_end = .;
+        *(SORT_BY_ALIGNMENT(.percpu.hot*))
+        *(SORT_BY_ALIGNMENT(.percpu) SORT_BY_ALIGNMENT(.percpu.align*))
+        . = ALIGN(64);
+        *(SORT_BY_ALIGNMENT(.percpu.read_mostly*))
+        . = ALIGN(64);
+        *(SORT_BY_ALIGNMENT(.percpu*))
         _percpu_load_end = .;
         . = _percpu_load_start + ALIGN(64) * CPU_NUM;
     }
     . = _percpu_start + SIZEOF(.percpu);
+    _percpu_end = .;
+    ASSERT(_percpu_load_end - _percpu_load_start < 0x80000000, "per-CPU data exceeds 2 GiB")
 }
 INSERT AFTER .bss;
@@ -0,0 +1,12 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[percpu::def_percpu]
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+fn main() {
+    let count = COUNT.remote(0);
+    COUNT.with_current(|count| *count = AtomicUsize::new(5));
+    drop(COUNT.replace_current(AtomicUsize::new(5)));
+    let _ = unsafe { COUNT.current_mut() };
+    println!("{}", count.load(Ordering::Relaxed));
+}
@@ -0,0 +1,34 @@
+#[percpu::def_percpu(align = 3)]
+static ALIGNED: usize = 0;
+
+#[percpu::def_percpu(eager)]
+static UNKNOWN: usize = 0;
+
+#[percpu::def_percpu(offset_sym = "1ST")]
+static BAD_SYM: usize = 0;
+
+#[percpu::def_percpu(hot, read_mostly)]
+static PLACED: usize = 0;
+
+#[percpu::def_percpu(lazy, export_c)]
+static LAZY_C: usize = 0;
+
+#[percpu::def_percpu(section = ".data")]
+static BAD_SECTION: usize = 0;
+
+#[percpu::def_percpu(hot, section = ".percpu.vm")]
+static HOT_SECTION: usize = 0;
+
+#[percpu::def_percpu(debug, no_remote)]
+static DEBUG_PRIVATE: usize = 0;
+
+#[percpu::def_percpu(no_remote)]
+static PRIVATE_COUNTER: percpu::PercpuCounter = percpu::PercpuCounter::new();
+
+#[percpu::def_percpu(no_preempt_guard)]
+static UNGUARDED: usize = 0;
+
+#[percpu::def_percpu(unsafe(no_remote))]
+static UNSAFE_PRIVATE: usize = 0;
+
+fn main() {}
@@ -0,0 +1,28 @@
+/// A user type with the same name as `percpu::PercpuCounter`.
+struct PercpuCounter(u8);
+
+/// A user type with the same name as `core::num::NonZeroU32`.
+#[derive(Clone, Copy)]
+struct NonZeroU32(u8);
+
+impl NonZeroU32 {
+    unsafe fn new_unchecked(raw: u32) -> Self {
+        Self(raw as u8)
+    }
+
+    fn get(self) -> u32 {
+        self.0 as u32
+    }
+}
+
+#[percpu::def_percpu]
+static COUNTER: PercpuCounter = PercpuCounter(0);
+
+#[percpu::def_percpu]
+static ID: NonZeroU32 = NonZeroU32(1);
+
+fn main() {
+    COUNTER.add_current(0x1_0000);
+    ID.write_current(unsafe { NonZeroU32::new_unchecked(0x1_0000) });
+    assert_eq!(ID.read_current().get(), 0);
+}
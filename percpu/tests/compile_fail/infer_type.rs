@@ -0,0 +1,4 @@
+#[percpu::def_percpu]
+static COUNT: _ = 0usize;
+
+fn main() {}
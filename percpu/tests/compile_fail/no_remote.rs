@@ -0,0 +1,7 @@
+#[percpu::def_percpu(no_remote)]
+static TRAP_SCRATCH: usize = 0;
+
+fn main() {
+    let _ = TRAP_SCRATCH.read_remote(1);
+    let _ = unsafe { TRAP_SCRATCH.remote_ptr(1) };
+}
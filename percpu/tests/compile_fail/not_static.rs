@@ -0,0 +1,4 @@
+#[percpu::def_percpu]
+fn foo() {}
+
+fn main() {}
@@ -0,0 +1,8 @@
+#[percpu::def_percpu]
+static NAMES: Vec<&'static str> = Vec::new();
+
+fn main() {
+    let names = NAMES.current();
+    drop(NAMES.replace_current(vec!["cpu"]));
+    println!("{:?}", *names);
+}
@@ -0,0 +1,14 @@
+use percpu::PerCpuRefCell;
+
+#[percpu::def_percpu]
+static TIMERS: PerCpuRefCell<Vec<u64>> = PerCpuRefCell::new(Vec::new());
+
+fn main() {
+    let timers = TIMERS.borrow_current();
+    TIMERS.with_current(|cell| cell.get_mut().clear());
+    drop(TIMERS.replace_current(PerCpuRefCell::new(Vec::new())));
+    drop(TIMERS.take_current());
+    let _ = TIMERS.current();
+    let _ = unsafe { TIMERS.current_mut() };
+    println!("{:?}", *timers);
+}
@@ -0,0 +1,23 @@
+use percpu::{with_all_cpus_parked, CpuParker};
+
+#[percpu::def_percpu]
+static VALUE: usize = 0;
+
+struct Parker;
+
+unsafe impl CpuParker for Parker {
+    fn park_others(&mut self) {}
+    fn unpark_others(&mut self) {}
+}
+
+fn main() {
+    with_all_cpus_parked(&mut Parker, |token| {
+        let a = VALUE.remote_mut(token, 0);
+        let b = VALUE.remote_mut(token, 1);
+        *a += *b;
+    });
+
+    // The reference can not outlive the parked CPUs.
+    let value = with_all_cpus_parked(&mut Parker, |token| VALUE.remote_mut(token, 1));
+    *value = 1;
+}
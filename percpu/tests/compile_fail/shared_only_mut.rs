@@ -0,0 +1,29 @@
+use percpu::{with_all_cpus_parked, CpuParker, PerCpuOnce};
+
+#[percpu::def_percpu]
+static BUFFER: PerCpuOnce<Vec<u8>> = PerCpuOnce::new();
+
+#[percpu::def_percpu(lazy)]
+static NAMES: Vec<&'static str> = vec!["cpu"];
+
+struct Parker;
+
+unsafe impl CpuParker for Parker {
+    fn park_others(&mut self) {}
+    fn unpark_others(&mut self) {}
+}
+
+fn main() {
+    let buffer = BUFFER.get_or_init_current(|| vec![1, 2, 3]);
+    drop(BUFFER.replace_current(PerCpuOnce::new()));
+    println!("{:?}", buffer);
+
+    let names = NAMES.current();
+    NAMES.with_current(|names| names.clear());
+    println!("{:?}", **names);
+
+    // Not even if the other CPUs are parked.
+    let buffer = BUFFER.get_current().unwrap();
+    with_all_cpus_parked(&mut Parker, |token| BUFFER.remote_mut(token, 0).take());
+    println!("{:?}", buffer);
+}
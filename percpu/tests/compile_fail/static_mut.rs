@@ -0,0 +1,4 @@
+#[percpu::def_percpu]
+static mut COUNT: usize = 0;
+
+fn main() {}
@@ -0,0 +1,12 @@
+#[link_section = ".data"]
+#[percpu::def_percpu]
+static PLACED: usize = 0;
+
+#[no_mangle]
+#[percpu::def_percpu(inner_attrs(export_name = "renamed"))]
+static RENAMED: usize = 0;
+
+#[percpu::def_percpu(inner_attrs(inline))]
+static UNSUPPORTED: usize = 0;
+
+fn main() {}
@@ -0,0 +1,28 @@
+use percpu::{PercpuCounterBatched, PercpuEpoch, PercpuFlag, PercpuRef, PercpuRwLock, PercpuWorkQueue};
+
+#[percpu::def_percpu]
+static READY: PercpuFlag = PercpuFlag::new();
+
+#[percpu::def_percpu]
+static DEFERRED: PercpuWorkQueue = PercpuWorkQueue::new();
+
+#[percpu::def_percpu]
+static EPOCH: PercpuEpoch = PercpuEpoch::new();
+
+#[percpu::def_percpu]
+static REFS: PercpuRef = PercpuRef::new();
+
+#[percpu::def_percpu]
+static LOCK: PercpuRwLock = PercpuRwLock::new();
+
+#[percpu::def_percpu]
+static PAGES: PercpuCounterBatched = PercpuCounterBatched::new();
+
+fn main() {
+    READY.with_current(|flag| *flag = PercpuFlag::new());
+    drop(DEFERRED.replace_current(PercpuWorkQueue::new()));
+    drop(EPOCH.take_current());
+    let _ = unsafe { REFS.current_mut() };
+    LOCK.update_current(|lock| *lock = PercpuRwLock::new());
+    PAGES.with_current(|pages| *pages = PercpuCounterBatched::new());
+}
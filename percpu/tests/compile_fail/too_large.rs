@@ -0,0 +1,4 @@
+#[percpu::def_percpu]
+static BUFFER: [[u8; 0x10000]; 0x10000] = [[0; 0x10000]; 0x10000];
+
+fn main() {}
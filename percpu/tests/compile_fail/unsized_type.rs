@@ -0,0 +1,4 @@
+#[percpu::def_percpu]
+static NAME: str = "cpu";
+
+fn main() {}
@@ -0,0 +1,37 @@
+#![cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+
+use percpu::*;
+
+#[def_percpu]
+static VALUE: usize = 0;
+
+#[test]
+fn test_area_align() {
+    // With the room for the canary words after each area.
+    let size = if cfg!(feature = "debug-canary") {
+        percpu_area_size().next_multiple_of(8) + 16
+    } else {
+        percpu_area_size()
+    };
+    assert_eq!(percpu_area_stride(), size.next_multiple_of(64));
+    assert!(std::panic::catch_unwind(|| set_percpu_area_align(32)).is_err());
+
+    set_percpu_area_align(128);
+    let stride = percpu_area_stride();
+    assert_eq!(stride % 128, 0);
+    assert!(stride >= percpu_area_size());
+    assert_eq!(init_area_size_for(4), stride * 4);
+
+    init(4);
+    assert_eq!(percpu_area_num(), 4);
+    for cpu_id in 0..4 {
+        assert_eq!(
+            percpu_area_base(cpu_id),
+            percpu_area_base(0) + cpu_id * stride
+        );
+    }
+    set_local_thread_pointer(3);
+    VALUE.write_current(3);
+    assert_eq!(VALUE.read_remote(3), 3);
+    assert!(std::panic::catch_unwind(|| set_percpu_area_align(4096)).is_err());
+}
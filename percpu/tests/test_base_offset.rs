@@ -0,0 +1,31 @@
+#![cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+
+use percpu::*;
+
+#[def_percpu]
+static VALUE: usize = 0;
+
+#[test]
+fn test_base_offset() {
+    init(4);
+    let stride = init_area_size_for(1);
+    let base = percpu_area_base(0);
+    VALUE.write_remote(1, 0x1111);
+    VALUE.write_remote(2, 0x2222);
+
+    // Pretend that the per-CPU data areas are mapped one area higher.
+    set_base_offset(stride);
+    assert_eq!(base_offset(), stride);
+    assert_eq!(percpu_area_base(0), base + stride);
+    assert_eq!(VALUE.read_remote(0), 0x1111);
+    assert_eq!(VALUE.read_remote(1), 0x2222);
+
+    set_local_thread_pointer(0);
+    assert_eq!(get_local_thread_pointer(), base + stride);
+    assert_eq!(VALUE.read_current(), 0x1111);
+
+    set_base_offset(0);
+    set_local_thread_pointer(1);
+    assert_eq!(get_local_thread_pointer(), base + stride);
+    assert_eq!(VALUE.read_current(), 0x1111);
+}
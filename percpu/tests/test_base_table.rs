@@ -0,0 +1,42 @@
+#![cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+
+use std::sync::atomic::AtomicUsize;
+
+use percpu::*;
+
+#[def_percpu]
+static VALUE: usize = 0;
+
+static BASE_TABLE: [AtomicUsize; 4] = [const { AtomicUsize::new(0) }; 4];
+
+fn alloc_area() -> usize {
+    let layout = std::alloc::Layout::from_size_align(percpu_area_size().max(1), 64).unwrap();
+    unsafe { std::alloc::alloc(layout) as usize }
+}
+
+#[test]
+fn test_base_table() {
+    init_with_table(&BASE_TABLE);
+    assert!(is_init());
+    assert_eq!(percpu_area_num(), 4);
+
+    let bases = [alloc_area(), alloc_area(), alloc_area()];
+    for (cpu_id, &base) in bases.iter().enumerate() {
+        unsafe { register_area(cpu_id, base) };
+        assert_eq!(percpu_area_base(cpu_id), base);
+    }
+    assert!(std::panic::catch_unwind(|| unsafe { register_area(1, bases[0]) }).is_err());
+    // CPU 3 is not registered yet.
+    assert!(std::panic::catch_unwind(|| percpu_area_base(3)).is_err());
+
+    set_local_thread_pointer(2);
+    assert_eq!(get_local_thread_pointer(), bases[2]);
+    assert_eq!(VALUE.read_current(), 0);
+    VALUE.write_current(0x2222);
+    VALUE.write_remote(1, 0x1111);
+    assert_eq!(VALUE.read_remote(2), 0x2222);
+    assert_eq!(
+        unsafe { VALUE.remote_ptr(1) } as usize,
+        bases[1] + VALUE.offset()
+    );
+}
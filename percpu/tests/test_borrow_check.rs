@@ -0,0 +1,89 @@
+#![cfg(all(target_os = "linux", feature = "debug-borrow-check"))]
+
+use std::panic::catch_unwind;
+use std::sync::Once;
+
+use percpu::*;
+
+#[def_percpu]
+static COUNT: usize = 0;
+
+#[def_percpu]
+static NAMES: [u8; 4] = [0; 4];
+
+#[def_percpu]
+static TICKS: u64 = 0;
+
+fn bump() {
+    COUNT.with_current(|count| *count += 1);
+}
+
+/// Initializes the per-CPU data areas once for all tests, which run in
+/// parallel, and sets the current thread as `cpu_id`.
+fn init_on(cpu_id: usize) {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        #[cfg(not(feature = "sp-naive"))]
+        init(4);
+    });
+    #[cfg(not(feature = "sp-naive"))]
+    set_local_thread_pointer(cpu_id);
+    #[cfg(feature = "sp-naive")]
+    let _ = cpu_id;
+}
+
+#[test]
+fn test_borrow_check() {
+    init_on(0);
+
+    // Sequential and nested accesses to different variables are fine.
+    bump();
+    bump();
+    NAMES.with_current(|names| {
+        bump();
+        names[0] = 1;
+    });
+    assert_eq!(COUNT.read_current(), 3);
+
+    // A nested mutable access to the same variable panics, and the borrow is
+    // released by unwinding.
+    let err = catch_unwind(|| COUNT.with_current(|_| bump())).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<String>().unwrap(),
+        "the per-CPU data `COUNT` is already mutably borrowed on the current CPU"
+    );
+    bump();
+    assert_eq!(COUNT.read_current(), 4);
+
+    // So does a mutable access while the guard of `current_mut` is alive.
+    {
+        let mut names = unsafe { NAMES.current_mut() };
+        names[1] = 2;
+        assert!(catch_unwind(|| NAMES.replace_current([0; 4])).is_err());
+    }
+    assert_eq!(NAMES.replace_current([0; 4]), [1, 2, 0, 0]);
+
+    // The borrow flags are per-CPU.
+    #[cfg(not(feature = "sp-naive"))]
+    COUNT.with_current(|_| {
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                set_local_thread_pointer(1);
+                bump();
+                assert_eq!(COUNT.read_current(), 1);
+            });
+        });
+    });
+}
+
+#[test]
+#[should_panic(expected = "the per-CPU data `TICKS` is already borrowed on the current CPU")]
+fn test_borrow_check_shared() {
+    init_on(2);
+
+    // Shared borrows can coexist, but not with a mutable one.
+    let ticks = unsafe { TICKS.current() };
+    let again = unsafe { TICKS.current() };
+    assert_eq!(*ticks + *again, 0);
+    TICKS.with_current(|ticks| *ticks += 1);
+}
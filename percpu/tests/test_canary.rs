@@ -0,0 +1,31 @@
+#![cfg(all(
+    target_os = "linux",
+    feature = "debug-canary",
+    not(feature = "sp-naive")
+))]
+
+use percpu::*;
+
+#[def_percpu]
+static VALUE: u64 = 0;
+
+#[test]
+fn test_canary() {
+    init(3);
+    set_local_thread_pointer(0);
+    VALUE.write_current(1);
+    assert_eq!(check_canaries(), None);
+
+    // Overflow the area of CPU 1 into its padding.
+    let end = percpu_area_base(1) + percpu_area_size();
+    unsafe { (end.next_multiple_of(8) as *mut u64).write(0) };
+    assert_eq!(check_canaries(), Some(1));
+
+    // Restore the canary words by reinitializing the area.
+    unsafe { init_area(1) };
+    assert_eq!(check_canaries(), None);
+
+    // Underflow the area of CPU 2 into the padding of CPU 1.
+    unsafe { ((percpu_area_base(2) - 8) as *mut u64).write(0) };
+    assert_eq!(check_canaries(), Some(2));
+}
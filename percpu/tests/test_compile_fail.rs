@@ -0,0 +1,7 @@
+#![cfg(target_os = "linux")]
+
+#[test]
+fn test_compile_fail() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/compile_fail/*.rs");
+}
@@ -0,0 +1,33 @@
+#![cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+
+use percpu::*;
+
+#[def_percpu]
+static VALUE: u64 = 0;
+
+#[def_percpu]
+static FLAGS: u8 = 0;
+
+#[test]
+fn test_copy_area() {
+    init(3);
+    set_local_thread_pointer(1);
+    VALUE.write_current(0x1234);
+    FLAGS.write_current(0x5);
+
+    let bytes = area_bytes(1);
+    assert_eq!(bytes.len(), percpu_area_size());
+    assert_eq!(bytes as *mut u8 as usize, percpu_area_base(1));
+    let snapshot = unsafe { (*bytes).to_vec() };
+
+    unsafe { copy_area(1, 2) };
+    assert_eq!(VALUE.read_remote(2), 0x1234);
+    assert_eq!(FLAGS.read_remote(2), 0x5);
+
+    // Restore CPU 1 from the snapshot.
+    VALUE.write_current(0);
+    unsafe { (*bytes).copy_from_slice(&snapshot) };
+    assert_eq!(VALUE.read_current(), 0x1234);
+
+    assert!(std::panic::catch_unwind(|| area_bytes(3)).is_err());
+}
@@ -0,0 +1,45 @@
+#![cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+
+use percpu::*;
+
+#[def_percpu]
+static VALUE: usize = 0;
+
+#[test]
+fn test_cpu_id() {
+    init(4);
+    set_local_thread_pointer(3);
+    assert_eq!(CpuId::current(), CpuId::new(3));
+    assert_eq!(CpuId::try_new(3), Some(CpuId::new(3)));
+    assert_eq!(CpuId::try_new(4), None);
+    assert_eq!(CpuId::new(2).to_string(), "2");
+    assert_eq!(usize::from(CpuId::from(1)), 1);
+
+    let cpus = CpuId::all().collect::<Vec<_>>();
+    assert_eq!(cpus, (0..4).map(CpuId::new).collect::<Vec<_>>());
+    assert_eq!(CpuId::all().len(), 4);
+    assert_eq!(CpuId::all().next_back(), Some(CpuId::new(3)));
+
+    for cpu in CpuId::all() {
+        assert_eq!(percpu_area_base(cpu), percpu_area_base(cpu.get()));
+        assert_eq!(try_percpu_area_base(cpu), Ok(percpu_area_base(cpu)));
+        unsafe { *(VALUE.remote_ptr(cpu) as *mut usize) = cpu.get() * 10 };
+    }
+    assert_eq!(VALUE.read_current(), 30);
+    assert_eq!(VALUE.read_remote(1), 10);
+    assert_eq!(VALUE.read_remote(CpuId::new(2)), 20);
+    VALUE.write_remote(CpuId::new(2), 21);
+    assert_eq!(unsafe { *VALUE.remote_ref_raw(CpuId::new(2)) }, 21);
+
+    // The functions of the per-CPU data areas take `CpuId` as well.
+    unsafe { copy_area(CpuId::new(2), CpuId::new(1)) };
+    assert_eq!(VALUE.read_remote(1), 21);
+    unsafe { init_area(CpuId::new(1)) };
+    assert_eq!(VALUE.read_remote(1), 0);
+    set_local_thread_pointer(CpuId::new(1));
+    assert_eq!(current_cpu_id(), 1);
+    assert_eq!(
+        try_percpu_area_base(CpuId::new(4)),
+        Err(PercpuError::InvalidCpuId(4))
+    );
+}
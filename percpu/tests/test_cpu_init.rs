@@ -0,0 +1,27 @@
+#![cfg(target_os = "linux")]
+
+use percpu::*;
+
+#[def_percpu]
+static VALUE: usize = 0;
+
+#[test]
+fn test_cpu_init() {
+    #[cfg(not(feature = "sp-naive"))]
+    init(4);
+
+    register_cpu_init(|cpu_id| VALUE.write_current(cpu_id + 100));
+    register_cpu_init(|_| VALUE.add_current(1));
+
+    cpu_init(0);
+    assert_eq!(current_cpu_id(), 0);
+    assert_eq!(VALUE.read_current(), 101);
+
+    #[cfg(not(feature = "sp-naive"))]
+    {
+        cpu_init(3);
+        assert_eq!(current_cpu_id(), 3);
+        assert_eq!(VALUE.read_current(), 104);
+        assert_eq!(VALUE.read_remote(0), 101);
+    }
+}
@@ -0,0 +1,42 @@
+#![cfg(target_os = "linux")]
+
+use percpu::*;
+
+#[def_percpu]
+static VALUE: usize = 0;
+
+#[test]
+fn test_cpu_online() {
+    #[cfg(not(feature = "sp-naive"))]
+    init(4);
+
+    register_cpu_init(|cpu_id| VALUE.write_current(cpu_id + 100));
+
+    assert_eq!(online_cpus(), 0);
+    cpu_online(0);
+    assert_eq!(current_cpu_id(), 0);
+    assert!(is_cpu_online(0));
+    assert_eq!(online_cpus(), 1);
+    wait_for_cpus(1);
+
+    // Each thread acts as an application processor.
+    #[cfg(not(feature = "sp-naive"))]
+    {
+        std::thread::scope(|s| {
+            for cpu_id in 1..4 {
+                s.spawn(move || cpu_online(cpu_id));
+            }
+            wait_for_cpus(4);
+            for cpu_id in 0..4 {
+                assert!(is_cpu_online(cpu_id));
+                assert_eq!(VALUE.read_remote(cpu_id), cpu_id + 100);
+            }
+        });
+        assert!(!is_cpu_online(4));
+
+        // CPU 3 goes offline
+        unsafe { reset_area(3) };
+        assert!(!is_cpu_online(3));
+        assert_eq!(online_cpus(), 3);
+    }
+}
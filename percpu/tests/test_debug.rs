@@ -0,0 +1,24 @@
+#![cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+
+use percpu::*;
+
+#[def_percpu(debug)]
+static RUNQ_LEN: usize = 0;
+
+#[def_percpu(debug)]
+static IDLE_TASK: PerCpuOnce<u32> = PerCpuOnce::new();
+
+#[test]
+fn test_debug() {
+    assert_eq!(format!("{:?}", RUNQ_LEN), "{}");
+
+    init(3);
+    set_local_thread_pointer(1);
+    RUNQ_LEN.write_current(2);
+    IDLE_TASK.set_current(7).unwrap();
+    assert_eq!(format!("{:?}", RUNQ_LEN), "{cpu0: 0, cpu1: 2, cpu2: 0}");
+    assert_eq!(
+        format!("{:?}", unsafe { IDLE_TASK.debug_remote() }),
+        "{cpu0: PerCpuOnce(<uninit>), cpu1: PerCpuOnce(7), cpu2: PerCpuOnce(<uninit>)}"
+    );
+}
@@ -0,0 +1,53 @@
+#![cfg(target_os = "linux")]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use percpu::*;
+
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+struct Resource(usize);
+
+impl Drop for Resource {
+    fn drop(&mut self) {
+        DROPPED.fetch_add(self.0, Ordering::Relaxed);
+    }
+}
+
+#[def_percpu]
+static RESOURCE: Option<Resource> = None;
+
+#[def_percpu]
+static VALUE: usize = 0;
+
+#[test]
+fn test_deinit() {
+    #[cfg(not(feature = "sp-naive"))]
+    {
+        init(4);
+        set_local_thread_pointer(0);
+    }
+    VALUE.write_current(1);
+    RESOURCE.with_current(|r| *r = Some(Resource(1)));
+    #[cfg(not(feature = "sp-naive"))]
+    {
+        set_local_thread_pointer(1);
+        RESOURCE.with_current(|r| *r = Some(Resource(10)));
+        unsafe { deinit(1) };
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 10);
+        unsafe { init_area(1) };
+
+        // `reset_area` drops the per-CPU data before resetting it.
+        set_local_thread_pointer(2);
+        RESOURCE.with_current(|r| *r = Some(Resource(100)));
+        unsafe { reset_area(2) };
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 110);
+        assert!(RESOURCE.with_current(|r| r.is_none()));
+        set_local_thread_pointer(0);
+    }
+
+    let dropped = DROPPED.load(Ordering::Relaxed);
+    unsafe { deinit(0) };
+    assert_eq!(DROPPED.load(Ordering::Relaxed), dropped + 1);
+    assert_eq!(VALUE.read_current(), 1); // no need to drop
+}
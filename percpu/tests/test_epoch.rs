@@ -0,0 +1,50 @@
+#![cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use percpu::*;
+
+#[def_percpu]
+static RCU: PercpuEpoch = PercpuEpoch::new();
+
+#[test]
+fn test_epoch() {
+    init(4);
+    cpu_online(0);
+
+    // No other CPU is online.
+    RCU.quiescent_current();
+    RCU.synchronize();
+
+    // Each thread acts as a CPU, CPU 1 passes quiescent points all the time,
+    // while CPU 2 starts after a while.
+    let stop = AtomicBool::new(false);
+    let passed = AtomicBool::new(false);
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            cpu_online(1);
+            while !stop.load(Ordering::Relaxed) {
+                RCU.quiescent_current();
+            }
+            unsafe { reset_area(1) };
+        });
+        s.spawn(|| {
+            cpu_online(2);
+            std::thread::sleep(Duration::from_millis(50));
+            passed.store(true, Ordering::Relaxed);
+            while !stop.load(Ordering::Relaxed) {
+                RCU.quiescent_current();
+            }
+        });
+        wait_for_cpus(3);
+
+        RCU.synchronize();
+        assert!(passed.load(Ordering::Relaxed));
+        stop.store(true, Ordering::Relaxed);
+    });
+
+    // CPU 1 is offline, and CPU 2 does not pass quiescent points anymore.
+    unsafe { reset_area(2) };
+    RCU.synchronize();
+}
@@ -0,0 +1,33 @@
+#![cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+
+use percpu::*;
+
+#[def_percpu]
+static VALUE: usize = 0;
+
+#[test]
+fn test_error() {
+    assert_eq!(try_percpu_area_base(0), Err(PercpuError::NotInitialized));
+    assert!(std::panic::catch_unwind(|| percpu_area_base(0)).is_err());
+
+    assert_eq!(try_init(2), Ok(()));
+    // the areas allocated for 2 CPUs cannot hold more.
+    assert_eq!(try_init(4), Err(PercpuError::AllocFailed));
+    assert_eq!(try_init(2), Ok(()));
+    // `init_with` is the same as `init` in hosted mode.
+    init_with(2);
+    assert_eq!(
+        init_area_size_for(2),
+        percpu_area_base(2) - percpu_area_base(0)
+    );
+    assert_eq!(try_percpu_area_base(1), Ok(percpu_area_base(1)));
+    assert_eq!(try_percpu_area_base(2), Err(PercpuError::InvalidCpuId(2)));
+    assert_eq!(
+        PercpuError::InvalidCpuId(2).to_string(),
+        "invalid CPU ID: 2"
+    );
+
+    set_local_thread_pointer(1);
+    VALUE.write_current(1);
+    assert_eq!(VALUE.read_remote(1), 1);
+}
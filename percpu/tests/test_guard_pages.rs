@@ -0,0 +1,32 @@
+#![cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+
+use percpu::*;
+
+const PAGE_SIZE: usize = 0x1000;
+
+#[def_percpu]
+static VALUE: usize = 0;
+
+#[test]
+fn test_guard_pages() {
+    let mut guards = Vec::new();
+    init_with_guard_pages(3, PAGE_SIZE, |addr| guards.push(addr));
+    assert_eq!(percpu_area_num(), 3);
+
+    let stride = percpu_area_size().next_multiple_of(PAGE_SIZE) + PAGE_SIZE;
+    assert_eq!(init_area_size_for(3), stride * 3);
+    assert_eq!(guards.len(), 3);
+    for (cpu_id, guard) in guards.into_iter().enumerate() {
+        let base = percpu_area_base(cpu_id);
+        assert_eq!(base % PAGE_SIZE, 0);
+        assert_eq!(guard % PAGE_SIZE, 0);
+        // The guard page is between the area and the next one.
+        assert!(guard >= base + percpu_area_size());
+        assert_eq!(guard + PAGE_SIZE, base + stride);
+    }
+
+    set_local_thread_pointer(2);
+    VALUE.write_current(0x2222);
+    assert_eq!(VALUE.read_remote(2), 0x2222);
+    assert_eq!(VALUE.read_remote(1), 0);
+}
@@ -0,0 +1,37 @@
+#![cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+
+use percpu::*;
+
+#[def_percpu]
+static BSS: usize = 0;
+
+#[def_percpu]
+static DATA: usize = 1;
+
+#[test]
+fn test_hotplug() {
+    init(4);
+    set_local_thread_pointer(0);
+    // The initial values are not loaded in hosted mode, the per-CPU data of
+    // CPU 0 is used as the initial per-CPU data instead.
+    DATA.write_current(0x1234);
+
+    set_local_thread_pointer(2);
+    BSS.write_current(0xdead);
+    DATA.write_current(0xbeef);
+
+    // CPU 2 goes offline
+    set_local_thread_pointer(0);
+    unsafe { reset_area(2) };
+    assert_eq!(BSS.read_remote(2), 0);
+    assert_eq!(DATA.read_remote(2), 0x1234);
+
+    // CPU 2 comes back
+    unsafe { init_area(2) };
+    set_local_thread_pointer(2);
+    assert_eq!(current_cpu_id(), 2);
+    assert_eq!(BSS.read_current(), 0);
+    assert_eq!(DATA.read_current(), 0x1234);
+
+    assert!(std::panic::catch_unwind(|| unsafe { init_area(4) }).is_err());
+}
@@ -0,0 +1,27 @@
+#![cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+
+use percpu::*;
+
+#[def_percpu]
+static VALUE: usize = 0;
+
+#[test]
+fn test_init_with_base() {
+    let size = init_area_size_for(3) + 10;
+    let layout = std::alloc::Layout::from_size_align(size, 0x1000).unwrap();
+    let base = unsafe { std::alloc::alloc_zeroed(layout) as usize };
+
+    let num = unsafe { init_with_base(base, size) };
+    assert_eq!(num, 3);
+    assert_eq!(percpu_area_num(), 3);
+    assert_eq!(percpu_area_base(0), base);
+
+    set_local_thread_pointer(2);
+    assert_eq!(get_local_thread_pointer(), percpu_area_base(2));
+    VALUE.write_current(0xdead);
+    assert_eq!(VALUE.read_remote(2), 0xdead);
+    assert_eq!(
+        unsafe { VALUE.remote_ptr(2) } as usize,
+        base + init_area_size_for(2) + VALUE.offset()
+    );
+}
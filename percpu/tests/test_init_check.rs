@@ -0,0 +1,37 @@
+#![cfg(all(
+    target_os = "linux",
+    feature = "debug-init-check",
+    not(feature = "sp-naive")
+))]
+
+use std::panic::catch_unwind;
+
+use percpu::*;
+
+#[def_percpu]
+static VALUE: usize = 0;
+
+fn assert_uninit() {
+    let err = catch_unwind(|| VALUE.read_current()).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<&str>(),
+        Some(&"percpu not initialized on this CPU")
+    );
+}
+
+#[test]
+fn test_init_check() {
+    assert_uninit();
+    init(2);
+    assert_uninit();
+
+    set_local_thread_pointer(1);
+    VALUE.write_current(1);
+    assert_eq!(VALUE.read_current(), 1);
+
+    // The area is no longer initialized after the CPU goes offline.
+    unsafe { reset_area(1) };
+    assert_uninit();
+    set_local_thread_pointer(1);
+    assert_eq!(VALUE.read_current(), 0);
+}
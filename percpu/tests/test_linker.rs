@@ -0,0 +1,44 @@
+use percpu::linker::PercpuSection;
+
+#[test]
+fn test_linker_fragment() {
+    let fragment = PercpuSection::new(4).to_string();
+    println!("{}", fragment);
+    assert!(fragment.contains("    . = _percpu_load_start + ALIGN(64) * 4;\n"));
+
+    // The linker script for testing uses the same section definition.
+    let script = include_str!("../test_percpu.x");
+    let body: String = script
+        .lines()
+        .skip_while(|line| *line != "{")
+        .skip(1)
+        .take_while(|line| *line != "}")
+        .map(|line| format!("{}\n", line.strip_prefix("    ").unwrap_or(line)))
+        .collect();
+    // Except the room for the canary words with the `debug-canary` feature.
+    let fragment = fragment.replace("    . = ALIGN(8) + 16;\n", "");
+    assert_eq!(body.replace("CPU_NUM", "4"), fragment);
+}
+
+#[test]
+fn test_linker_fragment_align() {
+    let fragment = PercpuSection::new(8).with_align(4096).to_string();
+    assert!(fragment.contains("    . = _percpu_load_start + ALIGN(4096) * 8;\n"));
+}
+
+#[test]
+fn test_linker_fragment_subsections() {
+    let fragment = PercpuSection::new(4)
+        .with_align(4096)
+        .with_subsections(&[".percpu.vm"])
+        .to_string();
+    assert!(fragment.contains(
+        "    . = ALIGN(4096);\n    *(.percpu.vm)\n    . = ALIGN(4096);\n    *(SORT_BY_ALIGNMENT(.percpu*))\n"
+    ));
+}
+
+#[test]
+fn test_linker_fragment_stack() {
+    let fragment = PercpuSection::new(4).with_stack(0x4000, 0x1000).to_string();
+    assert!(fragment.contains("    . += 0x5000;\n    . = _percpu_load_start + ALIGN(64) * 4;\n"));
+}
@@ -0,0 +1,218 @@
+//! Checks the memory orderings of the per-CPU utilities with loom, by running
+//! each CPU as a loom thread with its own local state:
+//!
+//! ```sh
+//! RUSTFLAGS="--cfg loom" cargo test --release --test test_loom
+//! ```
+#![cfg(loom)]
+
+use loom::cell::UnsafeCell;
+use loom::sync::Arc;
+use loom::thread;
+
+use percpu::__priv::{PercpuCounterBatchedShared, PercpuRefShared, PercpuRwLockShared};
+use percpu::{
+    PercpuCounterBatched, PercpuEpoch, PercpuFlag, PercpuRef, PercpuRwLock, PercpuWorkQueue,
+};
+
+#[test]
+fn test_counter_batched() {
+    loom::model(|| {
+        let state = Arc::new((
+            PercpuCounterBatchedShared::new(),
+            [PercpuCounterBatched::new(), PercpuCounterBatched::new()],
+        ));
+        state.0.set_batch(2);
+
+        let handles: Vec<_> = (0..2)
+            .map(|cpu_id| {
+                let state = state.clone();
+                thread::spawn(move || {
+                    let (shared, locals) = &*state;
+                    shared.add(&locals[cpu_id], 1);
+                    shared.add(&locals[cpu_id], 1); // flushed
+                    shared.add(&locals[cpu_id], 1);
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let (shared, locals) = &*state;
+        assert_eq!(shared.approx_sum(), 4);
+        assert_eq!(shared.precise_sum(locals.iter()), 6);
+    });
+}
+
+#[test]
+fn test_ref_kill() {
+    loom::model(|| {
+        let state = Arc::new((PercpuRefShared::new(), [PercpuRef::new(), PercpuRef::new()]));
+        let (shared, locals) = &*state;
+        shared.get(&locals[1]);
+
+        // CPU 1 acquires and releases references with the one it holds, while
+        // CPU 0 kills the counter. The last reference must be reported exactly
+        // once.
+        let handle = {
+            let state = state.clone();
+            thread::spawn(move || {
+                let (shared, locals) = &*state;
+                shared.get(&locals[1]);
+                assert!(!shared.put(&locals[1]));
+                shared.put(&locals[1])
+            })
+        };
+        let killed_last = shared.kill(locals.iter());
+        let put_last = handle.join().unwrap();
+
+        assert!(shared.is_killed());
+        assert!(killed_last != put_last);
+    });
+}
+
+#[test]
+fn test_ref_remote_put() {
+    loom::model(|| {
+        let state = Arc::new((PercpuRefShared::new(), [PercpuRef::new(), PercpuRef::new()]));
+        let (shared, locals) = &*state;
+        shared.get(&locals[0]);
+
+        // The reference acquired on CPU 0 is released on CPU 1, while CPU 0
+        // kills the counter.
+        let handle = {
+            let state = state.clone();
+            thread::spawn(move || {
+                let (shared, locals) = &*state;
+                shared.put(&locals[1])
+            })
+        };
+        let killed_last = shared.kill(locals.iter());
+        let put_last = handle.join().unwrap();
+
+        assert!(killed_last != put_last);
+    });
+}
+
+#[test]
+fn test_rwlock() {
+    loom::model(|| {
+        let state = Arc::new((
+            PercpuRwLockShared::new(),
+            [PercpuRwLock::new(), PercpuRwLock::new()],
+            UnsafeCell::new(0usize),
+        ));
+
+        // A reader on CPU 1 must see either none or all of the writes, and
+        // loom reports a data race if the lock does not order them.
+        let handle = {
+            let state = state.clone();
+            thread::spawn(move || {
+                let (shared, locals, data) = &*state;
+                while !shared.try_read_lock(&locals[1]) {
+                    shared.wait_for_writer();
+                }
+                let val = data.with(|ptr| unsafe { *ptr });
+                shared.read_unlock(&locals[1]);
+                val
+            })
+        };
+
+        let (shared, locals, data) = &*state;
+        shared.write_lock(|| locals.iter());
+        data.with_mut(|ptr| unsafe { *ptr += 2 });
+        shared.write_unlock();
+
+        let val = handle.join().unwrap();
+        assert!(val == 0 || val == 2);
+        assert_eq!(data.with(|ptr| unsafe { *ptr }), 2);
+    });
+}
+
+#[test]
+fn test_flag() {
+    loom::model(|| {
+        let state = Arc::new((
+            [PercpuFlag::new(), PercpuFlag::new()],
+            UnsafeCell::new(0usize),
+        ));
+
+        // The data written by CPU 1 before setting its flag must be visible to
+        // CPU 0 after seeing the flag set.
+        let handle = {
+            let state = state.clone();
+            thread::spawn(move || {
+                let (flags, data) = &*state;
+                data.with_mut(|ptr| unsafe { *ptr = 1 });
+                flags[1].set();
+            })
+        };
+
+        let (flags, data) = &*state;
+        flags[1].wait();
+        assert_eq!(data.with(|ptr| unsafe { *ptr }), 1);
+        handle.join().unwrap();
+    });
+}
+
+#[test]
+fn test_work_queue() {
+    fn work0() {}
+    fn work1() {}
+
+    loom::model(|| {
+        let state = Arc::new((PercpuWorkQueue::new(), UnsafeCell::new(0usize)));
+
+        // CPU 1 publishes the data with a work pushed to the queue of CPU 0,
+        // while CPU 0 pushes its own work and drains the queue.
+        let handle = {
+            let state = state.clone();
+            thread::spawn(move || {
+                let (queue, data) = &*state;
+                data.with_mut(|ptr| unsafe { *ptr = 1 });
+                queue.push(work1).unwrap();
+            })
+        };
+
+        let (queue, data) = &*state;
+        queue.push(work0).unwrap();
+        let mut popped = Vec::new();
+        while popped.len() < 2 {
+            match queue.pop() {
+                Some(work) => popped.push(work as usize),
+                None => thread::yield_now(),
+            }
+        }
+        assert!(popped.contains(&(work0 as usize)) && popped.contains(&(work1 as usize)));
+        assert_eq!(data.with(|ptr| unsafe { *ptr }), 1);
+        assert!(queue.is_empty());
+        handle.join().unwrap();
+    });
+}
+
+#[test]
+fn test_epoch() {
+    loom::model(|| {
+        let state = Arc::new((PercpuEpoch::new(), UnsafeCell::new(0usize)));
+
+        // CPU 1 reads the data before its quiescent point, so CPU 0 can free
+        // (write) the data after seeing the new epoch, and loom reports a data
+        // race if the epoch does not order them.
+        let handle = {
+            let state = state.clone();
+            thread::spawn(move || {
+                let (epoch, data) = &*state;
+                data.with(|ptr| unsafe { *ptr });
+                epoch.quiescent();
+            })
+        };
+
+        let (epoch, data) = &*state;
+        while epoch.get() == 0 {
+            thread::yield_now();
+        }
+        data.with_mut(|ptr| unsafe { *ptr = 1 });
+        handle.join().unwrap();
+    });
+}
@@ -0,0 +1,35 @@
+#![cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+
+use percpu::*;
+
+#[def_percpu]
+static VALUE: usize = 0;
+
+#[test]
+fn test_max_cpus() {
+    // The build script passes the validated value, which is empty if it is not set.
+    let Some(max_cpus) = option_env!("PERCPU_MAX_CPUS").filter(|val| !val.is_empty()) else {
+        assert_eq!(MAX_CPUS, usize::MAX);
+        assert!(std::panic::catch_unwind(linker::PercpuSection::for_max_cpus).is_err());
+        return;
+    };
+    let max_cpus = max_cpus.parse::<usize>().unwrap();
+    assert_eq!(MAX_CPUS, max_cpus);
+    assert_eq!(linker::PercpuSection::for_max_cpus().cpu_num(), max_cpus);
+
+    assert_eq!(
+        try_init(max_cpus + 1),
+        Err(PercpuError::TooManyCpus(max_cpus + 1))
+    );
+    assert!(!is_init());
+    assert_eq!(try_init(max_cpus), Ok(()));
+    assert_eq!(
+        try_percpu_area_base(max_cpus),
+        Err(PercpuError::InvalidCpuId(max_cpus))
+    );
+    assert!(std::panic::catch_unwind(|| percpu_area_base(max_cpus)).is_err());
+
+    set_local_thread_pointer(max_cpus - 1);
+    VALUE.write_current(1);
+    assert_eq!(VALUE.read_remote(max_cpus - 1), 1);
+}
@@ -0,0 +1,66 @@
+#![cfg(miri)]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use percpu::*;
+
+// The per-CPU data is just global variables under Miri, like the "sp-naive" feature.
+// Initial value is unsupported for testing.
+
+#[def_percpu]
+static BOOL: bool = false;
+
+#[def_percpu]
+static U32: u32 = 0;
+
+#[def_percpu]
+static USIZE: usize = 0;
+
+#[def_percpu]
+static SLOTS: [u64; 4] = [0; 4];
+
+struct Struct {
+    foo: usize,
+    bar: u8,
+}
+
+#[def_percpu]
+static STRUCT: Struct = Struct { foo: 0, bar: 0 };
+
+#[def_percpu]
+static ATOMIC: AtomicUsize = AtomicUsize::new(0);
+
+#[test]
+fn test_miri() {
+    BOOL.write_current(true);
+    U32.write_current(42);
+    SLOTS.write_current([1, 2, 3, 4]);
+    assert!(BOOL.read_current());
+    assert_eq!(U32.read_current(), 42);
+    assert_eq!(SLOTS.read_current_at(2), 3);
+
+    BOOL.write_current(false);
+    U32.add_current(8);
+    USIZE.write_current(100);
+    USIZE.sub_current(1);
+    SLOTS.write_current_at(0, 10);
+    assert!(!BOOL.read_current());
+    assert_eq!(U32.fetch_add_current(1), 50);
+    assert_eq!(USIZE.swap_current(7), 99);
+    assert_eq!(SLOTS.read_current(), [10, 2, 3, 4]);
+
+    STRUCT.with_current(|s| {
+        s.foo = 1;
+        s.bar = 2;
+    });
+    STRUCT.with_current(|s| {
+        s.foo += 1;
+        s.bar += 1;
+    });
+    let s = unsafe { STRUCT.remote_ref_raw(0) };
+    assert_eq!((s.foo, s.bar), (2, 3));
+
+    ATOMIC.current().fetch_add(1, Ordering::Relaxed);
+    assert_eq!(ATOMIC.remote(0).load(Ordering::Relaxed), 1);
+    assert_eq!(unsafe { *USIZE.current_ptr() }, 7);
+}
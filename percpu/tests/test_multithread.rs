@@ -0,0 +1,83 @@
+#![cfg(target_os = "linux")]
+
+use std::sync::Barrier;
+
+use percpu::*;
+
+const NUM_CPUS: usize = 4;
+const ITERS: usize = 100_000;
+
+#[def_percpu]
+static VALUE: u64 = 0;
+
+#[def_percpu]
+static COUNT: usize = 0;
+
+#[def_percpu]
+static PAIR: (usize, usize) = (0, 0);
+
+#[cfg(not(feature = "sp-naive"))]
+#[test]
+fn test_one_cpu_per_thread() {
+    init(NUM_CPUS);
+    let barrier = Barrier::new(NUM_CPUS);
+
+    std::thread::scope(|s| {
+        for cpu_id in 0..NUM_CPUS {
+            let barrier = &barrier;
+            s.spawn(move || {
+                // Each thread has its own thread pointer, so it acts as a CPU.
+                set_local_thread_pointer(cpu_id);
+                assert_eq!(current_cpu_id(), cpu_id);
+                barrier.wait();
+
+                let tag = (cpu_id as u64) << 32;
+                for i in 0..ITERS {
+                    VALUE.write_current(tag | i as u64);
+                    assert_eq!(VALUE.read_current(), tag | i as u64);
+                    COUNT.add_current(1);
+                    PAIR.with_current(|pair| {
+                        pair.0 += cpu_id;
+                        pair.1 += 1;
+                    });
+                }
+                assert_eq!(current_cpu_id(), cpu_id);
+            });
+        }
+    });
+
+    for cpu_id in 0..NUM_CPUS {
+        assert_eq!(
+            VALUE.read_remote(cpu_id),
+            ((cpu_id as u64) << 32) | (ITERS as u64 - 1)
+        );
+        assert_eq!(COUNT.read_remote(cpu_id), ITERS);
+        assert_eq!(unsafe { *PAIR.remote_ptr(cpu_id) }, (cpu_id * ITERS, ITERS));
+    }
+}
+
+#[cfg(feature = "sp-naive")]
+#[test]
+fn test_thread_local_naive() {
+    let barrier = Barrier::new(NUM_CPUS);
+
+    std::thread::scope(|s| {
+        for id in 0..NUM_CPUS {
+            let barrier = &barrier;
+            s.spawn(move || {
+                // Each thread has its own copy of the per-CPU data.
+                assert_eq!(COUNT.read_current(), 0);
+                barrier.wait();
+                for i in 0..ITERS {
+                    VALUE.write_current(i as u64);
+                    COUNT.add_current(1);
+                    PAIR.with_current(|pair| pair.0 += id);
+                }
+                assert_eq!(VALUE.read_current(), ITERS as u64 - 1);
+                assert_eq!(COUNT.read_current(), ITERS);
+                assert_eq!(PAIR.with_current(|pair| pair.0), id * ITERS);
+            });
+        }
+    });
+    assert_eq!(COUNT.read_current(), 0);
+}
@@ -0,0 +1,84 @@
+#![cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+
+use percpu::*;
+
+#[def_percpu]
+static COUNTERS: [u32; 4] = [0; 4];
+
+#[derive(Default)]
+struct TestParker {
+    parked: bool,
+    parks: usize,
+}
+
+unsafe impl CpuParker for TestParker {
+    fn park_others(&mut self) {
+        assert!(!self.parked);
+        self.parked = true;
+        self.parks += 1;
+    }
+
+    fn unpark_others(&mut self) {
+        assert!(self.parked);
+        self.parked = false;
+    }
+}
+
+#[test]
+fn test_park() {
+    init(4);
+    set_local_thread_pointer(0);
+
+    let mut parker = TestParker::default();
+    let sum = with_all_cpus_parked(&mut parker, |token| {
+        COUNTERS.with_current(|c| c[0] = 1);
+        for cpu in (1..4).map(CpuId::new) {
+            COUNTERS.remote_mut(token, cpu)[cpu.get()] = cpu.get() as u32 + 1;
+        }
+        let last = COUNTERS.remote_mut(token, 3);
+        last[0] = 7;
+        (1..4)
+            .map(|cpu| COUNTERS.remote_mut(token, cpu).iter().sum::<u32>())
+            .sum::<u32>()
+    });
+    assert_eq!(sum, 2 + 3 + 4 + 7);
+    assert!(!parker.parked);
+    assert_eq!(parker.parks, 1);
+    assert_eq!(COUNTERS.read_current(), [1, 0, 0, 0]);
+    assert_eq!(unsafe { *COUNTERS.remote_ptr(3) }, [7, 0, 0, 4]);
+
+    // The other CPUs are resumed even if the closure panics.
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        with_all_cpus_parked(&mut parker, |token| COUNTERS.remote_mut(token, 4)[0] = 1)
+    }));
+    assert!(result.is_err());
+    assert!(!parker.parked);
+    assert_eq!(parker.parks, 2);
+
+    // The data of the current CPU can not be accessed by `remote_mut`, which
+    // would alias the reference of `with_current`.
+    COUNTERS.with_current(|c| {
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            with_all_cpus_parked(&mut parker, |token| COUNTERS.remote_mut(token, 0)[0] = 1)
+        }));
+        let msg = *result.unwrap_err().downcast::<&str>().unwrap();
+        assert_eq!(msg, "the current CPU can not be accessed by `remote_mut`");
+        c[0] = 2;
+    });
+    assert_eq!(COUNTERS.read_current(), [2, 0, 0, 0]);
+
+    // The nested call would mint a second token, which also allows two mutable
+    // references to the same data.
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        with_all_cpus_parked(&mut parker, |_token| {
+            with_all_cpus_parked(&mut TestParker::default(), |_token| {});
+        })
+    }));
+    let msg = *result.unwrap_err().downcast::<&str>().unwrap();
+    assert_eq!(msg, "the other CPUs are already parked");
+    assert!(!parker.parked);
+
+    // The outer call allows to park the CPUs again after it returns.
+    with_all_cpus_parked(&mut parker, |token| COUNTERS.remote_mut(token, 1)[0] = 1);
+    assert_eq!(parker.parks, 5);
+}
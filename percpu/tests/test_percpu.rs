@@ -1,7 +1,22 @@
-#![cfg(not(target_os = "macos"))]
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use percpu::*;
 
+/// Initializes the per-CPU data areas once for all tests, which run in
+/// parallel, and sets the current thread as `cpu_id`.
+#[cfg(not(target_os = "macos"))]
+fn init_on(cpu_id: usize) {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| {
+        #[cfg(not(feature = "sp-naive"))]
+        init(4);
+    });
+    #[cfg(not(feature = "sp-naive"))]
+    set_local_thread_pointer(cpu_id);
+    #[cfg(feature = "sp-naive")]
+    let _ = cpu_id;
+}
+
 // Initial value is unsupported for testing.
 
 #[def_percpu]
@@ -22,6 +37,15 @@ static U64: u64 = 0;
 #[def_percpu]
 static USIZE: usize = 0;
 
+#[def_percpu]
+static U128: u128 = 0;
+
+#[def_percpu]
+static F32: f32 = 0.0;
+
+#[def_percpu]
+static F64: f64 = 0.0;
+
 struct Struct {
     foo: usize,
     bar: u8,
@@ -30,22 +54,40 @@ struct Struct {
 #[def_percpu]
 static STRUCT: Struct = Struct { foo: 0, bar: 0 };
 
-#[cfg(target_os = "linux")]
+#[def_percpu(align = "cacheline")]
+static ALIGNED: u32 = 0;
+
+#[def_percpu]
+static PAIR: (u32, u32) = (0, 0);
+
+#[def_percpu]
+static SLOTS: [u64; 4] = [0; 4];
+
+#[def_percpu]
+static BYTES: [u8; 3] = [0; 3];
+
+#[def_percpu]
+static ATOMIC: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
 #[test]
 fn test_percpu() {
+    // The per-CPU data is just global variables on macOS, like the "sp-naive" feature.
     println!("feature = \"sp-naive\": {}", cfg!(feature = "sp-naive"));
 
-    #[cfg(feature = "sp-naive")]
+    #[cfg(any(feature = "sp-naive", target_os = "macos"))]
     let base = 0;
 
-    #[cfg(not(feature = "sp-naive"))]
+    #[cfg(not(any(feature = "sp-naive", target_os = "macos")))]
     let base = {
-        init(4);
-        set_local_thread_pointer(0);
+        init_on(0);
 
+        assert_eq!(current_cpu_id(), 0);
         let base = get_local_thread_pointer();
         println!("per-CPU area base = {:#x}", base);
         println!("per-CPU area size = {}", percpu_area_size());
+        println!("per-CPU bss size = {}", percpu_bss_size());
+        assert_eq!(percpu_bss_size() + percpu_data_size(), percpu_area_size());
         base
     };
 
@@ -58,6 +100,53 @@ fn test_percpu() {
     println!("struct offset: {:#x}", STRUCT.offset());
     println!();
 
+    assert_eq!(ALIGNED.offset() % 64, 0);
+    ALIGNED.write_current(0x1234_5678);
+    assert_eq!(ALIGNED.read_current(), 0x1234_5678);
+    ATOMIC.current().store(10, Ordering::Relaxed);
+    PAIR.write_current((1, 2));
+    assert_eq!(PAIR.read_current(), (1, 2));
+    assert_eq!(PAIR.replace_current((3, 4)), (1, 2));
+    assert_eq!(PAIR.update_current(|pair| pair.0 + pair.1), 7);
+    assert_eq!(PAIR.take_current(), (3, 4));
+    assert_eq!(PAIR.read_current(), (0, 0));
+
+    // test indexed accessors of arrays
+    SLOTS.write_current_at(2, 0xdead_beef_0000);
+    SLOTS.write_current_at(3, 1);
+    assert_eq!(SLOTS.read_current_at(2), 0xdead_beef_0000);
+    assert_eq!(SLOTS.read_current(), [0, 0, 0xdead_beef_0000, 1]);
+    BYTES.write_current_at(1, 0xab);
+    assert_eq!(BYTES.read_current_at(1), 0xab);
+    assert_eq!(BYTES.read_current(), [0, 0xab, 0]);
+    assert_eq!(ATOMIC.current().load(Ordering::Relaxed), 10);
+
+    // test 128-bit accessors, which are two loads (or stores) on x86_64
+    U128.write_current(0x0123_4567_89ab_cdef_fedc_ba98_7654_3210);
+    assert_eq!(
+        U128.read_current(),
+        0x0123_4567_89ab_cdef_fedc_ba98_7654_3210
+    );
+    unsafe { U128.write_current_raw(u128::MAX - 1) };
+    assert_eq!(unsafe { U128.read_current_raw() }, u128::MAX - 1);
+    assert_eq!(unsafe { *U128.current_ptr() }, u128::MAX - 1);
+
+    // test float accessors, which are bit-cast from and to integers
+    F32.write_current(1.5);
+    F64.write_current(-0.25);
+    assert_eq!(F32.read_current(), 1.5);
+    assert_eq!(F64.read_current(), -0.25);
+    assert_eq!(F64.read_remote(current_cpu_id()), -0.25);
+    F32.write_remote(current_cpu_id(), f32::INFINITY);
+    assert_eq!(unsafe { F32.read_current_raw() }, f32::INFINITY);
+
+    // zero-initialized data is placed before other data
+    #[cfg(not(any(feature = "sp-naive", target_os = "macos")))]
+    {
+        assert!(USIZE.offset() < percpu_bss_size());
+        assert!(STRUCT.offset() >= percpu_bss_size());
+    }
+
     unsafe {
         assert_eq!(base + BOOL.offset(), BOOL.current_ptr() as usize);
         assert_eq!(base + U8.offset(), U8.current_ptr() as usize);
@@ -93,6 +182,50 @@ fn test_percpu() {
     assert_eq!(U64.read_current(), 0xa2ce_a2ce_a2ce_a2ce);
     assert_eq!(USIZE.read_current(), 0xffff_0000);
 
+    // test arithmetic accessors
+    U8.add_current(200); // wrapping around
+    assert_eq!(U8.read_current(), 67);
+    U8.sub_current(200);
+    assert_eq!(U8.fetch_add_current(1), 123);
+    assert_eq!(U8.fetch_add_current(u8::MAX), 124); // wrapping around
+    assert_eq!(U8.read_current(), 123);
+    U64.add_current(0x1_0000_0000);
+    assert_eq!(U64.fetch_add_current(1), 0xa2ce_a2cf_a2ce_a2ce);
+    U64.sub_current(0x1_0000_0001);
+    assert_eq!(U64.read_current(), 0xa2ce_a2ce_a2ce_a2ce);
+
+    // test swap and compare-exchange accessors
+    assert_eq!(U8.swap_current(7), 123);
+    assert_eq!(U8.compare_exchange_current(7, 123), Ok(7));
+    assert_eq!(U8.compare_exchange_current(7, 0), Err(123));
+    assert_eq!(U16.swap_current(0x1234), 0xabcd);
+    assert_eq!(U16.compare_exchange_current(0x1234, 0xabcd), Ok(0x1234));
+    assert_eq!(U32.compare_exchange_current(0, 1), Err(0xdead_beef));
+    assert_eq!(
+        U32.compare_exchange_current(0xdead_beef, 0xbeef_dead),
+        Ok(0xdead_beef)
+    );
+    assert_eq!(U32.swap_current(0xdead_beef), 0xbeef_dead);
+    assert_eq!(USIZE.swap_current(1), 0xffff_0000);
+    assert_eq!(USIZE.compare_exchange_current(1, 0xffff_0000), Ok(1));
+    assert_eq!(
+        U64.compare_exchange_current(0, 0),
+        Err(0xa2ce_a2ce_a2ce_a2ce)
+    );
+    assert_eq!(U64.read_current(), 0xa2ce_a2ce_a2ce_a2ce);
+
+    // test bit accessors
+    U8.set_bit_current(7);
+    assert!(U8.test_bit_current(7));
+    assert_eq!(U8.read_current(), 123 | 0x80);
+    U8.clear_bit_current(7);
+    assert!(!U8.test_bit_current(7));
+    USIZE.clear_bit_current(16);
+    assert_eq!(USIZE.read_current(), 0xfffe_0000);
+    USIZE.set_bit_current(16);
+    assert!(USIZE.test_bit_current(16));
+    assert!(std::panic::catch_unwind(|| U16.set_bit_current(16)).is_err());
+
     STRUCT.with_current(|s| {
         println!("struct.foo value: {:#x}", s.foo);
         println!("struct.bar value: {}", s.bar);
@@ -100,6 +233,28 @@ fn test_percpu() {
         assert_eq!(s.bar, 100);
     });
 
+    // test RAII guards
+    {
+        let mut s = unsafe { STRUCT.current_mut() };
+        s.foo += 1;
+        s.bar += 1;
+    }
+    {
+        let s = unsafe { STRUCT.current() };
+        assert_eq!(s.foo, 0x2334);
+        assert_eq!(s.bar, 101);
+    }
+
+    // test IRQ-disabling accessors
+    #[cfg(feature = "irq")]
+    {
+        U32.write_current_irqsave(0xbeef_dead);
+        assert_eq!(U32.read_current_irqsave(), 0xbeef_dead);
+        STRUCT.with_current_irqsave(|s| s.bar += 1);
+        assert_eq!(unsafe { STRUCT.current() }.bar, 102);
+        STRUCT.with_current_irqsave(|s| s.bar -= 1);
+    }
+
     // test remote write
     unsafe {
         *BOOL.remote_ref_mut_raw(1) = false;
@@ -129,8 +284,56 @@ fn test_percpu() {
         assert_eq!(s.bar, 200);
     }
 
+    // test safe remote read/write
+    #[cfg(not(any(feature = "sp-naive", target_os = "macos")))]
+    {
+        assert_eq!(percpu_area_num(), 4);
+        assert!(!BOOL.read_remote(1));
+        assert_eq!(U8.read_remote(1), 222);
+        assert_eq!(U64.read_remote(1), 0xfeed_feed_feed_feed);
+        assert_eq!(U32.read_remote(3), 0); // cleared during initialization
+
+        U16.write_remote(2, 0x5678);
+        USIZE.write_remote(2, 0xdead_0000);
+        unsafe {
+            assert_eq!(*U16.remote_ptr(2), 0x5678);
+            assert_eq!(*USIZE.remote_ptr(2), 0xdead_0000);
+        }
+        #[cfg(debug_assertions)]
+        assert!(std::panic::catch_unwind(|| unsafe { USIZE.remote_ptr(4) }).is_err());
+
+        U32.remote_write_release(3, 0x600d_600d);
+        assert_eq!(U32.remote_read_acquire(3), 0x600d_600d);
+        U32.remote_write_release(3, 0);
+
+        // no `unsafe` for atomic types
+        ATOMIC.remote(2).fetch_add(5, Ordering::Relaxed);
+        assert_eq!(ATOMIC.remote(0).load(Ordering::Relaxed), 10);
+        assert_eq!(ATOMIC.remote(2).load(Ordering::Relaxed), 5);
+        assert!(std::panic::catch_unwind(|| ATOMIC.remote(4)).is_err());
+    }
+
+    // test iteration over all CPUs
+    #[cfg(not(any(feature = "sp-naive", target_os = "macos")))]
+    unsafe {
+        let mut cpu_ids = Vec::new();
+        USIZE.for_each(|cpu_id, _| cpu_ids.push(cpu_id));
+        assert_eq!(cpu_ids, [0, 1, 2, 3]);
+
+        let (_, val) = U32.iter_remote().nth(1).unwrap();
+        assert_eq!(*val, 0xf00d_f00d);
+
+        let sum = USIZE.fold(
+            0,
+            |acc, cpu_id, val| if cpu_id < 3 { acc + val } else { acc },
+        );
+        assert_eq!(sum, 0xffff_0000 + 0x0000_ffff + 0xdead_0000);
+    }
+
     // test read on another CPU
     set_local_thread_pointer(1); // we are now on CPU 1
+    #[cfg(not(any(feature = "sp-naive", target_os = "macos")))]
+    assert_eq!(current_cpu_id(), 1);
 
     println!("bool value on CPU 1: {}", BOOL.read_current());
     println!("u8 value on CPU 1: {}", U8.read_current());
@@ -153,3 +356,1268 @@ fn test_percpu() {
         assert_eq!(s.bar, 200);
     });
 }
+
+#[cfg(target_os = "linux")]
+mod once {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu]
+    static IDLE_TASK: PerCpuOnce<usize> = PerCpuOnce::new();
+
+    #[test]
+    fn test_percpu_once() {
+        init_on(0);
+
+        assert!(!IDLE_TASK.is_set_current());
+        assert_eq!(IDLE_TASK.get_current(), None);
+        assert_eq!(IDLE_TASK.set_current(1), Ok(()));
+        assert_eq!(IDLE_TASK.set_current(2), Err(2));
+        assert_eq!(IDLE_TASK.get_or_init_current(|| 3), &1);
+        assert!(IDLE_TASK.is_set_current());
+        assert_eq!(IDLE_TASK.get_current(), Some(&1));
+
+        // The references are not guarded, and stay valid since the value can not
+        // be changed once set.
+        let idle = IDLE_TASK.get_current().unwrap();
+        assert_eq!(IDLE_TASK.set_current(5), Err(5));
+        assert_eq!(*idle, 1);
+
+        #[cfg(not(feature = "sp-naive"))]
+        {
+            set_local_thread_pointer(1);
+            assert_eq!(IDLE_TASK.get_current(), None);
+            assert_eq!(IDLE_TASK.get_or_init_current(|| 3), &3);
+            assert_eq!(IDLE_TASK.set_current(4), Err(4));
+            assert_eq!(unsafe { IDLE_TASK.remote_ref_raw(0) }.get(), Some(&1));
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod lazy {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use percpu::*;
+
+    use super::init_on;
+
+    static INIT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    fn make_vec() -> Vec<usize> {
+        INIT_COUNT.fetch_add(1, Ordering::Relaxed);
+        vec![1, 2, 3]
+    }
+
+    #[def_percpu(lazy)]
+    static VEC: Vec<usize> = make_vec();
+
+    #[test]
+    fn test_percpu_lazy() {
+        init_on(0);
+        #[cfg(not(feature = "sp-naive"))]
+        {
+            // Initial value is unsupported for testing, write it manually.
+            for cpu_id in 0..2 {
+                unsafe {
+                    core::ptr::write(VEC.remote_ptr(cpu_id) as *mut _, PerCpuLazy::new(make_vec))
+                };
+            }
+        }
+
+        assert!(!VEC.is_init_current());
+        assert_eq!(**VEC.current(), [1, 2, 3]);
+        assert!(VEC.is_init_current());
+        assert_eq!(VEC.current().len(), 3);
+        assert_eq!(INIT_COUNT.load(Ordering::Relaxed), 1);
+
+        #[cfg(not(feature = "sp-naive"))]
+        {
+            set_local_thread_pointer(1);
+            VEC.init_current(|| vec![5]);
+            assert_eq!(**VEC.current(), [5]);
+            assert_eq!(INIT_COUNT.load(Ordering::Relaxed), 1);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod refcell {
+    use std::panic::catch_unwind;
+
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu]
+    static TIMERS: PerCpuRefCell<Vec<u64>> = PerCpuRefCell::new(Vec::new());
+
+    fn add_timer(deadline: u64) {
+        TIMERS.borrow_mut_current().push(deadline);
+    }
+
+    #[test]
+    fn test_refcell() {
+        init_on(0);
+
+        add_timer(10);
+        add_timer(20);
+
+        // Shared borrows can coexist, but not with a mutable one.
+        {
+            let timers = TIMERS.borrow_current();
+            let again = TIMERS.borrow_current();
+            assert_eq!(*timers, [10, 20]);
+            assert_eq!(again.len(), 2);
+            assert!(TIMERS.try_borrow_mut_current().is_none());
+        }
+
+        // A callee that borrows the same data again panics instead of aliasing the
+        // mutable reference, and the borrow is released by unwinding.
+        let err = catch_unwind(|| {
+            let _timers = TIMERS.borrow_mut_current();
+            add_timer(30);
+        })
+        .unwrap_err();
+        assert_eq!(
+            *err.downcast_ref::<&str>().unwrap(),
+            "the per-CPU data `TIMERS` is already borrowed on the current CPU"
+        );
+        {
+            let mut timers = TIMERS.borrow_mut_current();
+            assert!(TIMERS.try_borrow_current().is_none());
+            timers.push(30);
+        }
+        assert!(TIMERS.try_borrow_mut_current().is_some());
+        assert_eq!(*TIMERS.borrow_current(), [10, 20, 30]);
+
+        // Each thread acts as a CPU, with its own data and borrow state.
+        #[cfg(not(feature = "sp-naive"))]
+        {
+            let _timers = TIMERS.borrow_mut_current();
+            std::thread::scope(|s| {
+                s.spawn(|| {
+                    set_local_thread_pointer(1);
+                    add_timer(40);
+                    assert_eq!(*TIMERS.borrow_current(), [40]);
+                });
+            });
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod flag {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu]
+    static BOOTED: PercpuFlag = PercpuFlag::new();
+
+    #[def_percpu]
+    static BOOT_DATA: usize = 0;
+
+    #[test]
+    fn test_percpu_flag() {
+        init_on(0);
+
+        assert!(!BOOTED.is_set_current());
+        BOOTED.set_current();
+        assert!(BOOTED.is_set_current());
+        assert!(BOOTED.is_set_remote(0));
+        BOOTED.wait_until_all_set([0]);
+        BOOTED.clear_current();
+        assert!(!BOOTED.is_set_remote(0));
+
+        // Each thread acts as a CPU, and publishes its data with the flag.
+        #[cfg(not(feature = "sp-naive"))]
+        {
+            std::thread::scope(|s| {
+                for cpu_id in 1..4 {
+                    s.spawn(move || {
+                        set_local_thread_pointer(cpu_id);
+                        BOOT_DATA.write_current(cpu_id * 10);
+                        BOOTED.set_current();
+                    });
+                }
+                BOOTED.wait_until_all_set(1..4);
+                for cpu_id in 1..4 {
+                    assert!(BOOTED.is_set_remote(cpu_id));
+                    assert_eq!(BOOT_DATA.read_remote(cpu_id), cpu_id * 10);
+                }
+            });
+            assert!(!BOOTED.is_set_remote(0));
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod counter {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu]
+    static COUNTER: PercpuCounter = PercpuCounter::new();
+
+    #[test]
+    fn test_percpu_counter() {
+        init_on(0);
+
+        COUNTER.inc_current();
+        COUNTER.add_current(10);
+        assert_eq!(COUNTER.read_current(), 11);
+        assert_eq!(COUNTER.sum(), 11);
+
+        #[cfg(not(feature = "sp-naive"))]
+        {
+            set_local_thread_pointer(1);
+            assert_eq!(COUNTER.read_current(), 0);
+            COUNTER.add_current(-20);
+            COUNTER.dec_current();
+            assert_eq!(COUNTER.read_current(), -21);
+
+            set_local_thread_pointer(3);
+            COUNTER.add_current(5);
+            assert_eq!(COUNTER.sum(), -5);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod counter_batched {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu]
+    static NR_PAGES: PercpuCounterBatched = PercpuCounterBatched::new();
+
+    #[test]
+    fn test_counter_batched() {
+        init_on(0);
+
+        assert_eq!(NR_PAGES.batch(), DEFAULT_COUNTER_BATCH);
+        NR_PAGES.set_batch(4);
+        assert!(std::panic::catch_unwind(|| NR_PAGES.set_batch(0)).is_err());
+
+        NR_PAGES.add_current(3);
+        assert_eq!(NR_PAGES.approx_sum(), 0);
+        assert_eq!(NR_PAGES.precise_sum(), 3);
+        NR_PAGES.inc_current();
+        assert_eq!(NR_PAGES.approx_sum(), 4);
+        assert_eq!(NR_PAGES.precise_sum(), 4);
+
+        #[cfg(not(feature = "sp-naive"))]
+        {
+            set_local_thread_pointer(1);
+            NR_PAGES.add_current(-2);
+            assert_eq!(NR_PAGES.approx_sum(), 4);
+            assert_eq!(NR_PAGES.precise_sum(), 2);
+            NR_PAGES.add_current(-5);
+            assert_eq!(NR_PAGES.approx_sum(), -3);
+            assert_eq!(NR_PAGES.precise_sum(), -3);
+        }
+
+        NR_PAGES.dec_current();
+        assert_eq!(NR_PAGES.precise_sum(), NR_PAGES.approx_sum() - 1);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod refcount {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu]
+    static REF: PercpuRef = PercpuRef::new();
+
+    #[test]
+    fn test_percpu_ref() {
+        init_on(0);
+
+        // per-CPU mode
+        REF.get();
+        REF.get();
+        assert!(!REF.put());
+        #[cfg(not(feature = "sp-naive"))]
+        set_local_thread_pointer(1);
+        assert!(!REF.put()); // released on another CPU
+        #[cfg(not(feature = "sp-naive"))]
+        set_local_thread_pointer(2);
+        REF.get();
+        assert!(!REF.is_killed());
+
+        // shared atomic mode, 1 reference is held besides the initial one
+        assert!(!REF.kill());
+        assert!(REF.is_killed());
+        REF.get();
+        assert!(!REF.put());
+        assert!(REF.put());
+
+        assert!(std::panic::catch_unwind(|| REF.kill()).is_err());
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod rwlock {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu]
+    static LOCK: PercpuRwLock = PercpuRwLock::new();
+
+    #[test]
+    fn test_percpu_rwlock() {
+        init_on(0);
+
+        LOCK.read_lock();
+        LOCK.read_lock();
+        #[cfg(not(feature = "sp-naive"))]
+        set_local_thread_pointer(1);
+        // the read lock is released on another CPU
+        unsafe { LOCK.read_unlock() };
+        unsafe { LOCK.read_unlock() };
+
+        assert_eq!(LOCK.write(|| 42), 42);
+        assert_eq!(LOCK.read(|| 43), 43);
+        LOCK.write(|| {});
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod work_queue {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu]
+    static DEFERRED: PercpuWorkQueue = PercpuWorkQueue::new();
+
+    static LOG: AtomicUsize = AtomicUsize::new(0);
+    static RUN: AtomicUsize = AtomicUsize::new(0);
+
+    fn count() {
+        RUN.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn work1() {
+        LOG.store(LOG.load(Ordering::Relaxed) * 10 + 1, Ordering::Relaxed);
+    }
+
+    fn work2() {
+        LOG.store(LOG.load(Ordering::Relaxed) * 10 + 2, Ordering::Relaxed);
+    }
+
+    fn requeue() {
+        work1();
+        DEFERRED.push_current(requeue).unwrap();
+    }
+
+    #[test]
+    fn test_work_queue() {
+        init_on(0);
+
+        // Works are run in the order they are pushed.
+        DEFERRED.push_current(work2).unwrap();
+        DEFERRED.push_current(work1).unwrap();
+        DEFERRED.push_current(work2).unwrap();
+        assert_eq!(DEFERRED.drain_current(), 3);
+        assert_eq!(LOG.load(Ordering::Relaxed), 212);
+        assert_eq!(DEFERRED.drain_current(), 0);
+
+        // The queue is full after `WORK_QUEUE_CAPACITY` works, and wraps around after draining.
+        for _ in 0..2 {
+            for _ in 0..WORK_QUEUE_CAPACITY {
+                DEFERRED.push_current(count).unwrap();
+            }
+            assert!(DEFERRED.push_current(count).is_err());
+            assert_eq!(DEFERRED.drain_current(), WORK_QUEUE_CAPACITY);
+        }
+        assert_eq!(RUN.swap(0, Ordering::Relaxed), WORK_QUEUE_CAPACITY * 2);
+
+        // Works pushed by works are run by the next drain.
+        LOG.store(0, Ordering::Relaxed);
+        DEFERRED.push_current(requeue).unwrap();
+        assert_eq!(DEFERRED.drain_current(), 1);
+        assert_eq!(DEFERRED.drain_current(), 1);
+        assert_eq!(LOG.load(Ordering::Relaxed), 11);
+        DEFERRED.current().pop().unwrap();
+        assert!(DEFERRED.current().is_empty());
+
+        // Each thread acts as a CPU, and runs the works pushed by CPU 0.
+        #[cfg(not(feature = "sp-naive"))]
+        {
+            for cpu_id in 1..4 {
+                DEFERRED.push_remote(cpu_id, count).unwrap();
+                DEFERRED.push_remote(cpu_id, count).unwrap();
+            }
+            assert_eq!(DEFERRED.drain_current(), 0);
+            std::thread::scope(|s| {
+                for cpu_id in 1..4 {
+                    s.spawn(move || {
+                        set_local_thread_pointer(cpu_id);
+                        assert_eq!(DEFERRED.drain_current(), 2);
+                    });
+                }
+            });
+            assert_eq!(RUN.load(Ordering::Relaxed), 6);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod group {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[derive(Clone, Copy)]
+    struct Pair(u32, u32);
+
+    def_percpu_group! {
+        static FLAG: u8 = 0;
+        /// The ticks on each CPU.
+        static TICKS: u64 = 0;
+        #[def_percpu(align = 64)]
+        static STATS: [usize; 4] = [0; 4];
+        pub static GROUP_PAIR: Pair = Pair(0, 0);
+        #[def_percpu(lazy)]
+        static NAMES: Vec<&'static str> = vec!["cpu"];
+    }
+
+    #[test]
+    fn test_percpu_group() {
+        init_on(0);
+        #[cfg(not(feature = "sp-naive"))]
+        {
+            // Initial value is unsupported for testing, write it manually.
+            unsafe {
+                core::ptr::write(
+                    NAMES.remote_ptr(0) as *mut _,
+                    PerCpuLazy::new(|| vec!["cpu"]),
+                )
+            };
+            assert_eq!(STATS.offset() % 64, 0);
+        }
+
+        FLAG.write_current(1);
+        TICKS.write_current(10);
+        assert_eq!(FLAG.read_current(), 1);
+        assert_eq!(TICKS.read_current(), 10);
+        STATS.with_current(|stats| stats[1] = 5);
+        assert_eq!(STATS.read_current(), [0, 5, 0, 0]);
+        GROUP_PAIR.write_current(Pair(2, 3));
+        let Pair(a, b) = GROUP_PAIR.read_current();
+        assert_eq!((a, b), (2, 3));
+        assert_eq!(**NAMES.current(), ["cpu"]);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod percpu_trait {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu]
+    static RX_BYTES: u64 = 0;
+
+    #[def_percpu]
+    static TX_BYTES: u64 = 0;
+
+    #[def_percpu]
+    static HISTORY: [u64; 2] = [0; 2];
+
+    fn add_bytes<P: PerCpu<u64>>(var: &P, n: u64) -> u64 {
+        let val = var.read_current() + n;
+        var.write_current(val);
+        val
+    }
+
+    fn push<P: PerCpuMut<[u64; 2]>>(var: &P, val: u64) {
+        var.with_current(|h| *h = [h[1], val]);
+    }
+
+    #[test]
+    fn test_percpu_trait() {
+        init_on(0);
+
+        assert_eq!(add_bytes(&RX_BYTES, 10), 10);
+        assert_eq!(add_bytes(&RX_BYTES, 5), 15);
+        assert_eq!(add_bytes(&TX_BYTES, 1), 1);
+        assert_eq!(RX_BYTES.read_current(), 15);
+
+        push(&HISTORY, 1);
+        push(&HISTORY, 2);
+        assert_eq!(HISTORY.read_current(), [1, 2]);
+
+        let vars: [&dyn PerCpu<u64>; 2] = [&RX_BYTES, &TX_BYTES];
+        assert_eq!(vars[1].offset(), TX_BYTES.offset());
+        assert_eq!(vars[0].percpu_ptr(), RX_BYTES.percpu_ptr());
+        assert_eq!(unsafe { *vars[0].current_ptr() }, 15);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod percpu_ptr {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu]
+    static NR_RUNNING: usize = 0;
+
+    #[def_percpu]
+    static LOAD: u64 = 0;
+
+    /// Generic code that only knows the per-CPU slot.
+    fn add_to_slot(slot: PerCpuPtr<usize>, val: usize) {
+        unsafe { *slot.resolve_current() += val };
+    }
+
+    #[test]
+    fn test_percpu_ptr() {
+        init_on(0);
+
+        let slot = NR_RUNNING.percpu_ptr();
+        assert_eq!(slot, NR_RUNNING.percpu_ptr());
+        assert_eq!(slot.offset(), NR_RUNNING.offset());
+        assert_ne!(slot.offset(), LOAD.percpu_ptr().offset());
+
+        add_to_slot(slot, 3);
+        assert_eq!(NR_RUNNING.read_current(), 3);
+        assert_eq!(slot.resolve_on(0), unsafe { NR_RUNNING.current_ptr() }
+            as *mut usize);
+
+        #[cfg(not(feature = "sp-naive"))]
+        {
+            unsafe { *slot.resolve_on(1) = 5 };
+            set_local_thread_pointer(1);
+            assert_eq!(NR_RUNNING.read_current(), 5);
+            add_to_slot(slot, 1);
+            assert_eq!(unsafe { *NR_RUNNING.remote_ptr(1) }, 6);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod nonzero_ptr {
+    use std::num::{NonZero, NonZeroU32, NonZeroUsize};
+    use std::ptr::NonNull;
+
+    use percpu::*;
+
+    use super::init_on;
+
+    struct Task {
+        id: usize,
+    }
+
+    #[def_percpu]
+    static RUNNING_TASK: Option<NonNull<Task>> = None;
+
+    #[def_percpu]
+    static NR_FREE_PAGES: NonZeroUsize = NonZeroUsize::MIN;
+
+    #[def_percpu]
+    static LEVEL: NonZeroU32 = NonZeroU32::MIN;
+
+    #[def_percpu]
+    static ORDER: NonZero<u8> = NonZero::<u8>::MIN;
+
+    #[test]
+    fn test_nonzero_ptr() {
+        init_on(0);
+
+        // Initial values are unsupported for testing, so the non-zero ones are written first.
+        NR_FREE_PAGES.write_current(NonZeroUsize::new(16).unwrap());
+        LEVEL.write_current(NonZeroU32::new(3).unwrap());
+        ORDER.write_current(NonZero::new(9).unwrap());
+        assert_eq!(NR_FREE_PAGES.read_current().get(), 16);
+        assert_eq!(LEVEL.read_current().get(), 3);
+        assert_eq!(ORDER.read_current().get(), 9);
+        assert_eq!(NR_FREE_PAGES.read_remote(0).get(), 16);
+
+        let mut task = Task { id: 42 };
+        RUNNING_TASK.write_current(None);
+        assert_eq!(RUNNING_TASK.read_current(), None);
+        RUNNING_TASK.write_current(Some(NonNull::from(&mut task)));
+        let current = RUNNING_TASK.read_current().unwrap();
+        assert_eq!(unsafe { current.as_ref() }.id, 42);
+        assert_eq!(RUNNING_TASK.read_remote(0), Some(current));
+
+        #[cfg(not(feature = "sp-naive"))]
+        {
+            RUNNING_TASK.write_remote(1, None);
+            LEVEL.write_remote(1, NonZeroU32::MAX);
+            set_local_thread_pointer(1);
+            assert_eq!(RUNNING_TASK.read_current(), None);
+            assert_eq!(LEVEL.read_current(), NonZeroU32::MAX);
+            set_local_thread_pointer(0);
+            assert_eq!(RUNNING_TASK.read_current(), Some(current));
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod placement {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu(cold)]
+    static COLD_STATS: [u64; 4] = [0; 4];
+
+    #[def_percpu]
+    static NORMAL: usize = 0;
+
+    #[def_percpu(hot)]
+    static HOT_TICKS: u64 = 0;
+
+    #[def_percpu(hot)]
+    static HOT_ARRAY: [u32; 2] = [1, 2];
+
+    #[def_percpu(cold)]
+    static COLD_FLAG: u8 = 1;
+
+    #[def_percpu(read_mostly)]
+    static CPU_FREQ: u32 = 0;
+
+    #[test]
+    fn test_hot_cold_placement() {
+        #[cfg(not(feature = "sp-naive"))]
+        {
+            // Zero-initialized part
+            assert!(HOT_TICKS.offset() < NORMAL.offset());
+            assert!(NORMAL.offset() < CPU_FREQ.offset());
+            assert!(CPU_FREQ.offset() < COLD_STATS.offset());
+            // Read-mostly data occupies its own cache lines.
+            assert_eq!(CPU_FREQ.offset() % 64, 0);
+            assert!(COLD_STATS.offset() >= CPU_FREQ.offset() + 64);
+            // Initialized part
+            assert!(COLD_STATS.offset() < percpu_bss_size());
+            assert!(HOT_ARRAY.offset() >= percpu_bss_size());
+            assert!(HOT_ARRAY.offset() < COLD_FLAG.offset());
+        }
+        init_on(0);
+
+        HOT_TICKS.write_current(1);
+        CPU_FREQ.write_current(1000);
+        assert_eq!(CPU_FREQ.read_current(), 1000);
+        COLD_FLAG.write_current(0);
+        assert_eq!(HOT_TICKS.read_current(), 1);
+        assert_eq!(COLD_FLAG.read_current(), 0);
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+mod dump {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu]
+    static MAGIC: u64 = 0;
+
+    #[test]
+    fn test_dump_area() {
+        init_on(1);
+        MAGIC.write_current(0x1122_3344_5566_7788);
+
+        let mut out = String::new();
+        dump_area(1, &mut out).unwrap();
+        println!("{}", out);
+        assert!(out.starts_with(&format!(
+            "per-CPU data area of CPU 1 at {:#x}",
+            percpu_area_base(1)
+        )));
+        assert!(out.contains("88 77 66 55 44 33 22 11"));
+        #[cfg(feature = "introspect")]
+        assert!(out.contains(&format!("@{:#06x} MAGIC: u64 (8 bytes)", MAGIC.offset())));
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+mod no_remote {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu(no_remote)]
+    static TRAP_SCRATCH: [usize; 4] = [0; 4];
+
+    #[def_percpu(no_remote)]
+    static PRIVATE_IRQS: AtomicUsize = AtomicUsize::new(0);
+
+    #[test]
+    fn test_no_remote() {
+        init_on(1);
+
+        TRAP_SCRATCH.with_current(|scratch| scratch[0] = 42);
+        assert_eq!(TRAP_SCRATCH.read_current(), [42, 0, 0, 0]);
+        PRIVATE_IRQS.current().fetch_add(1, Ordering::Relaxed);
+        assert_eq!(PRIVATE_IRQS.current().load(Ordering::Relaxed), 1);
+
+        // The trait method still works for the current CPU.
+        let var: &dyn PerCpu<[usize; 4]> = &TRAP_SCRATCH;
+        assert_eq!(unsafe { *var.remote_ptr(1) }, [42, 0, 0, 0]);
+
+        // And is poisoned for other CPUs with debug assertions.
+        if cfg!(debug_assertions) {
+            let result =
+                std::panic::catch_unwind(|| unsafe { *PerCpu::remote_ptr(&TRAP_SCRATCH, 0) });
+            assert!(result.is_err());
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+mod subsection {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu(section = ".percpu.vm")]
+    static VM_EXITS: u64 = 0;
+
+    #[def_percpu(section = ".percpu.vm")]
+    static VM_REGS: [usize; 8] = [0; 8];
+
+    #[def_percpu]
+    static HOST_TICKS: usize = 0;
+
+    #[test]
+    fn test_subsection() {
+        init_on(0);
+
+        let range = percpu_area_range_of(".percpu.vm").unwrap();
+        println!("per-CPU subsection .percpu.vm: {:#x?}", range);
+        assert!(range.contains(&VM_EXITS.offset()));
+        assert!(range.contains(&VM_REGS.offset()));
+        assert!(!range.contains(&HOST_TICKS.offset()));
+        assert!(percpu_area_size_of(".percpu.vm") >= size_of::<u64>() + size_of::<[usize; 8]>());
+        assert!(range.end <= percpu_area_size());
+        assert_eq!(percpu_area_range_of(".percpu.none"), None);
+        assert_eq!(percpu_area_size_of(".percpu.none"), 0);
+
+        VM_EXITS.write_current(3);
+        VM_REGS.write_current_at(7, 0xdead);
+        set_local_thread_pointer(1);
+        VM_EXITS.write_current(5);
+        assert_eq!(VM_EXITS.read_remote(0), 3);
+        assert_eq!(VM_REGS.read_current_at(7), 0);
+        set_local_thread_pointer(0);
+        assert_eq!(VM_REGS.read_current_at(7), 0xdead);
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+mod offset_sym {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu(offset_sym)]
+    static CURRENT_TASK: usize = 0;
+
+    #[def_percpu(offset_sym = "PERCPU_OFF_KSTACK")]
+    static KERNEL_STACK_TOP: u64 = 0;
+
+    extern "C" {
+        static PERCPU_OFF_CURRENT_TASK: u8;
+        static PERCPU_OFF_KSTACK: u8;
+    }
+
+    #[test]
+    fn test_offset_sym() {
+        init_on(0);
+
+        let current_task_off = core::ptr::addr_of!(PERCPU_OFF_CURRENT_TASK) as usize;
+        let kstack_off = core::ptr::addr_of!(PERCPU_OFF_KSTACK) as usize;
+        assert_eq!(current_task_off, CURRENT_TASK.offset());
+        assert_eq!(kstack_off, KERNEL_STACK_TOP.offset());
+
+        // Access the per-CPU data in assembly code with the offset symbol.
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            core::arch::asm!("mov qword ptr gs:[PERCPU_OFF_CURRENT_TASK], {0}", in(reg) 0xdead_usize);
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        CURRENT_TASK.write_current(0xdead);
+        assert_eq!(CURRENT_TASK.read_current(), 0xdead);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod no_mangle {
+    use percpu::*;
+
+    #[no_mangle]
+    #[def_percpu]
+    static TIMER: usize = 0;
+
+    #[export_name = "percpu_test_symbols_tick"]
+    #[def_percpu]
+    static TICK: usize = 0;
+
+    #[def_percpu(inner_attrs(no_mangle))]
+    static IRQ_DEPTH: usize = 0;
+
+    // The per-CPU data is named `__PERCPU_TIMER` in Rust, so the symbols are declared in another module.
+    mod symbols {
+        extern "C" {
+            pub static __PERCPU_test_percpu_TIMER: usize;
+            pub static __PERCPU_TIMER: usize;
+            pub static percpu_test_symbols_tick: usize;
+            pub static __PERCPU_IRQ_DEPTH: usize;
+        }
+    }
+
+    #[test]
+    fn test_no_mangle_symbols() {
+        // The crate-qualified symbol and its weak alias are the same per-CPU data.
+        let sym = core::ptr::addr_of!(symbols::__PERCPU_test_percpu_TIMER) as usize;
+        let alias = core::ptr::addr_of!(symbols::__PERCPU_TIMER) as usize;
+        assert_eq!(sym, alias);
+        // The `.percpu` section is linked at address 0. With the "sp-naive" feature, the per-CPU data is thread-local
+        // instead in hosted mode.
+        #[cfg(not(feature = "sp-naive"))]
+        assert_eq!(sym, TIMER.offset());
+    }
+
+    #[test]
+    fn test_routed_symbols() {
+        let tick = core::ptr::addr_of!(symbols::percpu_test_symbols_tick) as usize;
+        let irq_depth = core::ptr::addr_of!(symbols::__PERCPU_IRQ_DEPTH) as usize;
+        assert_ne!(tick, irq_depth);
+        #[cfg(not(feature = "sp-naive"))]
+        {
+            assert_eq!(tick, TICK.offset());
+            assert_eq!(irq_depth, IRQ_DEPTH.offset());
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "introspect"))]
+mod layout {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu]
+    static IRQ_NUM: u32 = 0;
+
+    #[def_percpu]
+    static TRACE_BUF: [u8; 100] = [0; 100];
+
+    #[test]
+    fn test_layout() {
+        init_on(0);
+
+        for info in layout() {
+            println!("{:?}", info);
+        }
+        let info = layout().find(|info| info.name() == "TRACE_BUF").unwrap();
+        assert_eq!(info.size(), 100);
+        assert_eq!(info.offset(), TRACE_BUF.offset());
+        let info = layout().find(|info| info.name() == "IRQ_NUM").unwrap();
+        assert_eq!(info.type_name(), "u32");
+        assert_eq!(info.size(), 4);
+        // built-in per-CPU data
+        #[cfg(not(feature = "sp-naive"))]
+        assert!(layout().any(|info| info.name() == "CPU_ID"));
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "profile"))]
+mod profile {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu]
+    static HOT: usize = 0;
+
+    #[def_percpu]
+    static COLD: u32 = 0;
+
+    #[def_percpu]
+    static SAMPLES: [u64; 2] = [0; 2];
+
+    fn report(name: &str) -> &'static PercpuProfile {
+        profile_report().find(|p| p.name() == name).unwrap()
+    }
+
+    #[test]
+    fn test_profile() {
+        init_on(0);
+
+        for _ in 0..10 {
+            HOT.write_current(HOT.read_current() + 1);
+        }
+        HOT.add_current(1);
+        COLD.write_current(1);
+        SAMPLES.with_current(|v| v[1] = 2);
+        unsafe {
+            // Accesses of other CPUs are not counted.
+            *(HOT.remote_ptr(0) as *mut usize) = 0;
+        }
+
+        for p in profile_report() {
+            println!("{:?}", p);
+        }
+        assert_eq!(report("HOT").hits(0), 21);
+        assert_eq!(report("COLD").hits(0), 1);
+        assert_eq!(report("SAMPLES").hits(0), 1);
+        assert_eq!(report("HOT").total_hits(), 21);
+        // Internal per-CPU data is not profiled.
+        assert!(profile_report().all(|p| p.name() != "CPU_ID"));
+
+        #[cfg(not(feature = "sp-naive"))]
+        {
+            set_local_thread_pointer(1);
+            HOT.write_current(1);
+            assert_eq!(report("HOT").hits(0), 21);
+            assert_eq!(report("HOT").hits(1), 1);
+            assert_eq!(report("HOT").total_hits(), 22);
+            set_local_thread_pointer(0);
+        }
+
+        report("HOT").reset();
+        assert_eq!(report("HOT").total_hits(), 0);
+        assert_eq!(report("COLD").hits(0), 1);
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64", not(feature = "sp-naive")))]
+mod asm_access {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu(offset_sym)]
+    static SCRATCH: usize = 0;
+
+    core::arch::global_asm!(
+        ".globl percpu_test_load",
+        "percpu_test_load:",
+        percpu_asm_access!(load "rax", "PERCPU_OFF_SCRATCH", ""),
+        "ret",
+        ".globl percpu_test_store",
+        "percpu_test_store:",
+        percpu_asm_access!(store "rdi", "PERCPU_OFF_SCRATCH", "", ""),
+        "ret",
+    );
+
+    extern "C" {
+        fn percpu_test_load() -> usize;
+        fn percpu_test_store(val: usize);
+    }
+
+    #[test]
+    fn test_asm_access() {
+        init_on(2);
+
+        SCRATCH.write_current(0x1234);
+        assert_eq!(unsafe { percpu_test_load() }, 0x1234);
+        unsafe { percpu_test_store(0x5678) };
+        assert_eq!(SCRATCH.read_current(), 0x5678);
+        assert_eq!(SCRATCH.read_remote(2), 0x5678);
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+mod export_c {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu(export_c)]
+    static IRQ_COUNT: u32 = 0;
+
+    /// The C side, as declared in a header.
+    mod c {
+        extern "C" {
+            pub fn percpu_read_irq_count() -> u32;
+            pub fn percpu_write_irq_count(val: u32);
+            pub static PERCPU_OFF_IRQ_COUNT: u8;
+        }
+    }
+
+    #[test]
+    fn test_export_c() {
+        init_on(1);
+
+        unsafe {
+            c::percpu_write_irq_count(3);
+            assert_eq!(IRQ_COUNT.read_current(), 3);
+            IRQ_COUNT.write_current(4);
+            assert_eq!(c::percpu_read_irq_count(), 4);
+
+            let offset = core::ptr::addr_of!(c::PERCPU_OFF_IRQ_COUNT) as usize;
+            assert_eq!(offset, IRQ_COUNT.offset());
+        }
+    }
+}
+
+#[cfg(all(
+    target_os = "linux",
+    feature = "const-offset",
+    not(feature = "sp-naive")
+))]
+mod const_offset {
+    use percpu::*;
+
+    #[def_percpu]
+    static FOO: usize = 0;
+
+    #[def_percpu]
+    static BAR: u32 = 1;
+
+    struct Entry(*const ());
+
+    unsafe impl Sync for Entry {}
+
+    // The offsets are resolved at link time.
+    static TABLE: [Entry; 2] = [
+        Entry(FOO.offset_ptr().cast()),
+        Entry(BAR.offset_ptr().cast()),
+    ];
+
+    #[test]
+    fn test_const_offset() {
+        assert_eq!(TABLE[0].0 as usize, FOO.offset());
+        assert_eq!(TABLE[1].0 as usize, BAR.offset());
+    }
+}
+
+#[cfg(all(
+    target_os = "linux",
+    feature = "alternatives",
+    not(feature = "sp-naive")
+))]
+mod alternatives {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu]
+    static IRQS: usize = 0;
+
+    #[test]
+    fn test_alternatives() {
+        // The accessors on x86 always use the `gs` segment, so there is nothing to
+        // patch, and applying twice is harmless anyway.
+        assert_eq!(unsafe { apply_alternatives() }, 0);
+        assert_eq!(unsafe { apply_alternatives() }, 0);
+
+        init_on(1);
+        IRQS.write_current(3);
+        assert_eq!(IRQS.read_current(), 3);
+        assert_eq!(current_cpu_id(), 1);
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+mod reg_save {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu]
+    static VALUE: usize = 0;
+
+    #[test]
+    fn test_reg_save() {
+        // Set up the area of CPU 1 first.
+        init_on(1);
+        set_local_thread_pointer(0);
+        VALUE.write_current(1);
+        let host = get_local_thread_pointer();
+
+        {
+            let saved = scoped_reg_save();
+            assert_eq!(saved.saved(), host);
+            // Switch to the area of CPU 1, like a guest with its own per-CPU data.
+            let old = unsafe { swap_percpu_reg(percpu_area_base(1)) };
+            assert_eq!(old, host);
+            VALUE.write_current(2);
+        }
+
+        assert_eq!(get_local_thread_pointer(), host);
+        assert_eq!(VALUE.read_current(), 1);
+        assert_eq!(VALUE.read_remote(1), 2);
+    }
+}
+
+#[cfg(all(
+    target_os = "linux",
+    feature = "asm-free-offset",
+    not(feature = "sp-naive")
+))]
+mod asm_free_offset {
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu]
+    static A: u64 = 0;
+
+    #[def_percpu]
+    static B: [u8; 100] = [1; 100];
+
+    extern "C" {
+        static PERCPU_OFF_A: u8;
+    }
+
+    #[def_percpu(offset_sym = "PERCPU_OFF_A")]
+    static C: u64 = 0;
+
+    #[test]
+    fn test_asm_free_offset() {
+        init_on(1);
+
+        // The offsets are the same as the ones resolved by the linker.
+        assert_eq!(C.offset(), core::ptr::addr_of!(PERCPU_OFF_A) as usize);
+        for offset in [A.offset(), B.offset(), C.offset()] {
+            assert!(offset < percpu_area_size());
+        }
+        assert_eq!(A.offset() % 8, 0);
+
+        A.write_current(0x1234);
+        assert_eq!(A.read_remote(1), 0x1234);
+        assert_eq!(
+            unsafe { B.remote_ptr(1) } as usize,
+            percpu_area_base(1) + B.offset()
+        );
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "tls-compat", not(feature = "sp-naive")))]
+mod tls_compat {
+    use std::cell::Cell;
+
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu]
+    static SOFTIRQS: usize = 0;
+
+    std::thread_local! {
+        static TLS_VAR: Cell<usize> = const { Cell::new(0) };
+    }
+
+    #[test]
+    fn test_tls_compat() {
+        init_on(0);
+        TLS_VAR.with(|v| v.set(100));
+        SOFTIRQS.write_current(1);
+
+        // Each thread acts as a CPU, the per-CPU data and TLS do not interfere.
+        std::thread::scope(|s| {
+            for cpu_id in 1..4 {
+                s.spawn(move || {
+                    TLS_VAR.with(|v| v.set(cpu_id * 10));
+                    set_local_thread_pointer(cpu_id);
+                    SOFTIRQS.write_current(cpu_id + 1);
+                    assert_eq!(TLS_VAR.with(|v| v.get()), cpu_id * 10);
+                    assert_eq!(SOFTIRQS.read_current(), cpu_id + 1);
+                });
+            }
+        });
+
+        assert_eq!(TLS_VAR.with(|v| v.get()), 100);
+        assert_eq!(SOFTIRQS.read_current(), 1);
+        assert_eq!(get_local_thread_pointer(), percpu_area_base(0));
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+mod region {
+    use std::alloc::Layout;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use percpu::*;
+
+    use super::init_on;
+
+    #[def_percpu]
+    static EXITS: usize = 0;
+
+    #[def_percpu]
+    static SHADOW: [u64; 4] = [0; 4];
+
+    #[def_percpu]
+    static GENERATION: usize = 1;
+
+    static CURRENT_VCPU: AtomicUsize = AtomicUsize::new(0);
+
+    fn current_vcpu_id() -> usize {
+        CURRENT_VCPU.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn test_region() {
+        init_on(0);
+        EXITS.write_current(100);
+        GENERATION.write_current(5);
+
+        let host = PercpuRegion::global();
+        assert_eq!(host.num(), 4);
+        assert_eq!(host.area_base(1), percpu_area_base(1));
+        assert_eq!(host.current_area_base(), get_local_thread_pointer());
+        assert_eq!(unsafe { *host.current_ptr(&EXITS) }, 100);
+
+        let stride = percpu_area_stride();
+        let layout = Layout::from_size_align(stride * 3, 64).unwrap();
+        let start = unsafe { std::alloc::alloc_zeroed(layout) } as usize;
+        let vcpus = unsafe {
+            PercpuRegion::new(start, 3, stride, PercpuRegionBase::Index(current_vcpu_id))
+        };
+        assert_eq!(vcpus.size(), stride * 3);
+        vcpus.init();
+
+        // The zero-initialized per-CPU data is cleared, and the rest is copied
+        // from the initial per-CPU data, which is the one of CPU 0 in hosted mode.
+        for vcpu_id in 0..3 {
+            assert_eq!(unsafe { *vcpus.remote_ptr(&EXITS, vcpu_id) }, 0);
+            assert_eq!(unsafe { *vcpus.remote_ptr(&GENERATION, vcpu_id) }, 5);
+        }
+        CURRENT_VCPU.store(2, Ordering::Relaxed);
+        unsafe {
+            *(vcpus.current_ptr(&EXITS) as *mut usize) = 7;
+            (*(vcpus.current_ptr(&SHADOW) as *mut [u64; 4]))[3] = 0xdead;
+        }
+        assert_eq!(unsafe { *vcpus.remote_ptr(&EXITS, 2) }, 7);
+        assert_eq!(unsafe { *vcpus.remote_ptr(&SHADOW, 2) }, [0, 0, 0, 0xdead]);
+        assert_eq!(unsafe { *vcpus.remote_ptr(&EXITS, 1) }, 0);
+        assert_eq!(EXITS.read_current(), 100);
+        assert_eq!(SHADOW.read_current(), [0; 4]);
+
+        // The area can be installed in the per-CPU register, e.g., while running
+        // the vCPU.
+        {
+            let _saved = scoped_reg_save();
+            unsafe { swap_percpu_reg(vcpus.area_base(2)) };
+            assert_eq!(current_cpu_id(), 2);
+            assert_eq!(EXITS.read_current(), 7);
+            EXITS.write_current(8);
+        }
+        assert_eq!(EXITS.read_current(), 100);
+        assert_eq!(unsafe { *vcpus.remote_ptr(&EXITS, 2) }, 8);
+
+        vcpus.init_area(2);
+        assert_eq!(unsafe { *vcpus.remote_ptr(&EXITS, 2) }, 0);
+        unsafe { std::alloc::dealloc(start as *mut u8, layout) };
+    }
+}
@@ -0,0 +1,36 @@
+#![cfg(all(target_os = "linux", feature = "debug-preempt-check"))]
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use percpu::*;
+
+#[def_percpu]
+static U32: u32 = 0;
+
+#[def_percpu(unsafe(no_preempt_guard))]
+static TRAP_DEPTH: usize = 0;
+
+static PREEMPT_DISABLED: AtomicBool = AtomicBool::new(true);
+
+#[test]
+fn test_preempt_check() {
+    #[cfg(not(feature = "sp-naive"))]
+    {
+        init(4);
+        set_local_thread_pointer(0);
+    }
+
+    set_preempt_check_hook(|| PREEMPT_DISABLED.load(Ordering::Relaxed));
+    U32.write_current(1);
+    TRAP_DEPTH.write_current(1);
+    assert_eq!(unsafe { U32.read_current_raw() }, 1);
+
+    PREEMPT_DISABLED.store(false, Ordering::Relaxed);
+    let res = std::panic::catch_unwind(|| unsafe { U32.read_current_raw() });
+    assert!(res.is_err());
+    let res = std::panic::catch_unwind(|| unsafe { U32.current_ptr() });
+    assert!(res.is_err());
+    // The safe accessors without the preemption guard are checked as well.
+    let res = std::panic::catch_unwind(|| TRAP_DEPTH.read_current());
+    assert!(res.is_err());
+}
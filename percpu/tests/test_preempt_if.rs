@@ -0,0 +1,55 @@
+#![cfg(all(target_os = "linux", feature = "preempt-if", not(feature = "preempt")))]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use percpu::*;
+
+#[def_percpu]
+static STRUCT: (usize, u8) = (0, 0);
+
+#[def_percpu(unsafe(no_preempt_guard))]
+static TRAP_DEPTH: (usize, u8) = (0, 0);
+
+static PREEMPT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+struct PreemptGuardIfImpl;
+
+#[crate_interface::impl_interface]
+impl PreemptGuardIf for PreemptGuardIfImpl {
+    fn disable_preempt() {
+        PREEMPT_COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn enable_preempt() {
+        PREEMPT_COUNT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn test_preempt_if() {
+    #[cfg(not(feature = "sp-naive"))]
+    {
+        init(4);
+        set_local_thread_pointer(0);
+    }
+
+    STRUCT.with_current(|s| {
+        assert_eq!(PREEMPT_COUNT.load(Ordering::Relaxed), 1);
+        s.0 = 1;
+    });
+    assert_eq!(PREEMPT_COUNT.load(Ordering::Relaxed), 0);
+
+    {
+        let s = unsafe { STRUCT.current() };
+        assert_eq!(PREEMPT_COUNT.load(Ordering::Relaxed), 1);
+        assert_eq!(s.0, 1);
+    }
+    assert_eq!(PREEMPT_COUNT.load(Ordering::Relaxed), 0);
+
+    // No guard for the data only accessed with preemption disabled.
+    TRAP_DEPTH.with_current(|depth| {
+        assert_eq!(PREEMPT_COUNT.load(Ordering::Relaxed), 0);
+        depth.0 += 1;
+    });
+    assert_eq!(TRAP_DEPTH.read_current(), (1, 0));
+}
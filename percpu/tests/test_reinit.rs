@@ -0,0 +1,29 @@
+#![cfg(all(target_os = "linux", feature = "test-util", not(feature = "sp-naive")))]
+
+use percpu::*;
+
+#[def_percpu]
+static VALUE: usize = 0;
+
+#[test]
+fn test_reinit() {
+    assert!(!is_init());
+    init(2);
+    assert!(is_init());
+    set_local_thread_pointer(1);
+    VALUE.write_current(0xdead);
+
+    for num in [4, 3] {
+        unsafe { reinit_for_test() };
+        assert!(!is_init());
+        assert_eq!(percpu_area_num(), 0);
+
+        init(num);
+        set_local_thread_pointer(num - 1);
+        assert!(is_init());
+        assert_eq!(percpu_area_num(), num);
+        assert_eq!(VALUE.read_current(), 0); // the allocation starts zeroed
+        VALUE.write_current(num);
+        assert_eq!(VALUE.read_remote(num - 1), num);
+    }
+}
@@ -0,0 +1,42 @@
+#![cfg(all(target_os = "linux", not(feature = "sp-naive")))]
+
+use percpu::*;
+
+const STACK_SIZE: usize = 0x4000;
+const GUARD_SIZE: usize = 0x100;
+
+#[def_percpu]
+static VALUE: usize = 0;
+
+#[test]
+fn test_stack() {
+    assert!(std::panic::catch_unwind(|| set_percpu_stack_size(STACK_SIZE + 8, 0)).is_err());
+    set_percpu_stack_size(STACK_SIZE, GUARD_SIZE);
+    let stride = percpu_area_stride();
+    assert!(stride >= percpu_area_size() + GUARD_SIZE + STACK_SIZE);
+    assert_eq!(stride % 64, 0);
+
+    init(4);
+    for cpu_id in 0..4 {
+        set_local_thread_pointer(cpu_id);
+        VALUE.write_current(cpu_id + 1);
+    }
+    for cpu_id in 0..4 {
+        let base = percpu_area_base(cpu_id);
+        let top = stack_top(cpu_id, STACK_SIZE);
+        assert_eq!(top % 64, 0);
+        assert_eq!(top, base + stride);
+        // The guard gap is between the per-CPU data and the stack.
+        assert!(top - STACK_SIZE - GUARD_SIZE >= base + percpu_area_size());
+        unsafe { core::ptr::write_bytes((top - STACK_SIZE) as *mut u8, 0xcc, STACK_SIZE) };
+    }
+    for cpu_id in 0..4 {
+        assert_eq!(VALUE.read_remote(cpu_id), cpu_id + 1);
+    }
+    #[cfg(feature = "debug-canary")]
+    assert_eq!(check_canaries(), None);
+
+    assert_eq!(stack_top(1, 0x1000), stack_top(1, STACK_SIZE));
+    assert!(std::panic::catch_unwind(|| stack_top(0, STACK_SIZE * 2)).is_err());
+    assert!(std::panic::catch_unwind(|| set_percpu_stack_size(STACK_SIZE, 0)).is_err());
+}
@@ -0,0 +1,43 @@
+#![cfg(all(target_os = "linux", target_arch = "x86_64", not(feature = "sp-naive")))]
+
+use percpu::x86::is_kernel_gs;
+
+#[test]
+fn test_is_kernel_gs() {
+    assert!(!is_kernel_gs());
+    percpu::init(2);
+    assert!(!is_kernel_gs());
+    percpu::set_local_thread_pointer(1);
+    assert!(is_kernel_gs());
+}
+
+core::arch::global_asm!(
+    ".globl percpu_test_load_base",
+    "percpu_test_load_base:",
+    percpu::percpu_load_base!("rax"),
+    "ret",
+    // A fake trap frame with the saved `CS` of the kernel, so `swapgs` is skipped.
+    ".globl percpu_test_swapgs_from_kernel",
+    "percpu_test_swapgs_from_kernel:",
+    "sub rsp, 16",
+    "mov qword ptr [rsp + 8], 0x8",
+    percpu::percpu_swapgs_if_needed!(8),
+    percpu::percpu_load_base!("rax"),
+    "add rsp, 16",
+    "ret",
+);
+
+extern "C" {
+    fn percpu_test_load_base() -> usize;
+    fn percpu_test_swapgs_from_kernel() -> usize;
+}
+
+#[test]
+fn test_asm_fragments() {
+    percpu::init(4);
+    percpu::set_local_thread_pointer(3);
+
+    let base = percpu::percpu_area_base(3);
+    assert_eq!(unsafe { percpu_test_load_base() }, base);
+    assert_eq!(unsafe { percpu_test_swapgs_from_kernel() }, base);
+}
@@ -1,21 +1,173 @@
 use quote::{format_ident, quote};
 use syn::{Ident, Type};
 
-fn macos_unimplemented(item: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+use crate::RmwOp;
+
+/// The register that holds the per-CPU data area base on RISC-V. `gp` is used by default, since `tp` is used for
+/// thread-local storage, but it conflicts with the linker relaxation based on `__global_pointer$`. `tls-compat` takes
+/// precedence over `riscv-tp`, since `tp` is the TLS base.
+const RISCV_REG: &str = if cfg!(all(feature = "riscv-tp", not(feature = "tls-compat"))) {
+    "tp"
+} else {
+    "gp"
+};
+
+/// The thread ID register that holds the per-CPU data area base on AArch64, of the exception level the code runs at.
+/// We assume running in EL1 by default. `arm-el3` takes precedence over `arm-el2` if both are enabled.
+const AARCH64_TPIDR: &str = if cfg!(feature = "arm-el3") {
+    "TPIDR_EL3"
+} else if cfg!(feature = "arm-el2") {
+    "TPIDR_EL2"
+} else {
+    "TPIDR_EL1"
+};
+
+/// Whether the thread ID register on AArch64 is selected at runtime between `TPIDR_EL2` and `TPIDR_EL1`, by the
+/// `__PERCPU_ARM_EL2` symbol defined in crate `percpu`.
+const AARCH64_TPIDR_RUNTIME: bool =
+    cfg!(all(feature = "arm-el2-runtime", not(feature = "arm-el3")));
+
+/// Returns the instructions that read the per-CPU data area base on AArch64 into the operand `{0}`.
+///
+/// With the `alternatives` feature, the `mrs` instruction is recorded as a patch site of the kind `1` in the
+/// `percpu_alternatives` section, to be rewritten by `percpu::apply_alternatives`, instead of selecting the register
+/// by runtime branches.
+fn aarch64_read_tpidr() -> String {
+    if AARCH64_TPIDR_RUNTIME && cfg!(feature = "alternatives") {
+        [
+            "1:",
+            &format!("mrs {{0}}, {AARCH64_TPIDR}"),
+            ".pushsection percpu_alternatives, \"aR\"",
+            ".balign 4",
+            ".word 1b - .",
+            ".word 1",
+            ".popsection",
+        ]
+        .join("\n")
+    } else if AARCH64_TPIDR_RUNTIME {
+        [
+            "adrp {0}, __PERCPU_ARM_EL2",
+            "ldr {0}, [{0}, :lo12:__PERCPU_ARM_EL2]",
+            "cbnz {0}, 1f",
+            "mrs {0}, TPIDR_EL1",
+            "b 2f",
+            "1:",
+            "mrs {0}, TPIDR_EL2",
+            "2:",
+        ]
+        .join("\n")
+    } else {
+        format!("mrs {{0}}, {AARCH64_TPIDR}")
+    }
+}
+
+/// Runs `macos` or `windows` instead of `item` in hosted mode on macOS or Windows, where the thread pointer register
+/// can not be used. The per-CPU data is just a global variable on macOS like the `sp-naive` feature, and the per-CPU
+/// data area base is stored in a thread-local variable on Windows.
+///
+/// The `macos` code is also run under Miri, which can not interpret inline assembly, and on the architectures without
+/// a supported thread pointer register.
+fn gen_hosted_dispatch(
+    item: proc_macro2::TokenStream,
+    macos: proc_macro2::TokenStream,
+    windows: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let naive_cfg = crate::gen_naive_cfg();
     quote! {
         {
-            #[cfg(not(target_os = "macos"))]
+            #[cfg(not(any(#naive_cfg, target_os = "windows")))]
             { #item }
-            #[cfg(target_os = "macos")]
-            unimplemented!()
+            #[cfg(#naive_cfg)]
+            { #macos }
+            #[cfg(all(target_os = "windows", not(#naive_cfg)))]
+            { #windows }
         }
     }
 }
 
+/// Generate a code block that runs the given arch-specific code on the corresponding `target_arch`, and runs the
+/// `fallback` code on other architectures.
+///
+/// With the `pic` feature, the `fallback` code is run on all architectures, since the arch-specific code uses absolute
+/// relocations of the inner symbol.
+///
+/// The hit counter of the per-CPU variable is bumped before the arch-specific code with the `profile` feature.
+fn gen_arch_dispatch(
+    symbol: &Ident,
+    arch_code: Vec<(&str, proc_macro2::TokenStream)>,
+    fallback: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    if cfg!(feature = "pic") {
+        return quote! {
+            { #fallback }
+        };
+    }
+    let arches = arch_code.iter().map(|(arch, _)| arch).collect::<Vec<_>>();
+    // The fallback code accesses the per-CPU data by `current_ptr`, which bumps the hit counter by itself.
+    let profile_hit = crate::gen_profile_hit(symbol);
+    let blocks = arch_code.iter().map(|(arch, code)| {
+        quote! {
+            #[cfg(target_arch = #arch)]
+            { #profile_hit #code }
+        }
+    });
+    quote! {
+        #(#blocks)*
+        #[cfg(not(any(#(target_arch = #arches),*)))]
+        { #fallback }
+    }
+}
+
+/// Returns the register modifier, the memory operand size and the register class of a `gs`-relative instruction
+/// operand of the given type on x86_64 (or 32-bit x86 if `x86_64` is false).
+///
+/// Returns `None` if the type can not be accessed by one instruction, i.e., `u64` on 32-bit x86.
+fn x86_operand(ty_str: &str, x86_64: bool) -> Option<(&'static str, &'static str, Ident)> {
+    let (reg_mod, ptr, reg_class) = match ty_str {
+        "bool" | "u8" => ("", "byte", "reg_byte"),
+        "u16" => (":x", "word", "reg"),
+        "u32" => (":e", "dword", "reg"),
+        "u64" if x86_64 => (":r", "qword", "reg"),
+        "usize" if x86_64 => (":r", "qword", "reg"),
+        "usize" => (":e", "dword", "reg"),
+        "u64" => return None,
+        _ => unreachable!(),
+    };
+    Some((reg_mod, ptr, format_ident!("{}", reg_class)))
+}
+
+/// Returns the `gs`-relative `mov` instruction that loads (or stores if `store` is true) the per-CPU variable of the
+/// given type on x86_64 (or 32-bit x86 if `x86_64` is false), and the register class of the operand.
+///
+/// Returns `None` if the type can not be accessed by one instruction, i.e., `u64` on 32-bit x86.
+fn x86_mov_asm(ty_str: &str, x86_64: bool, store: bool) -> Option<(String, Ident)> {
+    let (reg_mod, ptr, reg_class) = x86_operand(ty_str, x86_64)?;
+    let asm = if store {
+        format!("mov {ptr} ptr gs:[offset {{VAR}}], {{0{reg_mod}}}")
+    } else {
+        format!("mov {{0{reg_mod}}}, {ptr} ptr gs:[offset {{VAR}}]")
+    };
+    Some((asm, reg_class))
+}
+
+/// Returns the `gs`-relative read-modify-write instruction `op` (e.g., `add` or `xadd`) whose destination is the
+/// per-CPU variable of the given type on x86_64 (or 32-bit x86 if `x86_64` is false), and the register class of the
+/// source operand.
+///
+/// Returns `None` if the type can not be accessed by one instruction, i.e., `u64` on 32-bit x86.
+fn x86_rmw_asm(op: &str, ty_str: &str, x86_64: bool) -> Option<(String, Ident)> {
+    let (reg_mod, ptr, reg_class) = x86_operand(ty_str, x86_64)?;
+    let asm = format!("{op} {ptr} ptr gs:[offset {{VAR}}], {{0{reg_mod}}}");
+    Some((asm, reg_class))
+}
+
 /// Generate a code block that calculates the offset of the per-CPU variable based on the inner symbol name.
+///
+/// With the `pic` or `asm-free-offset` feature, the offset is the distance between the inner symbol and the section
+/// start, without inline assembly.
 pub fn gen_offset(symbol: &Ident) -> proc_macro2::TokenStream {
     // the outer pair of braces is necessary to make the result an expression
-    quote! {
+    let offset = quote! {
         unsafe {
             let value: usize;
             #[cfg(target_arch = "x86_64")]
@@ -24,9 +176,25 @@ pub fn gen_offset(symbol: &Ident) -> proc_macro2::TokenStream {
                 out(reg) value,
                 VAR = sym #symbol,
             );
+            #[cfg(target_arch = "x86")]
+            ::core::arch::asm!(
+                "mov {0}, offset {VAR}",
+                out(reg) value,
+                VAR = sym #symbol,
+            );
+            // `abs_g1` is overflow-checked by the linker, so offsets larger than 4 GiB
+            // fail to link instead of being silently truncated.
             #[cfg(target_arch = "aarch64")]
             ::core::arch::asm!(
-                "movz {0}, #:abs_g0_nc:{VAR}",
+                "movz {0}, #:abs_g1:{VAR}",
+                "movk {0}, #:abs_g0_nc:{VAR}",
+                out(reg) value,
+                VAR = sym #symbol,
+            );
+            #[cfg(target_arch = "arm")]
+            ::core::arch::asm!(
+                "movw {0}, #:lower16:{VAR}",
+                "movt {0}, #:upper16:{VAR}",
                 out(reg) value,
                 VAR = sym #symbol,
             );
@@ -46,53 +214,86 @@ pub fn gen_offset(symbol: &Ident) -> proc_macro2::TokenStream {
             );
             value
         }
-    }
+    };
+    // Both the inner symbol and the section start are moved by the same distance when the code is relocated, so the
+    // difference between their (PC-relative) addresses is position-independent.
+    let pic_offset =
+        quote! { ::core::ptr::addr_of!(#symbol) as usize - percpu::__priv::percpu_section_start() };
+    gen_hosted_dispatch(
+        if cfg!(any(feature = "pic", feature = "asm-free-offset")) {
+            pic_offset.clone()
+        } else {
+            offset
+        },
+        quote! { ::core::ptr::addr_of!(#symbol) as usize },
+        pic_offset,
+    )
+}
+
+/// Generate the extra storage of the per-CPU data besides the inner symbol, which is only needed by the `sp-naive`
+/// feature.
+pub fn gen_thread_local(
+    _symbol: &Ident,
+    _storage_ty: &proc_macro2::TokenStream,
+    _storage_init: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    quote! {}
 }
 
 /// Generate a code block that calculates the pointer to the per-CPU variable on the current CPU, based on the inner
 /// symbol name and the type of the variable.
 pub fn gen_current_ptr(symbol: &Ident, ty: &Type) -> proc_macro2::TokenStream {
-    let aarch64_tpidr = if cfg!(feature = "arm-el2") {
-        "TPIDR_EL2"
-    } else {
-        // For ARM architecture, we assume running in EL1 by default,
-        // and use `TPIDR_EL1` to store the base address of the per-CPU data area.
-        "TPIDR_EL1"
-    };
-    let aarch64_asm = format!("mrs {{}}, {aarch64_tpidr}");
+    let aarch64_asm = aarch64_read_tpidr();
 
-    macos_unimplemented(quote! {
+    let current_ptr = quote! {
         let base: usize;
-        #[cfg(target_arch = "x86_64")]
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
         {
-            // `__PERCPU_SELF_PTR` stores GS_BASE, which is defined in crate `percpu`.
+            // `__PERCPU_percpu_SELF_PTR` stores GS_BASE, which is defined in crate `percpu`.
             ::core::arch::asm!(
-                "mov {0}, gs:[offset __PERCPU_SELF_PTR]",
+                "mov {0}, gs:[offset __PERCPU_percpu_SELF_PTR]",
                 "add {0}, offset {VAR}",
                 out(reg) base,
                 VAR = sym #symbol,
             );
             base as *const #ty
         }
-        #[cfg(not(target_arch = "x86_64"))]
+        #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
         {
             #[cfg(target_arch = "aarch64")]
             ::core::arch::asm!(#aarch64_asm, out(reg) base);
+            // `TPIDRPRW`, the PL1-only thread ID register.
+            #[cfg(target_arch = "arm")]
+            ::core::arch::asm!("mrc p15, 0, {}, c13, c0, 4", out(reg) base);
             #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
-            ::core::arch::asm!("mv {}, gp", out(reg) base);
+            ::core::arch::asm!(concat!("mv {}, ", #RISCV_REG), out(reg) base);
             #[cfg(any(target_arch = "loongarch64"))]
             ::core::arch::asm!("move {}, $r21", out(reg) base);
             (base + self.offset()) as *const #ty
         }
-    })
+    };
+    // The thread pointer is read without absolute relocations with the `pic` feature.
+    let tp_relative = quote! { (percpu::__priv::thread_pointer() + self.offset()) as *const #ty };
+    gen_hosted_dispatch(
+        if cfg!(feature = "pic") {
+            tp_relative.clone()
+        } else {
+            current_ptr
+        },
+        quote! { ::core::ptr::addr_of!(#symbol).cast::<#ty>() },
+        tp_relative,
+    )
 }
 
 /// Generate a code block that reads the value of the per-CPU variable on the current CPU, based on the inner symbol
 /// name and the type of the variable.
 ///
-/// The type of the variable must be one of the following: `bool`, `u8`, `u16`, `u32`, `u64`, or `usize`.
+/// The type of the variable must be one of the following: `bool`, `u8`, `u16`, `u32`, `u64`, `usize`, or `u128`.
 pub fn gen_read_current_raw(symbol: &Ident, ty: &Type) -> proc_macro2::TokenStream {
     let ty_str = quote!(#ty).to_string();
+    if ty_str == "u128" {
+        return gen_read_current_u128(symbol);
+    }
     let rv64_op = match ty_str.as_str() {
         "bool" => "lbu",
         "u8" => "lbu",
@@ -105,13 +306,34 @@ pub fn gen_read_current_raw(symbol: &Ident, ty: &Type) -> proc_macro2::TokenStre
     let rv64_asm = quote! {
         ::core::arch::asm!(
             "lui {0}, %hi({VAR})",
-            "add {0}, {0}, gp",
+            concat!("add {0}, {0}, ", #RISCV_REG),
             concat!(#rv64_op, " {0}, %lo({VAR})({0})"),
             out(reg) value,
             VAR = sym #symbol,
         )
     };
 
+    // 64-bit values can not be loaded by one instruction on RV32.
+    let rv32_op = match ty_str.as_str() {
+        "bool" => Some("lbu"),
+        "u8" => Some("lbu"),
+        "u16" => Some("lhu"),
+        "u32" => Some("lw"),
+        "usize" => Some("lw"),
+        _ => None,
+    };
+    let rv32_asm = rv32_op.map(|rv32_op| {
+        quote! {
+            ::core::arch::asm!(
+                "lui {0}, %hi({VAR})",
+                concat!("add {0}, {0}, ", #RISCV_REG),
+                concat!(#rv32_op, " {0}, %lo({VAR})({0})"),
+                out(reg) value,
+                VAR = sym #symbol,
+            )
+        }
+    });
+
     // https://loongson.github.io/LoongArch-Documentation/LoongArch-Vol1-EN.html#_ldx_buhuwud_stx_bhwd
     let la64_op = match ty_str.as_str() {
         "bool" => "ldx.bu",
@@ -132,27 +354,15 @@ pub fn gen_read_current_raw(symbol: &Ident, ty: &Type) -> proc_macro2::TokenStre
         )
     };
 
-    let (x64_asm, x64_reg) = if ["bool", "u8"].contains(&ty_str.as_str()) {
-        (
-            "mov {0}, byte ptr gs:[offset {VAR}]".into(),
-            format_ident!("reg_byte"),
-        )
-    } else {
-        let (x64_mod, x64_ptr) = match ty_str.as_str() {
-            "u16" => ("x", "word"),
-            "u32" => ("e", "dword"),
-            "u64" => ("r", "qword"),
-            "usize" => ("r", "qword"),
-            _ => unreachable!(),
-        };
-        (
-            format!("mov {{0:{x64_mod}}}, {x64_ptr} ptr gs:[offset {{VAR}}]"),
-            format_ident!("reg"),
-        )
-    };
+    let (x64_asm, x64_reg) = x86_mov_asm(&ty_str, true, false).unwrap();
     let x64_asm = quote! {
         ::core::arch::asm!(#x64_asm, out(#x64_reg) value, VAR = sym #symbol)
     };
+    let x86_asm = x86_mov_asm(&ty_str, false, false).map(|(x86_asm, x86_reg)| {
+        quote! {
+            ::core::arch::asm!(#x86_asm, out(#x86_reg) value, VAR = sym #symbol)
+        }
+    });
 
     let gen_code = |asm_stmt| {
         if ty_str.as_str() == "bool" {
@@ -170,27 +380,34 @@ pub fn gen_read_current_raw(symbol: &Ident, ty: &Type) -> proc_macro2::TokenStre
         }
     };
 
-    let rv64_code = gen_code(rv64_asm);
-    let la64_code = gen_code(la64_asm);
-    let x64_code = gen_code(x64_asm);
-    macos_unimplemented(quote! {
-        #[cfg(target_arch = "riscv64")]
-        { #rv64_code }
-        #[cfg(target_arch = "loongarch64")]
-        { #la64_code }
-        #[cfg(target_arch = "x86_64")]
-        { #x64_code }
-        #[cfg(not(any(target_arch = "riscv64", target_arch = "loongarch64", target_arch = "x86_64")))]
-        { *self.current_ptr() }
-    })
+    let mut arch_code = vec![
+        ("riscv64", gen_code(rv64_asm)),
+        ("loongarch64", gen_code(la64_asm)),
+        ("x86_64", gen_code(x64_asm)),
+    ];
+    if let Some(rv32_asm) = rv32_asm {
+        arch_code.push(("riscv32", gen_code(rv32_asm)));
+    }
+    if let Some(x86_asm) = x86_asm {
+        arch_code.push(("x86", gen_code(x86_asm)));
+    }
+    let fallback = quote! { *(self.current_ptr() as *const #ty) };
+    gen_hosted_dispatch(
+        gen_arch_dispatch(symbol, arch_code, fallback.clone()),
+        fallback.clone(),
+        fallback,
+    )
 }
 
 /// Generate a code block that writes the value of the per-CPU variable on the current CPU, based on the inner symbol
 /// name, the identifier of the value to write, and the type of the variable.
 ///
-/// The type of the variable must be one of the following: `bool`, `u8`, `u16`, `u32`, `u64`, or `usize`.
+/// The type of the variable must be one of the following: `bool`, `u8`, `u16`, `u32`, `u64`, `usize`, or `u128`.
 pub fn gen_write_current_raw(symbol: &Ident, val: &Ident, ty: &Type) -> proc_macro2::TokenStream {
     let ty_str = quote!(#ty).to_string();
+    if ty_str == "u128" {
+        return gen_write_current_u128(symbol, val);
+    }
     let ty_fixup = if ty_str.as_str() == "bool" {
         format_ident!("u8")
     } else {
@@ -209,7 +426,7 @@ pub fn gen_write_current_raw(symbol: &Ident, val: &Ident, ty: &Type) -> proc_mac
     let rv64_code = quote! {
         ::core::arch::asm!(
             "lui {0}, %hi({VAR})",
-            "add {0}, {0}, gp",
+            concat!("add {0}, {0}, ", #RISCV_REG),
             concat!(#rv64_op, " {1}, %lo({VAR})({0})"),
             out(reg) _,
             in(reg) #val as #ty_fixup,
@@ -217,6 +434,28 @@ pub fn gen_write_current_raw(symbol: &Ident, val: &Ident, ty: &Type) -> proc_mac
         );
     };
 
+    // 64-bit values can not be stored by one instruction on RV32.
+    let rv32_op = match ty_str.as_str() {
+        "bool" => Some("sb"),
+        "u8" => Some("sb"),
+        "u16" => Some("sh"),
+        "u32" => Some("sw"),
+        "usize" => Some("sw"),
+        _ => None,
+    };
+    let rv32_code = rv32_op.map(|rv32_op| {
+        quote! {
+            ::core::arch::asm!(
+                "lui {0}, %hi({VAR})",
+                concat!("add {0}, {0}, ", #RISCV_REG),
+                concat!(#rv32_op, " {1}, %lo({VAR})({0})"),
+                out(reg) _,
+                in(reg) #val as #ty_fixup,
+                VAR = sym #symbol,
+            );
+        }
+    });
+
     // https://loongson.github.io/LoongArch-Documentation/LoongArch-Vol1-EN.html#common-memory-access-instructions
     let la64_op = match ty_str.as_str() {
         "bool" => "stx.b",
@@ -238,36 +477,581 @@ pub fn gen_write_current_raw(symbol: &Ident, val: &Ident, ty: &Type) -> proc_mac
         );
     };
 
-    let (x64_asm, x64_reg) = if ["bool", "u8"].contains(&ty_str.as_str()) {
-        (
-            "mov byte ptr gs:[offset {VAR}], {0}".into(),
-            format_ident!("reg_byte"),
+    let (x64_asm, x64_reg) = x86_mov_asm(&ty_str, true, true).unwrap();
+    let x64_code = quote! {
+        ::core::arch::asm!(#x64_asm, in(#x64_reg) #val as #ty_fixup, VAR = sym #symbol)
+    };
+    let x86_code = x86_mov_asm(&ty_str, false, true).map(|(x86_asm, x86_reg)| {
+        quote! {
+            ::core::arch::asm!(#x86_asm, in(#x86_reg) #val as #ty_fixup, VAR = sym #symbol)
+        }
+    });
+
+    let mut arch_code = vec![
+        ("riscv64", rv64_code),
+        ("loongarch64", la64_code),
+        ("x86_64", x64_code),
+    ];
+    if let Some(rv32_code) = rv32_code {
+        arch_code.push(("riscv32", rv32_code));
+    }
+    if let Some(x86_code) = x86_code {
+        arch_code.push(("x86", x86_code));
+    }
+    let fallback = quote! { *(self.current_ptr() as *mut #ty) = #val };
+    gen_hosted_dispatch(
+        gen_arch_dispatch(symbol, arch_code, fallback.clone()),
+        fallback.clone(),
+        fallback,
+    )
+}
+
+/// Generate a code block that reads the `u128` per-CPU variable on the current CPU by two 64-bit loads (low half
+/// first), based on the inner symbol name.
+///
+/// It is not a single instruction, so the caller must disable preemption even on x86. Other architectures load it by
+/// the pointer, e.g., with one `ldp` on AArch64.
+fn gen_read_current_u128(symbol: &Ident) -> proc_macro2::TokenStream {
+    let gen_code = |asm_stmt| {
+        quote! {
+            let (lo, hi): (u64, u64);
+            #asm_stmt;
+            ((hi as u128) << 64) | lo as u128
+        }
+    };
+    let rv64_asm = quote! {
+        ::core::arch::asm!(
+            "lui {0}, %hi({VAR})",
+            concat!("add {0}, {0}, ", #RISCV_REG),
+            "addi {0}, {0}, %lo({VAR})",
+            "ld {1}, 0({0})",
+            "ld {2}, 8({0})",
+            out(reg) _,
+            out(reg) lo,
+            out(reg) hi,
+            VAR = sym #symbol,
         )
-    } else {
-        let (x64_mod, x64_ptr) = match ty_str.as_str() {
-            "u16" => ("x", "word"),
-            "u32" => ("e", "dword"),
-            "u64" => ("r", "qword"),
-            "usize" => ("r", "qword"),
-            _ => unreachable!(),
-        };
-        (
-            format!("mov {x64_ptr} ptr gs:[offset {{VAR}}], {{0:{x64_mod}}}"),
-            format_ident!("reg"),
+    };
+    let la64_asm = quote! {
+        ::core::arch::asm!(
+            "lu12i.w {0}, %abs_hi20({VAR})",
+            "ori {0}, {0}, %abs_lo12({VAR})",
+            "add.d {0}, {0}, $r21",
+            "ld.d {1}, {0}, 0",
+            "ld.d {2}, {0}, 8",
+            out(reg) _,
+            out(reg) lo,
+            out(reg) hi,
+            VAR = sym #symbol,
+        )
+    };
+    let x64_asm = quote! {
+        ::core::arch::asm!(
+            "mov {0}, qword ptr gs:[offset {VAR}]",
+            "mov {1}, qword ptr gs:[offset {VAR} + 8]",
+            out(reg) lo,
+            out(reg) hi,
+            VAR = sym #symbol,
         )
     };
+
+    let arch_code = vec![
+        ("riscv64", gen_code(rv64_asm)),
+        ("loongarch64", gen_code(la64_asm)),
+        ("x86_64", gen_code(x64_asm)),
+    ];
+    let fallback = quote! { *(self.current_ptr() as *const u128) };
+    gen_hosted_dispatch(
+        gen_arch_dispatch(symbol, arch_code, fallback.clone()),
+        fallback.clone(),
+        fallback,
+    )
+}
+
+/// Generate a code block that writes the `u128` per-CPU variable on the current CPU by two 64-bit stores (low half
+/// first), based on the inner symbol name and the identifier of the value to write.
+///
+/// It is not a single instruction, so the caller must disable preemption even on x86. Other architectures store it by
+/// the pointer, e.g., with one `stp` on AArch64.
+fn gen_write_current_u128(symbol: &Ident, val: &Ident) -> proc_macro2::TokenStream {
+    let rv64_code = quote! {
+        ::core::arch::asm!(
+            "lui {0}, %hi({VAR})",
+            concat!("add {0}, {0}, ", #RISCV_REG),
+            "addi {0}, {0}, %lo({VAR})",
+            "sd {1}, 0({0})",
+            "sd {2}, 8({0})",
+            out(reg) _,
+            in(reg) #val as u64,
+            in(reg) (#val >> 64) as u64,
+            VAR = sym #symbol,
+        );
+    };
+    let la64_code = quote! {
+        ::core::arch::asm!(
+            "lu12i.w {0}, %abs_hi20({VAR})",
+            "ori {0}, {0}, %abs_lo12({VAR})",
+            "add.d {0}, {0}, $r21",
+            "st.d {1}, {0}, 0",
+            "st.d {2}, {0}, 8",
+            out(reg) _,
+            in(reg) #val as u64,
+            in(reg) (#val >> 64) as u64,
+            VAR = sym #symbol,
+        );
+    };
     let x64_code = quote! {
-        ::core::arch::asm!(#x64_asm, in(#x64_reg) #val as #ty_fixup, VAR = sym #symbol)
+        ::core::arch::asm!(
+            "mov qword ptr gs:[offset {VAR}], {0}",
+            "mov qword ptr gs:[offset {VAR} + 8], {1}",
+            in(reg) #val as u64,
+            in(reg) (#val >> 64) as u64,
+            VAR = sym #symbol,
+        )
+    };
+
+    let arch_code = vec![
+        ("riscv64", rv64_code),
+        ("loongarch64", la64_code),
+        ("x86_64", x64_code),
+    ];
+    let fallback = quote! { *(self.current_ptr() as *mut u128) = #val };
+    gen_hosted_dispatch(
+        gen_arch_dispatch(symbol, arch_code, fallback.clone()),
+        fallback.clone(),
+        fallback,
+    )
+}
+
+/// Returns the suffix of the AMO instructions (e.g., `amoadd` on RISC-V or `amadd` on LoongArch) that operate on a
+/// 32-bit or 64-bit value, or `None` for other types.
+fn amo_suffix(ty_str: &str, rv32: bool) -> Option<&'static str> {
+    match ty_str {
+        "u32" => Some(".w"),
+        "usize" if rv32 => Some(".w"),
+        "u64" | "usize" if !rv32 => Some(".d"),
+        _ => None,
+    }
+}
+
+/// Generate a code block that applies the read-modify-write operation with the value to the per-CPU variable on the
+/// current CPU, based on the inner symbol name, the identifier of the value, and the type of the variable.
+///
+/// On x86, it is a single `gs`-relative instruction, which can not be interrupted halfway, so the `guard` is only
+/// placed on other architectures.
+///
+/// The type of the variable must be one of the following: `u8`, `u16`, `u32`, `u64`, or `usize`.
+pub fn gen_rmw_current(
+    op: RmwOp,
+    symbol: &Ident,
+    val: &Ident,
+    ty: &Type,
+    guard: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let ty_str = quote!(#ty).to_string();
+    let (x86_op, rv_op, la_op) = match op {
+        RmwOp::Add => ("add", "amoadd", "amadd"),
+        RmwOp::Or => ("or", "amoor", "amor"),
+        RmwOp::And => ("and", "amoand", "amand"),
+    };
+    let mut arch_code = vec![];
+
+    for (arch, x86_64) in [("x86_64", true), ("x86", false)] {
+        if let Some((asm, reg)) = x86_rmw_asm(x86_op, &ty_str, x86_64) {
+            let code = quote! {
+                unsafe { ::core::arch::asm!(#asm, in(#reg) #val, VAR = sym #symbol) }
+            };
+            arch_code.push((arch, code));
+        }
+    }
+    for (arch, rv32) in [("riscv64", false), ("riscv32", true)] {
+        if let Some(suffix) = amo_suffix(&ty_str, rv32) {
+            let code = quote! {
+                #guard
+                unsafe {
+                    ::core::arch::asm!(
+                        "lui {0}, %hi({VAR})",
+                        "addi {0}, {0}, %lo({VAR})",
+                        concat!("add {0}, {0}, ", #RISCV_REG),
+                        concat!(#rv_op, #suffix, " zero, {1}, ({0})"),
+                        out(reg) _,
+                        in(reg) #val,
+                        VAR = sym #symbol,
+                    )
+                }
+            };
+            arch_code.push((arch, code));
+        }
+    }
+    if let Some(suffix) = amo_suffix(&ty_str, false) {
+        let code = quote! {
+            #guard
+            unsafe {
+                ::core::arch::asm!(
+                    "lu12i.w {0}, %abs_hi20({VAR})",
+                    "ori {0}, {0}, %abs_lo12({VAR})",
+                    "add.d {0}, {0}, $r21",
+                    concat!(#la_op, #suffix, " $zero, {1}, {0}"),
+                    out(reg) _,
+                    in(reg) #val,
+                    VAR = sym #symbol,
+                )
+            }
+        };
+        arch_code.push(("loongarch64", code));
+    }
+
+    let assign = op.assign(quote!(*ptr), val);
+    let fallback = quote! {
+        #guard
+        unsafe {
+            let ptr = self.current_ptr() as *mut #ty;
+            #assign;
+        }
+    };
+    gen_hosted_dispatch(
+        gen_arch_dispatch(symbol, arch_code, fallback.clone()),
+        fallback.clone(),
+        fallback,
+    )
+}
+
+/// Generate a code block that adds the value to the per-CPU variable on the current CPU (wrapping around on
+/// overflow), and returns the previous value, based on the inner symbol name, the identifier of the value to add, and
+/// the type of the variable.
+///
+/// On x86, it is a single `gs`-relative `xadd` instruction, which can not be interrupted halfway, so the `guard` is
+/// only placed on other architectures.
+///
+/// The type of the variable must be one of the following: `u8`, `u16`, `u32`, `u64`, or `usize`.
+pub fn gen_fetch_add_current(
+    symbol: &Ident,
+    val: &Ident,
+    ty: &Type,
+    guard: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let ty_str = quote!(#ty).to_string();
+    let mut arch_code = vec![];
+
+    for (arch, x86_64) in [("x86_64", true), ("x86", false)] {
+        if let Some((asm, reg)) = x86_rmw_asm("xadd", &ty_str, x86_64) {
+            let code = quote! {
+                let mut value: #ty = #val;
+                unsafe { ::core::arch::asm!(#asm, inout(#reg) value, VAR = sym #symbol) };
+                value
+            };
+            arch_code.push((arch, code));
+        }
+    }
+    for (arch, rv32) in [("riscv64", false), ("riscv32", true)] {
+        if let Some(suffix) = amo_suffix(&ty_str, rv32) {
+            let code = quote! {
+                #guard
+                let value: #ty;
+                unsafe {
+                    ::core::arch::asm!(
+                        "lui {0}, %hi({VAR})",
+                        "addi {0}, {0}, %lo({VAR})",
+                        concat!("add {0}, {0}, ", #RISCV_REG),
+                        concat!("amoadd", #suffix, " {1}, {2}, ({0})"),
+                        out(reg) _,
+                        out(reg) value,
+                        in(reg) #val,
+                        VAR = sym #symbol,
+                    )
+                };
+                value
+            };
+            arch_code.push((arch, code));
+        }
+    }
+    if let Some(suffix) = amo_suffix(&ty_str, false) {
+        let code = quote! {
+            #guard
+            let value: #ty;
+            unsafe {
+                ::core::arch::asm!(
+                    "lu12i.w {0}, %abs_hi20({VAR})",
+                    "ori {0}, {0}, %abs_lo12({VAR})",
+                    "add.d {0}, {0}, $r21",
+                    concat!("amadd", #suffix, " {1}, {2}, {0}"),
+                    out(reg) _,
+                    out(reg) value,
+                    in(reg) #val,
+                    VAR = sym #symbol,
+                )
+            };
+            value
+        };
+        arch_code.push(("loongarch64", code));
+    }
+
+    let fallback = quote! {
+        #guard
+        unsafe {
+            let ptr = self.current_ptr() as *mut #ty;
+            let value = *ptr;
+            *ptr = value.wrapping_add(#val);
+            value
+        }
     };
+    gen_hosted_dispatch(
+        gen_arch_dispatch(symbol, arch_code, fallback.clone()),
+        fallback.clone(),
+        fallback,
+    )
+}
+
+/// Generate a code block that swaps the value with the per-CPU variable on the current CPU, and returns the previous
+/// value, based on the inner symbol name, the identifier of the value, and the type of the variable.
+///
+/// On x86, it is a single `gs`-relative `xchg` instruction, which can not be interrupted halfway, so the `guard` is
+/// only placed on other architectures.
+///
+/// The type of the variable must be one of the following: `u8`, `u16`, `u32`, `u64`, or `usize`.
+pub fn gen_swap_current(
+    symbol: &Ident,
+    val: &Ident,
+    ty: &Type,
+    guard: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let ty_str = quote!(#ty).to_string();
+    let mut arch_code = vec![];
+
+    for (arch, x86_64) in [("x86_64", true), ("x86", false)] {
+        if let Some((asm, reg)) = x86_rmw_asm("xchg", &ty_str, x86_64) {
+            let code = quote! {
+                let mut value: #ty = #val;
+                unsafe { ::core::arch::asm!(#asm, inout(#reg) value, VAR = sym #symbol) };
+                value
+            };
+            arch_code.push((arch, code));
+        }
+    }
+    for (arch, rv32) in [("riscv64", false), ("riscv32", true)] {
+        if let Some(suffix) = amo_suffix(&ty_str, rv32) {
+            let code = quote! {
+                #guard
+                let value: #ty;
+                unsafe {
+                    ::core::arch::asm!(
+                        "lui {0}, %hi({VAR})",
+                        "addi {0}, {0}, %lo({VAR})",
+                        concat!("add {0}, {0}, ", #RISCV_REG),
+                        concat!("amoswap", #suffix, " {1}, {2}, ({0})"),
+                        out(reg) _,
+                        out(reg) value,
+                        in(reg) #val,
+                        VAR = sym #symbol,
+                    )
+                };
+                value
+            };
+            arch_code.push((arch, code));
+        }
+    }
 
-    macos_unimplemented(quote! {
-        #[cfg(target_arch = "riscv64")]
-        { #rv64_code }
-        #[cfg(target_arch = "loongarch64")]
-        { #la64_code }
-        #[cfg(target_arch = "x86_64")]
-        { #x64_code }
-        #[cfg(not(any(target_arch = "riscv64", target_arch = "loongarch64", target_arch = "x86_64")))]
-        { *(self.current_ptr() as *mut #ty) = #val }
-    })
+    let fallback = quote! {
+        #guard
+        unsafe { (self.current_ptr() as *mut #ty).replace(#val) }
+    };
+    gen_hosted_dispatch(
+        gen_arch_dispatch(symbol, arch_code, fallback.clone()),
+        fallback.clone(),
+        fallback,
+    )
+}
+
+/// Generate a code block that stores `new` to the per-CPU variable on the current CPU if its value is `old`, and
+/// returns the previous value in `Ok` if it is stored, or in `Err` otherwise, based on the inner symbol name, the
+/// identifiers of the values, and the type of the variable.
+///
+/// On x86, it is a single `gs`-relative `cmpxchg` instruction, which can not be interrupted halfway, so the `guard` is
+/// only placed on other architectures. On RISC-V, it is an LR/SC loop.
+///
+/// The type of the variable must be one of the following: `u8`, `u16`, `u32`, `u64`, or `usize`.
+pub fn gen_compare_exchange_current(
+    symbol: &Ident,
+    old: &Ident,
+    new: &Ident,
+    ty: &Type,
+    guard: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let ty_str = quote!(#ty).to_string();
+    let mut arch_code = vec![];
+
+    for (arch, x86_64) in [("x86_64", true), ("x86", false)] {
+        if let Some((reg_mod, ptr, reg_class)) = x86_operand(&ty_str, x86_64) {
+            // The comparand is implicitly in the accumulator.
+            let acc = match ptr {
+                "byte" => "al",
+                "word" => "ax",
+                "dword" => "eax",
+                _ => "rax",
+            };
+            let asm = format!("cmpxchg {ptr} ptr gs:[offset {{VAR}}], {{0{reg_mod}}}");
+            let code = quote! {
+                let value: #ty;
+                unsafe {
+                    ::core::arch::asm!(#asm, in(#reg_class) #new, inout(#acc) #old => value, VAR = sym #symbol)
+                };
+                value
+            };
+            arch_code.push((arch, code));
+        }
+    }
+    for (arch, rv32) in [("riscv64", false), ("riscv32", true)] {
+        if let Some(suffix) = amo_suffix(&ty_str, rv32) {
+            // `lr.w` sign-extends the loaded value on RV64, so does the comparand.
+            let sext = if !rv32 && suffix == ".w" {
+                quote! { "sext.w {2}, {2}", }
+            } else {
+                quote! {}
+            };
+            let code = quote! {
+                #guard
+                let value: #ty;
+                unsafe {
+                    ::core::arch::asm!(
+                        "lui {0}, %hi({VAR})",
+                        "addi {0}, {0}, %lo({VAR})",
+                        concat!("add {0}, {0}, ", #RISCV_REG),
+                        #sext
+                        "1:",
+                        concat!("lr", #suffix, " {1}, ({0})"),
+                        "bne {1}, {2}, 2f",
+                        concat!("sc", #suffix, " {4}, {3}, ({0})"),
+                        "bnez {4}, 1b",
+                        "2:",
+                        out(reg) _,
+                        out(reg) value,
+                        inout(reg) #old => _,
+                        in(reg) #new,
+                        out(reg) _,
+                        VAR = sym #symbol,
+                    )
+                };
+                value
+            };
+            arch_code.push((arch, code));
+        }
+    }
+
+    let fallback = quote! {
+        #guard
+        unsafe {
+            let ptr = self.current_ptr() as *mut #ty;
+            let value = *ptr;
+            if value == #old {
+                *ptr = #new;
+            }
+            value
+        }
+    };
+    let value = gen_hosted_dispatch(
+        gen_arch_dispatch(symbol, arch_code, fallback.clone()),
+        fallback.clone(),
+        fallback,
+    );
+    quote! {
+        let value: #ty = #value;
+        if value == #old {
+            Ok(value)
+        } else {
+            Err(value)
+        }
+    }
+}
+
+/// Generate a code block that reads the element at `index` of the per-CPU array on the current CPU, based on the inner
+/// symbol name, the identifier of the index, and the type of the elements.
+///
+/// On x86, it is a single `gs`-relative `mov` instruction with the scaled index, so the `guard` is only placed on
+/// other architectures. The index must have been checked.
+///
+/// The type of the elements must be one of the following: `u8`, `u16`, `u32`, `u64`, or `usize`.
+pub fn gen_read_current_at(
+    symbol: &Ident,
+    index: &Ident,
+    elem_ty: &Type,
+    guard: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let ty_str = quote!(#elem_ty).to_string();
+    let mut arch_code = vec![];
+    for (arch, x86_64) in [("x86_64", true), ("x86", false)] {
+        if let Some((reg_mod, ptr, reg_class)) = x86_operand(&ty_str, x86_64) {
+            let asm =
+                format!("mov {{0{reg_mod}}}, {ptr} ptr gs:[{{1}} * {{SIZE}} + offset {{VAR}}]");
+            let code = quote! {
+                let value: #elem_ty;
+                unsafe {
+                    ::core::arch::asm!(
+                        #asm,
+                        out(#reg_class) value,
+                        in(reg) #index,
+                        SIZE = const ::core::mem::size_of::<#elem_ty>(),
+                        VAR = sym #symbol,
+                    )
+                };
+                value
+            };
+            arch_code.push((arch, code));
+        }
+    }
+
+    let fallback = quote! {
+        #guard
+        unsafe { (self.current_ptr() as *const #elem_ty).add(#index).read() }
+    };
+    gen_hosted_dispatch(
+        gen_arch_dispatch(symbol, arch_code, fallback.clone()),
+        fallback.clone(),
+        fallback,
+    )
+}
+
+/// Generate a code block that writes the element at `index` of the per-CPU array on the current CPU, based on the
+/// inner symbol name, the identifiers of the index and the value, and the type of the elements.
+///
+/// On x86, it is a single `gs`-relative `mov` instruction with the scaled index, so the `guard` is only placed on
+/// other architectures. The index must have been checked.
+///
+/// The type of the elements must be one of the following: `u8`, `u16`, `u32`, `u64`, or `usize`.
+pub fn gen_write_current_at(
+    symbol: &Ident,
+    index: &Ident,
+    val: &Ident,
+    elem_ty: &Type,
+    guard: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let ty_str = quote!(#elem_ty).to_string();
+    let mut arch_code = vec![];
+    for (arch, x86_64) in [("x86_64", true), ("x86", false)] {
+        if let Some((reg_mod, ptr, reg_class)) = x86_operand(&ty_str, x86_64) {
+            let asm =
+                format!("mov {ptr} ptr gs:[{{1}} * {{SIZE}} + offset {{VAR}}], {{0{reg_mod}}}");
+            let code = quote! {
+                unsafe {
+                    ::core::arch::asm!(
+                        #asm,
+                        in(#reg_class) #val,
+                        in(reg) #index,
+                        SIZE = const ::core::mem::size_of::<#elem_ty>(),
+                        VAR = sym #symbol,
+                    )
+                }
+            };
+            arch_code.push((arch, code));
+        }
+    }
+
+    let fallback = quote! {
+        #guard
+        unsafe { (self.current_ptr() as *mut #elem_ty).add(#index).write(#val) }
+    };
+    gen_hosted_dispatch(
+        gen_arch_dispatch(symbol, arch_code, fallback.clone()),
+        fallback.clone(),
+        fallback,
+    )
 }
@@ -0,0 +1,225 @@
+//! Arguments of the `def_percpu` attribute.
+
+use proc_macro::TokenStream;
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{parse_quote, Attribute, Lit, Meta, Result, Token};
+
+/// The cache line size assumed by `align = "cacheline"`.
+const CACHE_LINE_SIZE: usize = 64;
+/// The maximum alignment, which should not exceed the alignment of each per-CPU data area.
+const MAX_ALIGN: usize = 64;
+
+/// Arguments of the `def_percpu` attribute, e.g., `#[def_percpu(lazy)]`.
+#[derive(Default)]
+pub struct PercpuArgs {
+    /// `lazy`: the initialization expression is evaluated on the first access on each CPU.
+    pub lazy: bool,
+    /// `align = N` or `align = "cacheline"`: the per-CPU data is aligned to (and padded to a multiple of) `N` bytes.
+    pub align: Option<usize>,
+    /// `offset_sym` or `offset_sym = "NAME"`: a global symbol (`PERCPU_OFF_X` by default) whose value is the offset of
+    /// the per-CPU data, for assembly code.
+    pub offset_sym: Option<OffsetSym>,
+    /// `inner_attrs(...)`: symbol attributes applied to the inner symbol `__PERCPU_X` only, as they are.
+    pub inner_attrs: Vec<Attribute>,
+    /// `hot`, `cold` or `read_mostly`: the subsection where the per-CPU data is placed.
+    pub placement: Option<Placement>,
+    /// `section = ".percpu.NAME"`: the user-named subsection where the per-CPU data is placed, which can be managed
+    /// separately, e.g., a hypervisor-only region.
+    pub section: Option<String>,
+    /// `debug`: the wrapper struct implements `Debug`, printing the value on every CPU.
+    pub debug: bool,
+    /// `export_c`: `extern "C"` accessors `percpu_read_x` and `percpu_write_x` are exported, with the offset symbol.
+    pub export_c: bool,
+    /// `no_remote`: the accessors of the per-CPU data on other CPUs are not generated, for CPU-private data.
+    pub no_remote: bool,
+    /// `unsafe(no_preempt_guard)`: the accessors of the current CPU do not disable preemption, for data only accessed
+    /// with preemption (or IRQs) disabled.
+    pub no_preempt_guard: bool,
+}
+
+/// The placement of the per-CPU data in the per-CPU data area.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// `hot`: placed at the beginning of the area, so frequently accessed data share a few cache lines.
+    Hot,
+    /// `cold`: placed at the end of the area.
+    Cold,
+    /// `read_mostly`: placed in separate cache lines, so remote readers do not contend with local writers.
+    ReadMostly,
+}
+
+impl Placement {
+    /// Returns the name of the subsection, e.g., `.percpu.hot`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Placement::Hot => "hot",
+            Placement::Cold => "cold",
+            Placement::ReadMostly => "read_mostly",
+        }
+    }
+}
+
+/// The name of the offset symbol given by the `offset_sym` argument.
+pub enum OffsetSym {
+    /// `offset_sym`: the default name `PERCPU_OFF_X`.
+    Default,
+    /// `offset_sym = "NAME"`: the given name.
+    Named(String),
+}
+
+impl PercpuArgs {
+    pub fn parse(attr: TokenStream) -> Result<Self> {
+        let mut args = Self::default();
+        let mut export_c_span = None;
+        let mut section_span = None;
+        let mut no_remote_span = None;
+        let parser = syn::meta::parser(|meta| {
+            if meta.path.is_ident("lazy") {
+                args.lazy = true;
+                Ok(())
+            } else if meta.path.is_ident("align") {
+                let align = match meta.value()?.parse()? {
+                    Lit::Int(int) => int.base10_parse()?,
+                    Lit::Str(s) if s.value() == "cacheline" => CACHE_LINE_SIZE,
+                    lit => {
+                        return Err(syn::Error::new(
+                            lit.span(),
+                            "expected an integer or \"cacheline\"",
+                        ))
+                    }
+                };
+                if !align.is_power_of_two() || align > MAX_ALIGN {
+                    return Err(meta.error(format!(
+                        "alignment must be a power of two no greater than {MAX_ALIGN}"
+                    )));
+                }
+                args.align = Some(align);
+                Ok(())
+            } else if meta.path.is_ident("offset_sym") {
+                args.offset_sym = Some(if meta.input.peek(syn::Token![=]) {
+                    let name: syn::LitStr = meta.value()?.parse()?;
+                    if !is_asm_symbol(&name.value()) {
+                        return Err(syn::Error::new(name.span(), "invalid symbol name"));
+                    }
+                    OffsetSym::Named(name.value())
+                } else {
+                    OffsetSym::Default
+                });
+                Ok(())
+            } else if let Some(placement) = [Placement::Hot, Placement::Cold, Placement::ReadMostly]
+                .into_iter()
+                .find(|placement| meta.path.is_ident(placement.name()))
+            {
+                if args.placement.is_some() {
+                    return Err(meta.error(
+                        "conflicting placement, `hot`, `cold` and `read_mostly` are exclusive",
+                    ));
+                }
+                args.placement = Some(placement);
+                Ok(())
+            } else if meta.path.is_ident("section") {
+                let name: syn::LitStr = meta.value()?.parse()?;
+                if !is_custom_section(&name.value()) {
+                    return Err(syn::Error::new(
+                        name.span(),
+                        "invalid section name, expected `.percpu.NAME` where `NAME` is an identifier other than \
+                         `bss`, `hot`, `cold`, `read_mostly` or `alignN`",
+                    ));
+                }
+                args.section = Some(name.value());
+                section_span = Some(meta.path.span());
+                Ok(())
+            } else if meta.path.is_ident("debug") {
+                args.debug = true;
+                Ok(())
+            } else if meta.path.is_ident("export_c") {
+                args.export_c = true;
+                export_c_span = Some(meta.path.span());
+                Ok(())
+            } else if meta.path.is_ident("no_remote") {
+                args.no_remote = true;
+                no_remote_span = Some(meta.path.span());
+                Ok(())
+            } else if meta.path.is_ident("unsafe") {
+                // The safe accessors rely on the caller to keep the task on the current CPU, so the opt-in is spelled
+                // `unsafe`, like `#[unsafe(no_mangle)]`.
+                meta.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("no_preempt_guard") {
+                        args.no_preempt_guard = true;
+                        Ok(())
+                    } else {
+                        Err(meta.error("unsupported unsafe argument, expected `no_preempt_guard`"))
+                    }
+                })
+            } else if meta.path.is_ident("no_preempt_guard") {
+                Err(meta.error(
+                    "`no_preempt_guard` must be written as `unsafe(no_preempt_guard)`, since the caller must keep \
+                     preemption disabled",
+                ))
+            } else if meta.path.is_ident("inner_attrs") {
+                let content;
+                syn::parenthesized!(content in meta.input);
+                for meta in Punctuated::<Meta, Token![,]>::parse_terminated(&content)? {
+                    let attr: Attribute = parse_quote!(#[#meta]);
+                    if crate::symbol_attr_name(&attr).is_none_or(|name| name == "link_section") {
+                        return Err(syn::Error::new_spanned(
+                            meta,
+                            "unsupported attribute, expected `no_mangle`, `export_name` or `used`",
+                        ));
+                    }
+                    args.inner_attrs.push(attr);
+                }
+                Ok(())
+            } else {
+                Err(meta.error(
+                    "unsupported argument, expected `lazy`, `align`, `offset_sym`, `inner_attrs`, `hot`, `cold`, \
+                     `read_mostly`, `section`, `debug`, `export_c`, `no_remote` or `unsafe(no_preempt_guard)`",
+                ))
+            }
+        });
+        parser.parse(attr)?;
+        if let Some(span) = section_span.filter(|_| args.placement.is_some()) {
+            return Err(syn::Error::new(
+                span,
+                "`section` can not be used with `hot`, `cold` or `read_mostly`",
+            ));
+        }
+        if let Some(span) = export_c_span.filter(|_| args.lazy) {
+            return Err(syn::Error::new(
+                span,
+                "`export_c` is not supported for `lazy` per-CPU data",
+            ));
+        }
+        if let Some(span) = no_remote_span.filter(|_| args.debug) {
+            return Err(syn::Error::new(
+                span,
+                "`no_remote` can not be used with `debug`, which reads the per-CPU data on all CPUs",
+            ));
+        }
+        if args.export_c && args.offset_sym.is_none() {
+            args.offset_sym = Some(OffsetSym::Default);
+        }
+        Ok(args)
+    }
+}
+
+/// Whether `name` is a valid user-named subsection, i.e., `.percpu.NAME` where `NAME` is a C identifier that does not
+/// clash with the subsections generated by the macro.
+fn is_custom_section(name: &str) -> bool {
+    name.strip_prefix(".percpu.").is_some_and(|sub| {
+        is_asm_symbol(sub)
+            && !["bss", "hot", "cold", "read_mostly"].contains(&sub)
+            && !sub.starts_with("align")
+    })
+}
+
+/// Whether `name` can be used as a symbol name in assembly code without quoting, i.e., a C identifier.
+fn is_asm_symbol(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,135 @@
+//! Diagnostics for unsupported per-CPU static variable definitions, which would otherwise fail later with
+//! inscrutable errors in the generated code, assembly or linking.
+
+use proc_macro::TokenStream;
+use syn::{Attribute, Error, Expr, Item, ItemStatic, Lit, Result, StaticMutability, Type};
+
+/// The maximum size of a per-CPU static variable.
+///
+/// The offset of a per-CPU static variable is encoded as a 32-bit signed immediate on most architectures (e.g.,
+/// `gs:[offset VAR]` on x86_64, `lui` + `addi` on RISC-V).
+const MAX_PERCPU_SIZE: u128 = 1 << 31;
+
+/// Parses the item that `def_percpu` is applied to, and checks that it is a supported `static` item.
+pub fn parse_static(item: TokenStream) -> Result<ItemStatic> {
+    check_static(syn::parse(item)?)
+}
+
+/// Checks that the item is a supported `static` item.
+pub fn check_static(item: Item) -> Result<ItemStatic> {
+    let Item::Static(item) = item else {
+        return Err(Error::new_spanned(
+            item,
+            "`#[def_percpu]` can only be applied to `static` items",
+        ));
+    };
+    if let StaticMutability::Mut(mutability) = &item.mutability {
+        return Err(Error::new_spanned(
+            mutability,
+            "per-CPU static variables can not be `static mut`, they are mutated through the generated accessors",
+        ));
+    }
+    check_type(&item.ty)?;
+    Ok(item)
+}
+
+/// Checks the symbol attributes on the static variable (`attrs`) and in the `inner_attrs` argument (`inner_attrs`),
+/// so that at most one of them names the inner symbol.
+pub fn check_symbol_attrs(attrs: &[Attribute], inner_attrs: &[Attribute]) -> Result<()> {
+    let mut naming: Option<&Attribute> = None;
+    for attr in attrs.iter().chain(inner_attrs) {
+        match crate::symbol_attr_name(attr) {
+            Some("link_section") => {
+                return Err(Error::new_spanned(
+                    attr,
+                    "`#[link_section]` is not supported, per-CPU data is always placed in the `.percpu` section",
+                ))
+            }
+            Some("no_mangle" | "export_name") => {
+                if let Some(prev) = naming {
+                    let mut err =
+                        Error::new_spanned(attr, "conflicting symbol name of the per-CPU data");
+                    err.combine(Error::new_spanned(prev, "the symbol name is first given here"));
+                    return Err(err);
+                }
+                naming = Some(attr);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the type can be the type of a per-CPU static variable.
+fn check_type(ty: &Type) -> Result<()> {
+    match ty {
+        Type::ImplTrait(_) | Type::Infer(_) => Err(Error::new_spanned(
+            ty,
+            "the type of a per-CPU static variable must be written explicitly",
+        )),
+        Type::TraitObject(_) | Type::Slice(_) => Err(Error::new_spanned(
+            ty,
+            "the type of a per-CPU static variable must be sized",
+        )),
+        Type::Path(path) if path.qself.is_none() && path.path.is_ident("str") => Err(
+            Error::new_spanned(ty, "the type of a per-CPU static variable must be sized"),
+        ),
+        _ => match obvious_size(ty) {
+            Some(size) if size >= MAX_PERCPU_SIZE => Err(Error::new_spanned(
+                ty,
+                format!(
+                    "per-CPU static variable too large: {size} bytes, the limit is {MAX_PERCPU_SIZE} bytes"
+                ),
+            )),
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Returns the alignment of the type if it is obvious, i.e., it is a primitive type, or an array of them.
+///
+/// The alignment of a primitive type is assumed to be its size, which may be larger than the actual one on some
+/// targets (e.g., `u64` on 32-bit x86).
+pub fn obvious_align(ty: &Type) -> Option<usize> {
+    match ty {
+        Type::Array(array) => obvious_align(&array.elem),
+        Type::Paren(paren) => obvious_align(&paren.elem),
+        Type::Group(group) => obvious_align(&group.elem),
+        Type::Path(_) => obvious_size(ty).map(|size| size as usize),
+        _ => None,
+    }
+}
+
+/// Returns the size of the type if it is obvious, i.e., it is a primitive type, or an array of them with a literal
+/// length.
+fn obvious_size(ty: &Type) -> Option<u128> {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => {
+            let ident = path.path.get_ident()?.to_string();
+            match ident.as_str() {
+                "bool" | "u8" | "i8" => Some(1),
+                "u16" | "i16" => Some(2),
+                "u32" | "i32" | "f32" | "char" => Some(4),
+                "u64" | "i64" | "f64" => Some(8),
+                "u128" | "i128" => Some(16),
+                // at least 4 bytes on all supported targets
+                "usize" | "isize" => Some(4),
+                _ => None,
+            }
+        }
+        Type::Array(array) => {
+            let Expr::Lit(len) = &array.len else {
+                return None;
+            };
+            let Lit::Int(len) = &len.lit else {
+                return None;
+            };
+            let len: u128 = len.base10_parse().ok()?;
+            len.checked_mul(obvious_size(&array.elem)?)
+                .or(Some(u128::MAX))
+        }
+        Type::Paren(paren) => obvious_size(&paren.elem),
+        Type::Group(group) => obvious_size(&group.elem),
+        _ => None,
+    }
+}
@@ -21,10 +21,12 @@
 //!
 //! - The base address of the per-CPU data area on the CPU,
 //!   - which can be calculated by the base address of the whole per-CPU data area and the CPU ID,
-//!   - and then stored in a register, like `TPIDR_EL1`/`TPIDR_EL2` on AArch64, or `gs` on x86_64.
+//!   - and then stored in a register, like `TPIDR_EL1`/`TPIDR_EL2`/`TPIDR_EL3` on AArch64, `TPIDRPRW` on ARMv7, or `gs` on
+//!     x86_64.
 //! - The offset of the per-CPU static variable relative to the per-CPU data area base,
-//!   - which can be calculated by assembly notations, like `offset symbol` on x86_64, or `#:abs_g0_nc:symbol` on
-//!     AArch64, or `%hi(symbol)` and `%lo(symbol)` on RISC-V.
+//!   - which can be calculated by assembly notations, like `offset symbol` on x86_64, or `#:abs_g1:symbol` and
+//!     `#:abs_g0_nc:symbol` on AArch64, `#:lower16:symbol` and `#:upper16:symbol` on ARMv7, or `%hi(symbol)` and
+//!     `%lo(symbol)` on RISC-V.
 //! - The size of the per-CPU static variable,
 //!   - which we actually do not need to know, just give the right type to rust compiler.
 //!
@@ -33,7 +35,8 @@
 //! For each static variable `X` with type `T` that is defined with the `def_percpu` macro, the following items are
 //! generated:
 //!
-//! - A static variable `__PERCPU_X` with type `T` that stores the per-CPU data.
+//! - A static variable `__PERCPU_X` with type `percpu::__priv::PercpuStorage<T>` that stores the per-CPU data. It is an
+//!   `UnsafeCell<T>` with the same layout as `T`, instead of a `static mut`.
 //!
 //!   This variable is placed in the `.percpu` section. All attributes of the original static variable, as well as the
 //!   initialization expression, are preserved.
@@ -41,10 +44,31 @@
 //!   This variable is never, and should never be, accessed directly. To access the per-CPU data, the offset of the
 //!   variable is, and should be, used.
 //!
-//! - A zero-sized wrapper struct `X_WRAPPER` that is used to access the per-CPU data.
+//!   If the original static variable is `#[no_mangle]`, this variable is exported with the crate-qualified symbol name
+//!   `__PERCPU_<crate>_X` instead, so that per-CPU data with the same name in different crates do not collide at link
+//!   time. A weak alias `__PERCPU_X` is also defined for assembly code on ELF targets. If the same name is exported by
+//!   more than one crate, the alias resolves to any one of them, so use the crate-qualified name to be sure.
+//!
+//! - A zero-sized wrapper struct `X_WRAPPER` that is used to access the per-CPU data. It implements the
+//!   `percpu::PerCpu<T>` trait, so that generic code can access any per-CPU data of type `T`.
 //!
 //!   Some methods are generated in this struct to access the per-CPU data. For primitive integer types, extra methods
-//!   are generated to accelerate the access.
+//!   are generated to accelerate the access, and so are `read_current` and `write_current` for `u128`, `f32`, `f64`,
+//!   `Option<NonNull<T>>` and `NonZero*` types, which are accessed as integers of the same size. For other `Copy` types, `read_current` and
+//!   `write_current` copy the data out and in. For arrays of primitive integers, `read_current_at` and `write_current_at` access one element. For
+//!   `PercpuCounter`, counter methods like `add_current` and `sum` are generated, and for `PercpuCounterBatched`,
+//!   `add_current`, `approx_sum` and `precise_sum`. For atomic types like `AtomicUsize`, `current()` and
+//!   `remote(cpu_id)` return plain references to the atomic data, which are safe to use on any CPU. For
+//!   `PerCpuOnce<T>`, `set_current` and `get_current` are generated, and for `PerCpuRefCell<T>`, `borrow_current` and
+//!   `borrow_mut_current`.
+//!
+//!   The accessors that hand out `&mut T` (e.g., `with_current`, `replace_current` and `remote_mut`), and the
+//!   `percpu::PerCpuMut<T>` trait, are not generated for atomic types and the types with interior mutability in the
+//!   `percpu` crate (e.g., `PerCpuLazy<T>`, `PerCpuOnce<T>` or `PercpuFlag`), which are only accessed by shared
+//!   references.
+//!
+//!   The types above are recognized by name, so a user type with the same name (e.g., `struct PercpuCounter(u8)`) is
+//!   rejected at compile time.
 //!
 //! - A static variable `X` of type `X_WRAPPER` that is used to access the per-CPU data.
 //!   
@@ -54,56 +78,975 @@
 #![feature(doc_cfg)]
 
 use proc_macro::TokenStream;
-use proc_macro2::Span;
 use quote::{format_ident, quote};
-use syn::{Error, ItemStatic};
+use syn::{parse_quote, Attribute, Error, Expr, Lit, Meta, Type};
 
 #[cfg_attr(feature = "sp-naive", path = "naive.rs")]
 mod arch;
+mod args;
+mod check;
 
 fn compiler_error(err: Error) -> TokenStream {
     err.to_compile_error().into()
 }
 
+/// The architectures whose thread pointer register is used to hold the per-CPU data area base.
+const SUPPORTED_ARCHES: &[&str] = &[
+    "x86",
+    "x86_64",
+    "aarch64",
+    "arm",
+    "riscv32",
+    "riscv64",
+    "loongarch64",
+];
+
+/// Generate the `cfg` predicate under which each per-CPU static variable is just a global variable located by plain
+/// pointers, as if the `sp-naive` feature is enabled: on macOS and under Miri, and on architectures not in
+/// [`SUPPORTED_ARCHES`] (e.g., MIPS or WebAssembly), so that crates depending on `percpu` still compile there.
+fn gen_naive_cfg() -> proc_macro2::TokenStream {
+    let arches = SUPPORTED_ARCHES;
+    quote! {
+        any(target_os = "macos", miri, not(any(#(target_arch = #arches),*)))
+    }
+}
+
+/// Whether the given type is the type `name` in the `percpu` crate (optionally with a path prefix like
+/// `percpu::PercpuCounter`), or in another crate like `core`.
+fn is_percpu_type(ty: &Type, name: &str) -> bool {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|seg| seg.ident == name && seg.arguments.is_empty()),
+        _ => false,
+    }
+}
+
+/// Returns the type argument `T` if the given type is the generic type `name<T>` in the `percpu` crate (optionally
+/// with a path prefix like `percpu::PerCpuOnce<T>`).
+fn percpu_type_arg<'a>(ty: &'a Type, name: &str) -> Option<&'a Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let seg = path
+        .path
+        .segments
+        .last()
+        .filter(|seg| seg.ident == name && path.qself.is_none())?;
+    let syn::PathArguments::AngleBracketed(args) = &seg.arguments else {
+        return None;
+    };
+    match args.args.first() {
+        Some(syn::GenericArgument::Type(arg)) if args.args.len() == 1 => Some(arg),
+        _ => None,
+    }
+}
+
+/// Whether the given type is a primitive integer type that can be the element of a per-CPU array with indexed
+/// accessors.
+fn is_primitive_int_elem(ty: &Type) -> bool {
+    ["u8", "u16", "u32", "u64", "usize"].contains(&quote!(#ty).to_string().as_str())
+}
+
+/// The primitive integer type that has the same size and in-memory representation as a type, so that the per-CPU data
+/// of the type can be accessed by the fast path of the integer.
+struct IntRepr {
+    /// The integer type.
+    int_ty: Type,
+    /// The expression that converts the integer `raw` into the type.
+    from_int: proc_macro2::TokenStream,
+    /// The expression that converts the value `val` of the type into the integer.
+    into_int: proc_macro2::TokenStream,
+}
+
+/// Returns the integer representation of the given type, i.e., `usize` for `Option<NonNull<T>>`, `u32` for
+/// `NonZeroU32`, `NonZero<u32>` or `f32` (bit-cast), and `u64` for `f64`, or `None` if the type is not one of them.
+///
+/// `u128` is represented by itself, since it only has the raw accessors but no arithmetic or atomic ones.
+fn int_repr_of(ty: &Type) -> Option<IntRepr> {
+    match quote!(#ty).to_string().as_str() {
+        "u128" => {
+            return Some(IntRepr {
+                int_ty: ty.clone(),
+                from_int: quote! { raw },
+                into_int: quote! { val },
+            })
+        }
+        float @ ("f32" | "f64") => {
+            return Some(IntRepr {
+                int_ty: if float == "f32" {
+                    parse_quote!(u32)
+                } else {
+                    parse_quote!(u64)
+                },
+                from_int: quote! { #ty::from_bits(raw) },
+                into_int: quote! { val.to_bits() },
+            })
+        }
+        _ => {}
+    }
+    if let Some(pointee) =
+        percpu_type_arg(ty, "Option").and_then(|ty| percpu_type_arg(ty, "NonNull"))
+    {
+        // Pointers to unsized types are not usize-sized.
+        if matches!(pointee, Type::TraitObject(_) | Type::Slice(_))
+            || quote!(#pointee).to_string() == "str"
+        {
+            return None;
+        }
+        return Some(IntRepr {
+            int_ty: parse_quote!(usize),
+            from_int: quote! { ::core::ptr::NonNull::new(raw as *mut #pointee) },
+            into_int: quote! { val.map_or(0, |ptr| ptr.as_ptr() as usize) },
+        });
+    }
+
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let seg = path.path.segments.last().filter(|_| path.qself.is_none())?;
+    let int_ty: Type = match seg.ident.to_string().as_str() {
+        "NonZeroU8" => parse_quote!(u8),
+        "NonZeroU16" => parse_quote!(u16),
+        "NonZeroU32" => parse_quote!(u32),
+        "NonZeroU64" => parse_quote!(u64),
+        "NonZeroUsize" => parse_quote!(usize),
+        "NonZero" => percpu_type_arg(ty, "NonZero")
+            .filter(|arg| is_primitive_int_elem(arg))?
+            .clone(),
+        _ => return None,
+    };
+    Some(IntRepr {
+        int_ty,
+        // The integer is always non-zero, since it is only written from a value of the type.
+        from_int: quote! { unsafe { <#ty>::new_unchecked(raw) } },
+        into_int: quote! { val.get() },
+    })
+}
+
+/// Whether the given type is an atomic integer (or boolean) type in `core::sync::atomic`, e.g., `AtomicUsize` or
+/// `core::sync::atomic::AtomicU32`.
+fn is_atomic_type(ty: &Type) -> bool {
+    const ATOMIC_TYPES: &[&str] = &[
+        "AtomicBool",
+        "AtomicU8",
+        "AtomicU16",
+        "AtomicU32",
+        "AtomicU64",
+        "AtomicUsize",
+        "AtomicI8",
+        "AtomicI16",
+        "AtomicI32",
+        "AtomicI64",
+        "AtomicIsize",
+    ];
+    ATOMIC_TYPES.iter().any(|name| is_percpu_type(ty, name))
+}
+
+/// Whether the given type is only accessed by shared references, i.e., an atomic type or a type with interior
+/// mutability in the `percpu` crate, like `PerCpuOnce<T>` or `PercpuFlag`.
+///
+/// Their generated methods hand out shared references that may outlive the call (e.g., `get_or_init_current`), or
+/// reference the per-CPU data on other CPUs (e.g., `is_set_remote`), so the accessors that hand out `&mut T` (e.g.,
+/// `with_current` or `replace_current`) are not generated for them.
+fn is_shared_only_type(ty: &Type) -> bool {
+    is_atomic_type(ty)
+        || ["PerCpuLazy", "PerCpuOnce", "PerCpuRefCell"]
+            .iter()
+            .any(|name| percpu_type_arg(ty, name).is_some())
+        || [
+            "PercpuCounter",
+            "PercpuCounterBatched",
+            "PercpuEpoch",
+            "PercpuFlag",
+            "PercpuRef",
+            "PercpuRwLock",
+            "PercpuWorkQueue",
+        ]
+        .iter()
+        .any(|name| is_percpu_type(ty, name))
+}
+
+/// Whether the attribute is `#[no_mangle]` or `#[unsafe(no_mangle)]`.
+fn is_no_mangle(attr: &Attribute) -> bool {
+    match &attr.meta {
+        Meta::Path(path) => path.is_ident("no_mangle"),
+        Meta::List(list) => list.path.is_ident("unsafe") && list.tokens.to_string() == "no_mangle",
+        Meta::NameValue(_) => false,
+    }
+}
+
+/// Returns the name of the attribute if it controls the symbol of a static item, i.e., `no_mangle`, `export_name`,
+/// `link_section` or `used` (optionally wrapped in `unsafe(...)`).
+///
+/// Such attributes are only applied to the inner symbol `__PERCPU_X`, instead of all generated statics.
+fn symbol_attr_name(attr: &Attribute) -> Option<&'static str> {
+    const SYMBOL_ATTRS: &[&str] = &["no_mangle", "export_name", "link_section", "used"];
+    let path = match &attr.meta {
+        Meta::List(list) if list.path.is_ident("unsafe") => {
+            let inner: Meta = list.parse_args().ok()?;
+            inner.path().clone()
+        }
+        meta => meta.path().clone(),
+    };
+    SYMBOL_ATTRS
+        .iter()
+        .copied()
+        .find(|name| path.is_ident(name))
+}
+
+/// Returns the attributes of the inner symbol `__PERCPU_X`, and the weak alias of it for assembly code.
+///
+/// `#[no_mangle]` is replaced with a crate-qualified `#[export_name]`, since every crate would otherwise export the
+/// same `__PERCPU_X`. The attributes given by the `inner_attrs` argument are appended as they are.
+fn gen_inner_symbol_attrs(
+    attrs: &[Attribute],
+    explicit_attrs: &[Attribute],
+    name: &proc_macro2::Ident,
+    inner_symbol_name: &proc_macro2::Ident,
+) -> (Vec<Attribute>, proc_macro2::TokenStream) {
+    if !attrs.iter().any(is_no_mangle) {
+        return ([attrs, explicit_attrs].concat(), quote! {});
+    }
+    let crate_name = std::env::var("CARGO_CRATE_NAME").unwrap_or_else(|_| "unknown".into());
+    let export_name = format!("__PERCPU_{crate_name}_{name}");
+    let alias = format!("__PERCPU_{name}");
+
+    let mut inner_attrs: Vec<Attribute> = attrs
+        .iter()
+        .filter(|attr| !is_no_mangle(attr))
+        .cloned()
+        .collect();
+    inner_attrs.push(parse_quote!(#[export_name = #export_name]));
+    inner_attrs.extend_from_slice(explicit_attrs);
+    let naive_cfg = gen_naive_cfg();
+    let alias_asm = quote! {
+        #[cfg(not(any(#naive_cfg, target_os = "windows")))] // ELF only
+        ::core::arch::global_asm!(
+            concat!(".weak ", #alias),
+            concat!(".set ", #alias, ", {0}"),
+            sym #inner_symbol_name,
+        );
+    };
+    (inner_attrs, alias_asm)
+}
+
+/// Whether the expression is obviously evaluated to all-zero bytes, i.e., it is a literal `0` or `false`, or an
+/// array or tuple of them.
+fn is_zero_expr(expr: &Expr) -> bool {
+    match expr {
+        Expr::Lit(lit) => match &lit.lit {
+            Lit::Int(int) => int.base10_digits() == "0",
+            Lit::Bool(b) => !b.value,
+            _ => false,
+        },
+        Expr::Array(array) => array.elems.iter().all(is_zero_expr),
+        Expr::Repeat(repeat) => is_zero_expr(&repeat.expr),
+        Expr::Tuple(tuple) => tuple.elems.iter().all(is_zero_expr),
+        Expr::Paren(paren) => is_zero_expr(&paren.expr),
+        _ => false,
+    }
+}
+
+/// A read-modify-write operation on a per-CPU integer, which is done by one instruction on some architectures.
+#[derive(Clone, Copy)]
+enum RmwOp {
+    /// Wrapping addition.
+    Add,
+    /// Bitwise OR.
+    Or,
+    /// Bitwise AND.
+    And,
+}
+
+impl RmwOp {
+    /// Returns the statement that applies the operation to the place `lhs` with `rhs`.
+    fn assign(
+        self,
+        lhs: proc_macro2::TokenStream,
+        rhs: &proc_macro2::Ident,
+    ) -> proc_macro2::TokenStream {
+        match self {
+            RmwOp::Add => quote! { #lhs = (#lhs).wrapping_add(#rhs) },
+            RmwOp::Or => quote! { #lhs |= #rhs },
+            RmwOp::And => quote! { #lhs &= #rhs },
+        }
+    }
+}
+
+/// Returns the name of the atomic type in `core::sync::atomic` that has the same size and in-memory representation
+/// as the given primitive integer type.
+fn atomic_type_of(ty_str: &str) -> proc_macro2::Ident {
+    let atomic_ty = match ty_str {
+        "bool" => "AtomicBool",
+        "u8" => "AtomicU8",
+        "u16" => "AtomicU16",
+        "u32" => "AtomicU32",
+        "u64" => "AtomicU64",
+        "usize" => "AtomicUsize",
+        _ => unreachable!(),
+    };
+    format_ident!("{}", atomic_ty)
+}
+
+/// Whether to count the accesses of per-CPU data with the `profile` feature. The internal per-CPU data of crate
+/// `percpu` is not profiled, since the hit counters are found by it.
+fn profile_enabled() -> bool {
+    cfg!(feature = "profile") && std::env::var("CARGO_CRATE_NAME").as_deref() != Ok("percpu")
+}
+
+/// Returns the name of the per-CPU hit counter of the per-CPU data with the inner symbol name `symbol`.
+fn profile_symbol_name(symbol: &proc_macro2::Ident) -> proc_macro2::Ident {
+    format_ident!("{}_HITS", symbol)
+}
+
+/// Generate the statement that bumps the hit counter of the per-CPU data with the inner symbol name `symbol` on the
+/// current CPU, or nothing if profiling is disabled.
+fn gen_profile_hit(symbol: &proc_macro2::Ident) -> proc_macro2::TokenStream {
+    if !profile_enabled() {
+        return quote! {};
+    }
+    let offset = arch::gen_offset(&profile_symbol_name(symbol));
+    quote! { percpu::__priv::profile_hit(#offset); }
+}
+
 /// Defines a per-CPU static variable.
 ///
 /// It should be used on a `static` variable definition.
 ///
+/// The following arguments are supported:
+///
+/// - `lazy`: the initialization expression does not need to be `const`. It is evaluated on the first access on each
+///   CPU, and the per-CPU data is stored in a `percpu::PerCpuLazy<T>`, e.g., `#[def_percpu(lazy)]`.
+/// - `align = N` or `align = "cacheline"`: the per-CPU data is aligned to `N` bytes (or the cache line size), and
+///   padded to a multiple of `N` bytes, so it does not share a cache line with other per-CPU data. `N` must be a power
+///   of two no greater than 64, e.g., `#[def_percpu(align = 64)]`.
+/// - `offset_sym` or `offset_sym = "NAME"`: a global symbol `PERCPU_OFF_X` (or `NAME`) is defined, whose value is the
+///   offset of the per-CPU data (the same as `X.offset()`), so that assembly code can access the per-CPU data without
+///   duplicating magic numbers, e.g., `mov rax, gs:[PERCPU_OFF_CURRENT_TASK]` on x86_64. It is not supported on
+///   macOS. With the `sp-naive` feature, it is the address of the per-CPU data on bare-metal.
+/// - `inner_attrs(...)`: symbol attributes (`no_mangle`, `export_name = "NAME"` or `used`) applied to the inner
+///   symbol `__PERCPU_X` as they are, e.g., `#[def_percpu(inner_attrs(no_mangle))]` exports `__PERCPU_X` without the
+///   crate-qualified name and the weak alias.
+/// - `hot` or `cold`: the per-CPU data is placed at the beginning (or the end) of the zero-initialized or initialized
+///   part of the per-CPU data area, so that frequently accessed data share a few cache lines, and have small offsets
+///   (e.g., within the 16-bit immediates on AArch64), e.g., `#[def_percpu(hot)]`.
+/// - `read_mostly`: the per-CPU data is placed in cache lines separated from other per-CPU data, like
+///   `__read_mostly` in Linux, so that remote CPUs reading it (e.g., by `remote_ptr`) do not contend with the writes
+///   to other per-CPU data on the local CPU, e.g., `#[def_percpu(read_mostly)]`.
+/// - `section = ".percpu.NAME"`: the per-CPU data is placed in the user-named subsection `.percpu.NAME` (whether
+///   zero-initialized or not), whose offset range and size in each per-CPU data area are returned by
+///   `percpu::percpu_area_range_of(".percpu.NAME")` and `percpu::percpu_area_size_of(".percpu.NAME")`, so that it can
+///   be managed separately, e.g., a hypervisor-only region that is unmapped while running guests. It can not be used
+///   with `hot`, `cold` or `read_mostly`, e.g., `#[def_percpu(section = ".percpu.vm")]`.
+/// - `debug`: the wrapper struct implements `Debug` (if the type does), which prints the value on every CPU by remote
+///   reads, e.g., `{cpu0: 1, cpu1: 0}`, so that it can be logged with `{:?}` during bring-up. It is only implemented
+///   for integers (and the types represented by them, like `f32` or `NonZeroU32`), which are copied by volatile
+///   reads. For other types, the unsafe method `debug_remote()` returns the formatter instead, which must not be used
+///   while other CPUs may mutate the data, e.g., `#[def_percpu(debug)]`.
+/// - `export_c`: `extern "C"` accessors `percpu_read_x()` and `percpu_write_x(val)` of the current CPU are exported
+///   (in lower case), and the offset symbol is defined as with `offset_sym`, so that C code and standalone assembly
+///   linked into the same kernel can access the per-CPU data. The type must be `Copy` and FFI-safe, and `lazy` is not
+///   supported, e.g., `#[def_percpu(export_c)]`.
+/// - `no_remote`: the accessors of the per-CPU data on other CPUs (`remote_ptr`, `remote_ref_mut_raw`, `read_remote`,
+///   `iter_remote`, etc.) are not generated, for CPU-private data such as the trap scratch space. `remote_ptr` of the
+///   `percpu::PerCpu` trait is still implemented, but panics with debug assertions if the CPU ID is not the current
+///   one. It can not be used with `debug`, or with the types that access the per-CPU data on all CPUs (e.g.,
+///   `percpu::PercpuCounter`), e.g., `#[def_percpu(no_remote)]`.
+/// - `unsafe(no_preempt_guard)`: the safe accessors of the current CPU (`read_current`, `with_current`, etc.) do not
+///   disable preemption with the `preempt` feature, for the per-CPU data that is only accessed with preemption or IRQs
+///   disabled, e.g., from the trap handler. It is spelled `unsafe` since the accessors are still safe to call, so the
+///   definition must keep the contract that the task is not migrated during any access, which is asserted by the
+///   accessors with the `debug-preempt-check` feature, e.g., `#[def_percpu(unsafe(no_preempt_guard))]`.
+///
+/// Symbol attributes on the static variable itself are also applied to the inner symbol only, instead of the
+/// generated wrapper and helper statics, where they would cause duplicate symbols. `#[no_mangle]` exports the
+/// crate-qualified name `__PERCPU_<crate>_X`, and `#[export_name = "NAME"]` exports `NAME`. `#[link_section]` is not
+/// supported, since the per-CPU data must be placed in the `.percpu` section.
+///
 /// See the documentation of the [percpu](https://docs.rs/percpu) crate for more details.
 #[proc_macro_attribute]
 pub fn def_percpu(attr: TokenStream, item: TokenStream) -> TokenStream {
-    if !attr.is_empty() {
-        return compiler_error(Error::new(
-            Span::call_site(),
-            "expect an empty attribute: `#[def_percpu]`",
-        ));
+    let args = match args::PercpuArgs::parse(attr) {
+        Ok(args) => args,
+        Err(err) => return compiler_error(err),
+    };
+
+    let ast = match check::parse_static(item) {
+        Ok(ast) => ast,
+        Err(err) => return compiler_error(err),
+    };
+
+    match gen_percpu(args, ast) {
+        Ok(output) => output.into(),
+        Err(err) => compiler_error(err),
+    }
+}
+
+/// Defines a group of per-CPU static variables at once.
+///
+/// It takes any number of `static` variable definitions, which are defined as if each of them is annotated with
+/// [`macro@def_percpu`]. Arguments of a variable are given by a `#[def_percpu(...)]` attribute on it, e.g.:
+///
+/// ```rust,ignore
+/// percpu::def_percpu_group! {
+///     static TICKS: u64 = 0;
+///     #[def_percpu(align = "cacheline")]
+///     static STATS: [usize; 4] = [0; 4];
+///     pub static IRQ_DEPTH: u8 = 0;
+/// }
+/// ```
+///
+/// The variables are defined in the descending order of their alignment (variables with an explicit `align` first,
+/// then variables whose alignment is unknown to the macro), so that the linker needs less padding between them if
+/// it keeps the order in the `.percpu` section.
+#[proc_macro]
+pub fn def_percpu_group(item: TokenStream) -> TokenStream {
+    match gen_percpu_group(item) {
+        Ok(output) => output.into(),
+        Err(err) => compiler_error(err),
+    }
+}
+
+fn gen_percpu_group(item: TokenStream) -> syn::Result<proc_macro2::TokenStream> {
+    let parser = |input: syn::parse::ParseStream| {
+        let mut items = Vec::new();
+        while !input.is_empty() {
+            items.push(input.parse::<syn::Item>()?);
+        }
+        Ok(items)
+    };
+    let items = syn::parse::Parser::parse(parser, item)?;
+
+    let mut defs = Vec::with_capacity(items.len());
+    for item in items {
+        let mut ast = check::check_static(item)?;
+        let mut def_attrs = ast.attrs.iter().filter(|attr| is_def_percpu_attr(attr));
+        if let Some(dup) = def_attrs.nth(1) {
+            return Err(Error::new_spanned(dup, "duplicate `def_percpu` attribute"));
+        }
+        let mut args = args::PercpuArgs::default();
+        if let Some(pos) = ast.attrs.iter().position(is_def_percpu_attr) {
+            let attr = ast.attrs.remove(pos);
+            match &attr.meta {
+                Meta::Path(_) => {}
+                Meta::List(list) => args = args::PercpuArgs::parse(list.tokens.clone().into())?,
+                Meta::NameValue(_) => {
+                    return Err(Error::new_spanned(attr, "expected `#[def_percpu(...)]`"));
+                }
+            }
+        }
+        defs.push((args, ast));
     }
 
-    let ast = syn::parse_macro_input!(item as ItemStatic);
+    // Variables with unknown alignment are placed before those with obvious alignment, since they may be large
+    // structs. The sort is stable, so the order of definitions is kept otherwise.
+    defs.sort_by_key(|(args, ast)| {
+        let key = match args.align {
+            Some(align) => (2, align),
+            None if args.lazy => (1, 0),
+            None => check::obvious_align(&ast.ty).map_or((1, 0), |align| (0, align)),
+        };
+        std::cmp::Reverse(key)
+    });
+    defs.into_iter()
+        .map(|(args, ast)| gen_percpu(args, ast))
+        .collect()
+}
+
+/// Whether the attribute is `#[def_percpu]` or `#[def_percpu(...)]` (optionally with a path prefix), which gives the
+/// arguments of a variable in `def_percpu_group`.
+fn is_def_percpu_attr(attr: &Attribute) -> bool {
+    attr.path()
+        .segments
+        .last()
+        .is_some_and(|seg| seg.ident == "def_percpu")
+}
+
+/// Generates the per-CPU static variable defined by `ast` with the arguments `args`, for both `def_percpu` and
+/// `def_percpu_group`.
+fn gen_percpu(
+    args: args::PercpuArgs,
+    ast: syn::ItemStatic,
+) -> syn::Result<proc_macro2::TokenStream> {
+    check::check_symbol_attrs(&ast.attrs, &args.inner_attrs)?;
 
-    let attrs = &ast.attrs;
+    // Symbol attributes are only applied to the inner symbol, other generated statics get the rest, e.g., `cfg` and
+    // doc comments.
+    let symbol_attrs: Vec<Attribute> = ast
+        .attrs
+        .iter()
+        .filter(|attr| symbol_attr_name(attr).is_some())
+        .cloned()
+        .collect();
+    let attrs: &Vec<Attribute> = &ast
+        .attrs
+        .iter()
+        .filter(|attr| symbol_attr_name(attr).is_none())
+        .cloned()
+        .collect();
     let vis = &ast.vis;
     let name = &ast.ident;
-    let ty = &ast.ty;
-    let init_expr = &ast.expr;
+    let value_ty = &ast.ty;
+
+    // For lazy per-CPU data, the data is stored in a `PerCpuLazy<T>` with the initialization expression as the
+    // initializer.
+    let (ty, init_expr): (Type, Expr) = if args.lazy {
+        let expr = &ast.expr;
+        (
+            parse_quote!(percpu::PerCpuLazy<#value_ty>),
+            parse_quote!(percpu::PerCpuLazy::new(|| #expr)),
+        )
+    } else {
+        ((*ast.ty).clone(), (*ast.expr).clone())
+    };
+    let ty = &ty;
+    let init_expr = &init_expr;
 
     let inner_symbol_name = &format_ident!("__PERCPU_{}", name);
+    let (inner_attrs, inner_alias) = gen_inner_symbol_attrs(
+        &[attrs.as_slice(), &symbol_attrs].concat(),
+        &args.inner_attrs,
+        name,
+        inner_symbol_name,
+    );
+    let offset_sym = args.offset_sym.map(|offset_sym| {
+        let offset_sym = match offset_sym {
+            args::OffsetSym::Default => format!("PERCPU_OFF_{name}"),
+            args::OffsetSym::Named(sym) => sym,
+        };
+        // The `.percpu` section is linked at address 0, so the address of the inner symbol is the offset.
+        let naive_cfg = gen_naive_cfg();
+        quote! {
+            #[cfg(not(any(#naive_cfg, target_os = "windows")))] // ELF only
+            ::core::arch::global_asm!(
+                concat!(".globl ", #offset_sym),
+                concat!(".set ", #offset_sym, ", {0}"),
+                sym #inner_symbol_name,
+            );
+        }
+    });
     let struct_name = &format_ident!("{}_WRAPPER", name);
 
+    // The C accessors are named in lower case, e.g., `percpu_read_irq_count` for `IRQ_COUNT`.
+    let export_c_fns = args.export_c.then(|| {
+        let lower_name = name.to_string().to_lowercase();
+        let read_fn = format_ident!("percpu_read_{}", lower_name);
+        let write_fn = format_ident!("percpu_write_{}", lower_name);
+        quote! {
+            #[doc = concat!("Returns the value of [`", stringify!(#name), "`] on the current CPU, for C code.")]
+            #[unsafe(no_mangle)]
+            #vis extern "C" fn #read_fn() -> #ty {
+                #name.read_current()
+            }
+
+            #[doc = concat!("Sets the value of [`", stringify!(#name), "`] on the current CPU, for C code.")]
+            #[unsafe(no_mangle)]
+            #vis extern "C" fn #write_fn(val: #ty) {
+                #name.write_current(val)
+            }
+        }
+    });
+
+    if args.no_remote {
+        if let Some(name) = [
+            "PercpuCounter",
+            "PercpuCounterBatched",
+            "PercpuEpoch",
+            "PercpuFlag",
+            "PercpuRef",
+            "PercpuRwLock",
+            "PercpuWorkQueue",
+        ]
+        .into_iter()
+        .find(|name| is_percpu_type(ty, name))
+        {
+            return Err(Error::new_spanned(
+                value_ty,
+                format!("`no_remote` can not be used with `{name}`, which accesses the per-CPU data on all CPUs"),
+            ));
+        }
+    }
+
     let ty_str = quote!(#ty).to_string();
+    let shared_only = is_shared_only_type(ty);
     let is_primitive_int = ["bool", "u8", "u16", "u32", "u64", "usize"].contains(&ty_str.as_str());
+    let int_repr = int_repr_of(ty);
+
+    // The special methods are generated by the type name, so a user type with the same name is rejected at compile
+    // time, instead of being accessed as another type.
+    let builtin_check = if shared_only {
+        quote! {
+            const _: () = percpu::__priv::assert_builtin::<#ty>();
+        }
+    } else if let Some(IntRepr { int_ty, .. }) = &int_repr {
+        quote! {
+            const _: () = assert!(
+                ::core::mem::size_of::<#ty>() == ::core::mem::size_of::<#int_ty>(),
+                concat!("`", stringify!(#ty), "` is not represented by `", stringify!(#int_ty), "`"),
+            );
+        }
+    } else {
+        quote! {}
+    };
+
+    // The value is copied out by a volatile read if it is an integer (or represented by one), which does not create
+    // a reference to the per-CPU data that the owner CPU may be mutating. Otherwise, only the unsafe
+    // `debug_remote()` is provided, which references the per-CPU data on all CPUs.
+    let debug_impl = args.debug.then(|| {
+        if is_primitive_int || int_repr.is_some() {
+            quote! {
+                impl ::core::fmt::Debug for #struct_name {
+                    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                        let debug = percpu::__priv::DebugRemote(|cpu_id: usize| unsafe {
+                            self.remote_ptr(cpu_id).read_volatile()
+                        });
+                        ::core::fmt::Debug::fmt(&debug, f)
+                    }
+                }
+            }
+        } else {
+            quote! {
+                impl #struct_name {
+                    /// Returns the formatter of the per-CPU data on every CPU, e.g., `{cpu0: .., cpu1: ..}`.
+                    ///
+                    /// # Safety
+                    ///
+                    /// No CPU may mutate the per-CPU data while it is formatted, e.g., the other CPUs are parked by
+                    /// [`with_all_cpus_parked`](percpu::with_all_cpus_parked).
+                    pub unsafe fn debug_remote(&self) -> impl ::core::fmt::Debug + '_ {
+                        percpu::__priv::DebugRemote(move |cpu_id: usize| unsafe { self.remote_ref_raw(cpu_id) })
+                    }
+                }
+            }
+        }
+    });
+
+    let init_check = if cfg!(feature = "debug-init-check") {
+        quote! { percpu::__priv::assert_init(); }
+    } else {
+        quote! {}
+    };
+
+    let irqsave_guard = quote! { #init_check let _guard = percpu::__priv::IrqSaveGuard::new(); };
+
+    // All CPUs share the same data in the naive mode, so any CPU ID is fine.
+    let remote_check = if cfg!(feature = "sp-naive") {
+        quote! {}
+    } else {
+        let naive_cfg = gen_naive_cfg();
+        quote! {
+            #[cfg(not(#naive_cfg))]
+            debug_assert!(cpu_id < percpu::percpu_area_num(), "invalid CPU ID: {}", cpu_id);
+        }
+    };
+
+    let preempt_check = if cfg!(feature = "debug-preempt-check") {
+        quote! { #init_check percpu::__priv::assert_preempt_disabled(); }
+    } else {
+        init_check.clone()
+    };
+
+    // Without the guard, the accessors assert that preemption has been disabled by the caller instead.
+    let no_preempt_guard = if args.no_preempt_guard {
+        preempt_check.clone()
+    } else if cfg!(feature = "preempt") {
+        quote! { #init_check let _guard = percpu::__priv::NoPreemptGuard::new(); }
+    } else {
+        init_check.clone()
+    };
+
+    // Generate the fast `fn read_current()`, `fn write_current()`, etc for primitive types, and the copying ones for
+    // other `Copy` types.
+    // Generate indexed accessors for arrays of primitive integers, which access one element without a reference.
+    let array_methods = match ty {
+        Type::Array(array) if is_primitive_int_elem(&array.elem) => {
+            let elem_ty = &*array.elem;
+            let len = &array.len;
+            let (index, val) = (&format_ident!("index"), &format_ident!("val"));
+            let read_current_at =
+                arch::gen_read_current_at(inner_symbol_name, index, elem_ty, &no_preempt_guard);
+            let write_current_at = arch::gen_write_current_at(
+                inner_symbol_name,
+                index,
+                val,
+                elem_ty,
+                &no_preempt_guard,
+            );
+            quote! {
+                /// Returns the element at `index` of the per-CPU array on the current CPU. Preemption will be
+                /// disabled during the call if necessary.
+                ///
+                /// On x86, it compiles to a single instruction, which needs no preemption guard.
+                ///
+                /// # Panics
+                ///
+                /// Panics if `index` is out of bounds.
+                #[inline]
+                pub fn read_current_at(&self, index: usize) -> #elem_ty {
+                    assert!(index < #len, "index out of bounds: the len is {} but the index is {}", #len, index);
+                    #read_current_at
+                }
+
+                /// Sets the element at `index` of the per-CPU array on the current CPU. Preemption will be disabled
+                /// during the call if necessary.
+                ///
+                /// On x86, it compiles to a single instruction, which needs no preemption guard.
+                ///
+                /// # Panics
+                ///
+                /// Panics if `index` is out of bounds.
+                #[inline]
+                pub fn write_current_at(&self, index: usize, val: #elem_ty) {
+                    assert!(index < #len, "index out of bounds: the len is {} but the index is {}", #len, index);
+                    #write_current_at
+                }
+            }
+        }
+        _ => quote! {},
+    };
 
-    let no_preempt_guard = if cfg!(feature = "preempt") {
-        quote! { let _guard = percpu::__priv::NoPreemptGuard::new(); }
+    // The faster inherent methods of primitive integers (and the types represented by them) override the provided
+    // methods of `percpu::PerCpu`.
+    let percpu_trait_methods = if is_primitive_int || int_repr.is_some() {
+        quote! {
+            #[inline]
+            fn read_current(&self) -> #ty {
+                Self::read_current(self)
+            }
+
+            #[inline]
+            fn write_current(&self, val: #ty) {
+                Self::write_current(self, val)
+            }
+        }
     } else {
         quote! {}
     };
 
-    // Do not generate `fn read_current()`, `fn write_current()`, etc for non primitive types.
     let read_write_methods = if is_primitive_int {
         let read_current_raw = arch::gen_read_current_raw(inner_symbol_name, ty);
         let write_current_raw =
             arch::gen_write_current_raw(inner_symbol_name, &format_ident!("val"), ty);
+        let atomic_ty = atomic_type_of(&ty_str);
+        // 64-bit atomics are not available on some 32-bit targets.
+        let cfg_has_atomic = if ty_str == "u64" {
+            quote! { #[cfg(target_has_atomic = "64")] }
+        } else {
+            quote! {}
+        };
+
+        let irqsave_methods = if cfg!(feature = "irq") {
+            quote! {
+                /// Returns the value of the per-CPU static variable on the current CPU. Local IRQs will be disabled
+                /// during the call.
+                pub fn read_current_irqsave(&self) -> #ty {
+                    #irqsave_guard
+                    unsafe { self.read_current_raw() }
+                }
+
+                /// Set the value of the per-CPU static variable on the current CPU. Local IRQs will be disabled during
+                /// the call.
+                pub fn write_current_irqsave(&self, val: #ty) {
+                    #irqsave_guard
+                    unsafe { self.write_current_raw(val) }
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        let arith_methods = if ty_str != "bool" {
+            let val = &format_ident!("val");
+            let add_current =
+                arch::gen_rmw_current(RmwOp::Add, inner_symbol_name, val, ty, &no_preempt_guard);
+            let mask = &format_ident!("mask");
+            let or_current =
+                arch::gen_rmw_current(RmwOp::Or, inner_symbol_name, mask, ty, &no_preempt_guard);
+            let and_current =
+                arch::gen_rmw_current(RmwOp::And, inner_symbol_name, mask, ty, &no_preempt_guard);
+            let fetch_add_current =
+                arch::gen_fetch_add_current(inner_symbol_name, val, ty, &no_preempt_guard);
+            let swap_current =
+                arch::gen_swap_current(inner_symbol_name, val, ty, &no_preempt_guard);
+            let (old, new) = (&format_ident!("old"), &format_ident!("new"));
+            let compare_exchange_current = arch::gen_compare_exchange_current(
+                inner_symbol_name,
+                old,
+                new,
+                ty,
+                &no_preempt_guard,
+            );
+            quote! {
+                /// Adds `val` to the per-CPU static variable on the current CPU, wrapping around on overflow.
+                /// Preemption will be disabled during the call if necessary.
+                ///
+                /// On x86, it compiles to a single instruction, which needs no preemption guard.
+                #[inline]
+                pub fn add_current(&self, val: #ty) {
+                    #add_current
+                }
+
+                /// Subtracts `val` from the per-CPU static variable on the current CPU, wrapping around on overflow.
+                /// Preemption will be disabled during the call if necessary.
+                #[inline]
+                pub fn sub_current(&self, val: #ty) {
+                    self.add_current(val.wrapping_neg())
+                }
+
+                /// Adds `val` to the per-CPU static variable on the current CPU, wrapping around on overflow, and
+                /// returns the previous value. Preemption will be disabled during the call if necessary.
+                #[inline]
+                pub fn fetch_add_current(&self, val: #ty) -> #ty {
+                    #fetch_add_current
+                }
+
+                /// Stores `val` to the per-CPU static variable on the current CPU, and returns the previous value.
+                /// Preemption will be disabled during the call if necessary.
+                ///
+                /// It is atomic with respect to interrupts on the current CPU, but not to other CPUs.
+                #[inline]
+                pub fn swap_current(&self, val: #ty) -> #ty {
+                    #swap_current
+                }
+
+                /// Stores `new` to the per-CPU static variable on the current CPU if its value is `old`. Preemption
+                /// will be disabled during the call if necessary.
+                ///
+                /// Returns the previous value in `Ok` if it is stored, or in `Err` otherwise, like
+                /// `AtomicUsize::compare_exchange`. It is atomic with respect to interrupts on the current CPU, but
+                /// not to other CPUs.
+                #[inline]
+                pub fn compare_exchange_current(&self, old: #ty, new: #ty) -> Result<#ty, #ty> {
+                    #compare_exchange_current
+                }
+
+                /// Sets the `n`-th bit of the per-CPU static variable on the current CPU. Preemption will be disabled
+                /// during the call if necessary.
+                ///
+                /// On x86, it compiles to a single `or` instruction, which needs no preemption guard.
+                ///
+                /// # Panics
+                ///
+                /// Panics if `n` is not less than the number of bits of the variable.
+                #[inline]
+                pub fn set_bit_current(&self, n: u32) {
+                    assert!(n < #ty::BITS, "bit index out of range: {}", n);
+                    let mask: #ty = 1 << n;
+                    #or_current
+                }
+
+                /// Clears the `n`-th bit of the per-CPU static variable on the current CPU. Preemption will be
+                /// disabled during the call if necessary.
+                ///
+                /// On x86, it compiles to a single `and` instruction, which needs no preemption guard.
+                ///
+                /// # Panics
+                ///
+                /// Panics if `n` is not less than the number of bits of the variable.
+                #[inline]
+                pub fn clear_bit_current(&self, n: u32) {
+                    assert!(n < #ty::BITS, "bit index out of range: {}", n);
+                    let mask: #ty = !(1 << n);
+                    #and_current
+                }
+
+                /// Returns whether the `n`-th bit of the per-CPU static variable on the current CPU is set.
+                /// Preemption will be disabled during the call.
+                ///
+                /// # Panics
+                ///
+                /// Panics if `n` is not less than the number of bits of the variable.
+                #[inline]
+                pub fn test_bit_current(&self, n: u32) -> bool {
+                    assert!(n < #ty::BITS, "bit index out of range: {}", n);
+                    self.read_current() & (1 << n) != 0
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        let remote_methods = if args.no_remote {
+            quote! {}
+        } else {
+            quote! {
+                /// Returns the value of the per-CPU static variable on the given CPU.
+                ///
+                /// The value is loaded with a single atomic-sized access, so it is never torn even if the given CPU
+                /// is updating it at the same time.
+                ///
+                /// # Panics
+                ///
+                /// Panics if `cpu_id` is not less than [`percpu_area_num()`](percpu::percpu_area_num).
+                #cfg_has_atomic
+                pub fn read_remote(&self, cpu_id: impl Into<percpu::CpuId>) -> #ty {
+                    let cpu_id = cpu_id.into().get();
+                    assert!(cpu_id < percpu::percpu_area_num(), "invalid CPU ID: {}", cpu_id);
+                    unsafe {
+                        ::core::sync::atomic::#atomic_ty::from_ptr(self.remote_ptr(cpu_id) as *mut #ty)
+                            .load(::core::sync::atomic::Ordering::Relaxed)
+                    }
+                }
+
+                /// Set the value of the per-CPU static variable on the given CPU.
+                ///
+                /// The value is stored with a single atomic-sized access, so it is never torn even if the given CPU
+                /// is reading it at the same time.
+                ///
+                /// # Panics
+                ///
+                /// Panics if `cpu_id` is not less than [`percpu_area_num()`](percpu::percpu_area_num).
+                #cfg_has_atomic
+                pub fn write_remote(&self, cpu_id: impl Into<percpu::CpuId>, val: #ty) {
+                    let cpu_id = cpu_id.into().get();
+                    assert!(cpu_id < percpu::percpu_area_num(), "invalid CPU ID: {}", cpu_id);
+                    unsafe {
+                        ::core::sync::atomic::#atomic_ty::from_ptr(self.remote_ptr(cpu_id) as *mut #ty)
+                            .store(val, ::core::sync::atomic::Ordering::Relaxed)
+                    }
+                }
+
+                /// Returns the value of the per-CPU static variable on the given CPU, with the `Acquire` ordering.
+                ///
+                /// It pairs with [`remote_write_release`](Self::remote_write_release): if the value written by it is
+                /// observed, all memory writes before that store are visible to the caller. The `cpu_id` can be the
+                /// current CPU.
+                ///
+                /// # Panics
+                ///
+                /// Panics if `cpu_id` is not less than [`percpu_area_num()`](percpu::percpu_area_num).
+                #cfg_has_atomic
+                pub fn remote_read_acquire(&self, cpu_id: impl Into<percpu::CpuId>) -> #ty {
+                    let cpu_id = cpu_id.into().get();
+                    assert!(cpu_id < percpu::percpu_area_num(), "invalid CPU ID: {}", cpu_id);
+                    unsafe {
+                        ::core::sync::atomic::#atomic_ty::from_ptr(self.remote_ptr(cpu_id) as *mut #ty)
+                            .load(::core::sync::atomic::Ordering::Acquire)
+                    }
+                }
+
+                /// Set the value of the per-CPU static variable on the given CPU, with the `Release` ordering.
+                ///
+                /// It pairs with [`remote_read_acquire`](Self::remote_read_acquire), e.g., to publish the data that
+                /// is prepared before the store. The `cpu_id` can be the current CPU.
+                ///
+                /// # Panics
+                ///
+                /// Panics if `cpu_id` is not less than [`percpu_area_num()`](percpu::percpu_area_num).
+                #cfg_has_atomic
+                pub fn remote_write_release(&self, cpu_id: impl Into<percpu::CpuId>, val: #ty) {
+                    let cpu_id = cpu_id.into().get();
+                    assert!(cpu_id < percpu::percpu_area_num(), "invalid CPU ID: {}", cpu_id);
+                    unsafe {
+                        ::core::sync::atomic::#atomic_ty::from_ptr(self.remote_ptr(cpu_id) as *mut #ty)
+                            .store(val, ::core::sync::atomic::Ordering::Release)
+                    }
+                }
+            }
+        };
 
         quote! {
             /// Returns the value of the per-CPU static variable on the current CPU.
@@ -113,6 +1056,7 @@ pub fn def_percpu(attr: TokenStream, item: TokenStream) -> TokenStream {
             /// Caller must ensure that preemption is disabled on the current CPU.
             #[inline]
             pub unsafe fn read_current_raw(&self) -> #ty {
+                #preempt_check
                 #read_current_raw
             }
 
@@ -123,6 +1067,7 @@ pub fn def_percpu(attr: TokenStream, item: TokenStream) -> TokenStream {
             /// Caller must ensure that preemption is disabled on the current CPU.
             #[inline]
             pub unsafe fn write_current_raw(&self, val: #ty) {
+                #preempt_check
                 #write_current_raw
             }
 
@@ -139,118 +1084,1287 @@ pub fn def_percpu(attr: TokenStream, item: TokenStream) -> TokenStream {
                 #no_preempt_guard
                 unsafe { self.write_current_raw(val) }
             }
-        }
 
-        // Todo: maybe add `(read|write)_remote(_raw)?` here?
-    } else {
-        quote! {}
-    };
+            #irqsave_methods
 
-    let offset = arch::gen_offset(inner_symbol_name);
-    let current_ptr = arch::gen_current_ptr(inner_symbol_name, ty);
-    quote! {
-        #[cfg_attr(not(target_os = "macos"), link_section = ".percpu")] // unimplemented on macos
-        #(#attrs)*
-        static mut #inner_symbol_name: #ty = #init_expr;
+            #arith_methods
 
-        #[doc = concat!("Wrapper struct for the per-CPU data [`", stringify!(#name), "`]")]
-        #[allow(non_camel_case_types)]
-        #vis struct #struct_name {}
+            #remote_methods
+        }
+    } else if let Some(IntRepr {
+        int_ty,
+        from_int,
+        into_int,
+    }) = &int_repr
+    {
+        // The value is converted from and to the integer, which is accessed by the fast path.
+        let read_current_raw = arch::gen_read_current_raw(inner_symbol_name, int_ty);
+        let write_current_raw =
+            arch::gen_write_current_raw(inner_symbol_name, &format_ident!("raw"), int_ty);
+        let int_ty_str = quote!(#int_ty).to_string();
 
-        #(#attrs)*
-        #vis static #name: #struct_name = #struct_name {};
+        let irqsave_methods = if cfg!(feature = "irq") {
+            quote! {
+                /// Returns the value of the per-CPU static variable on the current CPU. Local IRQs will be disabled
+                /// during the call.
+                pub fn read_current_irqsave(&self) -> #ty {
+                    #irqsave_guard
+                    unsafe { self.read_current_raw() }
+                }
 
-        impl #struct_name {
-            /// Returns the offset relative to the per-CPU data area base.
-            #[inline]
-            pub fn offset(&self) -> usize {
-                #offset
+                /// Set the value of the per-CPU static variable on the current CPU. Local IRQs will be disabled during
+                /// the call.
+                pub fn write_current_irqsave(&self, val: #ty) {
+                    #irqsave_guard
+                    unsafe { self.write_current_raw(val) }
+                }
             }
+        } else {
+            quote! {}
+        };
 
-            /// Returns the raw pointer of this per-CPU static variable on the current CPU.
-            ///
-            /// # Safety
-            ///
-            /// Caller must ensure that preemption is disabled on the current CPU.
-            #[inline]
-            pub unsafe fn current_ptr(&self) -> *const #ty {
-                #current_ptr
+        // There are no 128-bit atomics to access the data on other CPUs.
+        let remote_methods = if int_ty_str == "u128" || args.no_remote {
+            quote! {}
+        } else {
+            let atomic_ty = atomic_type_of(&int_ty_str);
+            let cfg_has_atomic = if int_ty_str == "u64" {
+                quote! { #[cfg(target_has_atomic = "64")] }
+            } else {
+                quote! {}
+            };
+            quote! {
+                /// Returns the value of the per-CPU static variable on the given CPU.
+                ///
+                /// The value is loaded with a single atomic-sized access, so it is never torn even if the given CPU is
+                /// updating it at the same time.
+                ///
+                /// # Panics
+                ///
+                /// Panics if `cpu_id` is not less than [`percpu_area_num()`](percpu::percpu_area_num).
+                #cfg_has_atomic
+                pub fn read_remote(&self, cpu_id: impl Into<percpu::CpuId>) -> #ty {
+                    let cpu_id = cpu_id.into().get();
+                    assert!(cpu_id < percpu::percpu_area_num(), "invalid CPU ID: {}", cpu_id);
+                    let raw = unsafe {
+                        ::core::sync::atomic::#atomic_ty::from_ptr(self.remote_ptr(cpu_id) as *mut #int_ty)
+                            .load(::core::sync::atomic::Ordering::Relaxed)
+                    };
+                    #from_int
+                }
+
+                /// Set the value of the per-CPU static variable on the given CPU.
+                ///
+                /// The value is stored with a single atomic-sized access, so it is never torn even if the given CPU is
+                /// reading it at the same time.
+                ///
+                /// # Panics
+                ///
+                /// Panics if `cpu_id` is not less than [`percpu_area_num()`](percpu::percpu_area_num).
+                #cfg_has_atomic
+                pub fn write_remote(&self, cpu_id: impl Into<percpu::CpuId>, val: #ty) {
+                    let cpu_id = cpu_id.into().get();
+                    assert!(cpu_id < percpu::percpu_area_num(), "invalid CPU ID: {}", cpu_id);
+                    let raw: #int_ty = #into_int;
+                    unsafe {
+                        ::core::sync::atomic::#atomic_ty::from_ptr(self.remote_ptr(cpu_id) as *mut #int_ty)
+                            .store(raw, ::core::sync::atomic::Ordering::Relaxed)
+                    }
+                }
             }
+        };
 
-            /// Returns the reference of the per-CPU static variable on the current CPU.
+        quote! {
+            /// Returns the value of the per-CPU static variable on the current CPU.
+            ///
+            /// It is loaded as an integer of the same size, by `gs`-relative instructions on x86.
             ///
             /// # Safety
             ///
             /// Caller must ensure that preemption is disabled on the current CPU.
             #[inline]
-            pub unsafe fn current_ref_raw(&self) -> &#ty {
-                &*self.current_ptr()
+            pub unsafe fn read_current_raw(&self) -> #ty {
+                #preempt_check
+                let raw: #int_ty = #read_current_raw;
+                #from_int
             }
 
-            /// Returns the mutable reference of the per-CPU static variable on the current CPU.
+            /// Set the value of the per-CPU static variable on the current CPU.
+            ///
+            /// It is stored as an integer of the same size, by `gs`-relative instructions on x86.
             ///
             /// # Safety
             ///
             /// Caller must ensure that preemption is disabled on the current CPU.
             #[inline]
-            #[allow(clippy::mut_from_ref)]
-            pub unsafe fn current_ref_mut_raw(&self) -> &mut #ty {
-                &mut *(self.current_ptr() as *mut #ty)
+            pub unsafe fn write_current_raw(&self, val: #ty) {
+                #preempt_check
+                let raw: #int_ty = #into_int;
+                #write_current_raw
             }
 
-            /// Manipulate the per-CPU data on the current CPU in the given closure.
-            /// Preemption will be disabled during the call.
-            pub fn with_current<F, T>(&self, f: F) -> T
+            /// Returns the value of the per-CPU static variable on the current CPU. Preemption will be disabled during
+            /// the call.
+            pub fn read_current(&self) -> #ty {
+                #no_preempt_guard
+                unsafe { self.read_current_raw() }
+            }
+
+            /// Set the value of the per-CPU static variable on the current CPU. Preemption will be disabled during the
+            /// call.
+            pub fn write_current(&self, val: #ty) {
+                #no_preempt_guard
+                unsafe { self.write_current_raw(val) }
+            }
+
+            #irqsave_methods
+
+            #remote_methods
+        }
+    } else if !shared_only {
+        // The types are unknown to the macro, so the methods are generated for all other types, but can only be called
+        // for `Copy` types. The higher-ranked bound is not checked until the methods are called.
+        quote! {
+            /// Returns a copy of the per-CPU data on the current CPU. Preemption will be disabled during the call.
+            ///
+            /// It is only available for `Copy` types, and is intended for small ones, e.g., `(u32, u32)` or a
+            /// `#[repr(C)]` struct of two integers.
+            pub fn read_current(&self) -> #ty
             where
-                F: FnOnce(&mut #ty) -> T,
+                for<'a> #ty: Copy,
             {
                 #no_preempt_guard
-                f(unsafe { self.current_ref_mut_raw() })
+                unsafe { self.current_ptr().read() }
             }
 
-            /// Returns the raw pointer of this per-CPU static variable on the given CPU.
+            /// Set the per-CPU data on the current CPU. Preemption will be disabled during the call.
             ///
-            /// # Safety
+            /// It is only available for `Copy` types, and is intended for small ones, e.g., `(u32, u32)` or a
+            /// `#[repr(C)]` struct of two integers.
+            pub fn write_current(&self, val: #ty)
+            where
+                for<'a> #ty: Copy,
+            {
+                #no_preempt_guard
+                unsafe { (self.current_ptr() as *mut #ty).write(val) }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // Generate counter methods for `percpu::PercpuCounter`, which is a `usize` in memory.
+    let counter_methods = if is_percpu_type(ty, "PercpuCounter") {
+        let usize_ty: Type = parse_quote!(usize);
+        let read_raw = arch::gen_read_current_raw(inner_symbol_name, &usize_ty);
+        let add_current = arch::gen_rmw_current(
+            RmwOp::Add,
+            inner_symbol_name,
+            &format_ident!("val"),
+            &usize_ty,
+            &no_preempt_guard,
+        );
+
+        quote! {
+            /// Adds `delta` to the counter on the current CPU. Preemption will be disabled during the call if
+            /// necessary.
             ///
-            /// Caller must ensure that
-            /// - the CPU ID is valid, and
-            /// - data races will not happen.
+            /// Only the per-CPU data area of the current CPU is touched.
             #[inline]
-            pub unsafe fn remote_ptr(&self, cpu_id: usize) -> *const #ty {
-                let base = percpu::percpu_area_base(cpu_id);
-                let offset = #offset;
-                (base + offset) as *const #ty
+            pub fn add_current(&self, delta: isize) {
+                let val = delta as usize;
+                #add_current
             }
 
-            /// Returns the reference of the per-CPU static variable on the given CPU.
-            ///
-            /// # Safety
-            ///
-            /// Caller must ensure that
-            /// - the CPU ID is valid, and
-            /// - data races will not happen.
+            /// Increments the counter on the current CPU. Preemption will be disabled during the call.
             #[inline]
-            pub unsafe fn remote_ref_raw(&self, cpu_id: usize) -> &#ty {
-                &*self.remote_ptr(cpu_id)
+            pub fn inc_current(&self) {
+                self.add_current(1)
             }
 
-            /// Returns the mutable reference of the per-CPU static variable on the given CPU.
-            ///
-            /// # Safety
-            ///
-            /// Caller must ensure that
-            /// - the CPU ID is valid, and
-            /// - data races will not happen.
+            /// Decrements the counter on the current CPU. Preemption will be disabled during the call.
             #[inline]
-            #[allow(clippy::mut_from_ref)]
-            pub unsafe fn remote_ref_mut_raw(&self, cpu_id: usize) -> &mut #ty {
-                &mut *(self.remote_ptr(cpu_id) as *mut #ty)
+            pub fn dec_current(&self) {
+                self.add_current(-1)
             }
 
-            #read_write_methods
-        }
-    }
-    .into()
+            /// Returns the counter value on the current CPU. Preemption will be disabled during the call.
+            ///
+            /// It is only the contribution of the current CPU, use [`sum`](Self::sum) to get the total value.
+            pub fn read_current(&self) -> isize {
+                #no_preempt_guard
+                let val: usize = unsafe { #read_raw };
+                val as isize
+            }
+
+            /// Returns the sum of the counter values on all CPUs.
+            ///
+            /// The per-CPU values are read one by one, so the result is only a snapshot if other CPUs are updating
+            /// the counter at the same time.
+            pub fn sum(&self) -> isize {
+                let mut sum = 0usize;
+                for cpu_id in 0..percpu::percpu_area_num() {
+                    let val = unsafe {
+                        ::core::sync::atomic::AtomicUsize::from_ptr(self.remote_ptr(cpu_id) as *mut usize)
+                            .load(::core::sync::atomic::Ordering::Relaxed)
+                    };
+                    sum = sum.wrapping_add(val);
+                }
+                sum as isize
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // Generate flag methods for `percpu::PercpuFlag`. The flags on other CPUs are referenced directly, which is sound
+    // since no accessor hands out `&mut PercpuFlag` (see `is_shared_only_type`), and so are the other synchronization
+    // types below.
+    let flag_methods = if is_percpu_type(ty, "PercpuFlag") {
+        quote! {
+            /// Sets the flag on the current CPU, with release ordering. Preemption will be disabled during the call.
+            #[inline]
+            pub fn set_current(&self) {
+                #no_preempt_guard
+                unsafe { self.current_ref_raw() }.set()
+            }
+
+            /// Clears the flag on the current CPU, with release ordering. Preemption will be disabled during the call.
+            #[inline]
+            pub fn clear_current(&self) {
+                #no_preempt_guard
+                unsafe { self.current_ref_raw() }.clear()
+            }
+
+            /// Returns whether the flag on the current CPU is set. Preemption will be disabled during the call.
+            #[inline]
+            pub fn is_set_current(&self) -> bool {
+                #no_preempt_guard
+                unsafe { self.current_ref_raw() }.is_set()
+            }
+
+            /// Returns whether the flag on the given CPU is set, with acquire ordering.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `cpu_id` is not less than [`percpu_area_num()`](percpu::percpu_area_num).
+            #[inline]
+            pub fn is_set_remote(&self, cpu_id: impl Into<percpu::CpuId>) -> bool {
+                let cpu_id = cpu_id.into().get();
+                assert!(cpu_id < percpu::percpu_area_num(), "invalid CPU ID: {}", cpu_id);
+                unsafe { self.remote_ref_raw(cpu_id) }.is_set()
+            }
+
+            /// Spins until the flags on all the given CPUs are set, e.g., `wait_until_all_set(1..num_cpus)`.
+            ///
+            /// Everything written by these CPUs before setting their flags is visible after the call. The flags
+            /// must not be cleared until all waiters return.
+            ///
+            /// # Panics
+            ///
+            /// Panics if a given CPU ID is not less than [`percpu_area_num()`](percpu::percpu_area_num).
+            pub fn wait_until_all_set<I>(&self, cpus: I)
+            where
+                I: IntoIterator,
+                I::Item: Into<percpu::CpuId>,
+            {
+                for cpu_id in cpus {
+                    let cpu_id = cpu_id.into().get();
+                    assert!(cpu_id < percpu::percpu_area_num(), "invalid CPU ID: {}", cpu_id);
+                    unsafe { self.remote_ref_raw(cpu_id) }.wait();
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // Generate epoch methods for `percpu::PercpuEpoch`.
+    let epoch_methods = if is_percpu_type(ty, "PercpuEpoch") {
+        quote! {
+            /// Bumps the epoch of the current CPU, which marks a quiescent point where it holds no reference to the
+            /// data protected by the epoch. Preemption will be disabled during the call.
+            ///
+            /// Only the per-CPU data area of the current CPU is touched.
+            #[inline]
+            pub fn quiescent_current(&self) {
+                #no_preempt_guard
+                unsafe { self.current_ref_raw() }.quiescent()
+            }
+
+            /// Spins until every online CPU other than the current one has bumped its epoch after the call begins,
+            /// so that the data unpublished before the call is no longer referenced. Preemption will be disabled
+            /// during the call.
+            ///
+            /// It must not be called between quiescent points of the current CPU, where it may hold references.
+            pub fn synchronize(&self) {
+                #no_preempt_guard
+                percpu::__priv::epoch_synchronize(percpu::current_cpu_id(), |cpu_id| unsafe {
+                    self.remote_ref_raw(cpu_id)
+                })
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // Generate work queue methods for `percpu::PercpuWorkQueue`.
+    let work_queue_methods = if is_percpu_type(ty, "PercpuWorkQueue") {
+        quote! {
+            /// Pushes `work` to the queue of the current CPU, with release ordering. Returns `Err(work)` if the queue
+            /// is full. Preemption will be disabled during the call.
+            #[inline]
+            pub fn push_current(&self, work: fn()) -> Result<(), fn()> {
+                #no_preempt_guard
+                unsafe { self.current_ref_raw() }.push(work)
+            }
+
+            /// Pushes `work` to the queue of the given CPU, with release ordering. Returns `Err(work)` if the queue
+            /// is full.
+            ///
+            /// The given CPU runs the work when it calls [`drain_current`](Self::drain_current), so it should be
+            /// notified, e.g., by an IPI.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `cpu_id` is not less than [`percpu_area_num()`](percpu::percpu_area_num).
+            pub fn push_remote(&self, cpu_id: impl Into<percpu::CpuId>, work: fn()) -> Result<(), fn()> {
+                let cpu_id = cpu_id.into().get();
+                assert!(cpu_id < percpu::percpu_area_num(), "invalid CPU ID: {}", cpu_id);
+                unsafe { self.remote_ref_raw(cpu_id) }.push(work)
+            }
+
+            /// Runs the works pending on the current CPU in the order they are pushed, and returns the number of
+            /// them. Preemption will be disabled during the call, including the works.
+            ///
+            /// Works pushed during the call are left for the next call.
+            pub fn drain_current(&self) -> usize {
+                #no_preempt_guard
+                unsafe { self.current_ref_raw() }.drain()
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // Generate methods for `percpu::PercpuRef`, `percpu::PercpuRwLock` and `percpu::PercpuCounterBatched`, whose
+    // states shared by all CPUs are stored in a global static variable.
+    let shared_symbol_name = &format_ident!("__PERCPU_{}_SHARED", name);
+    let (shared_ty, shared_methods) = if is_percpu_type(ty, "PercpuRef") {
+        let ref_methods = quote! {
+            /// Acquires a reference. The caller must already hold one. Preemption will be disabled during the call.
+            ///
+            /// Only the per-CPU data area of the current CPU is touched, unless the counter has been killed.
+            #[inline]
+            pub fn get(&self) {
+                #no_preempt_guard
+                #shared_symbol_name.get(unsafe { self.current_ref_raw() })
+            }
+
+            /// Releases a reference, returns `true` if it was the last one. Preemption will be disabled during the
+            /// call.
+            ///
+            /// Only the per-CPU data area of the current CPU is touched, unless the counter has been killed.
+            #[inline]
+            pub fn put(&self) -> bool {
+                #no_preempt_guard
+                #shared_symbol_name.put(unsafe { self.current_ref_raw() })
+            }
+
+            /// Switches the counter to the shared atomic mode, and drops the initial reference. Returns `true` if it
+            /// was the last one.
+            ///
+            /// It waits for the `get`/`put` in progress on other CPUs, and collects the per-CPU counts on all CPUs.
+            ///
+            /// # Panics
+            ///
+            /// Panics if the counter has already been killed.
+            pub fn kill(&self) -> bool {
+                #shared_symbol_name.kill(
+                    (0..percpu::percpu_area_num()).map(|cpu_id| unsafe { self.remote_ref_raw(cpu_id) }),
+                )
+            }
+
+            /// Returns whether the counter has been killed.
+            #[inline]
+            pub fn is_killed(&self) -> bool {
+                #shared_symbol_name.is_killed()
+            }
+        };
+        (Some(format_ident!("PercpuRefShared")), ref_methods)
+    } else if is_percpu_type(ty, "PercpuRwLock") {
+        let rwlock_methods = quote! {
+            /// Acquires the read lock, spinning while a writer holds (or is waiting for) the lock.
+            ///
+            /// Only the per-CPU data area of the current CPU is touched if there is no writer.
+            pub fn read_lock(&self) {
+                loop {
+                    {
+                        #no_preempt_guard
+                        if #shared_symbol_name.try_read_lock(unsafe { self.current_ref_raw() }) {
+                            return;
+                        }
+                    }
+                    #shared_symbol_name.wait_for_writer();
+                }
+            }
+
+            /// Releases the read lock. It may be called on a CPU other than the one the lock was acquired on.
+            ///
+            /// # Safety
+            ///
+            /// The read lock must be held by the caller.
+            pub unsafe fn read_unlock(&self) {
+                #no_preempt_guard
+                #shared_symbol_name.read_unlock(self.current_ref_raw())
+            }
+
+            /// Acquires the write lock, spinning until all readers and other writers have released the lock.
+            ///
+            /// The per-CPU data areas of all CPUs are read.
+            pub fn write_lock(&self) {
+                #shared_symbol_name.write_lock(|| {
+                    (0..percpu::percpu_area_num()).map(|cpu_id| unsafe { self.remote_ref_raw(cpu_id) })
+                })
+            }
+
+            /// Releases the write lock.
+            ///
+            /// # Safety
+            ///
+            /// The write lock must be held by the caller.
+            pub unsafe fn write_unlock(&self) {
+                #shared_symbol_name.write_unlock()
+            }
+
+            /// Calls `f` with the read lock held.
+            pub fn read<T>(&self, f: impl FnOnce() -> T) -> T {
+                self.read_lock();
+                let ret = f();
+                unsafe { self.read_unlock() };
+                ret
+            }
+
+            /// Calls `f` with the write lock held.
+            pub fn write<T>(&self, f: impl FnOnce() -> T) -> T {
+                self.write_lock();
+                let ret = f();
+                unsafe { self.write_unlock() };
+                ret
+            }
+        };
+        (Some(format_ident!("PercpuRwLockShared")), rwlock_methods)
+    } else if is_percpu_type(ty, "PercpuCounterBatched") {
+        let counter_methods = quote! {
+            /// Adds `delta` to the counter on the current CPU. Preemption will be disabled during the call.
+            ///
+            /// Only the per-CPU data area of the current CPU is touched, unless the local count reaches the batch
+            /// threshold and is flushed into the shared count.
+            #[inline]
+            pub fn add_current(&self, delta: i64) {
+                #no_preempt_guard
+                #shared_symbol_name.add(unsafe { self.current_ref_raw() }, delta)
+            }
+
+            /// Increments the counter on the current CPU. Preemption will be disabled during the call.
+            #[inline]
+            pub fn inc_current(&self) {
+                self.add_current(1)
+            }
+
+            /// Decrements the counter on the current CPU. Preemption will be disabled during the call.
+            #[inline]
+            pub fn dec_current(&self) {
+                self.add_current(-1)
+            }
+
+            /// Returns the shared count, which is off by less than the batch threshold per CPU.
+            #[inline]
+            pub fn approx_sum(&self) -> i64 {
+                #shared_symbol_name.approx_sum()
+            }
+
+            /// Returns the shared count plus the local counts on all CPUs.
+            ///
+            /// The local counts are read one by one, so the result is only a snapshot if other CPUs are updating
+            /// the counter at the same time.
+            pub fn precise_sum(&self) -> i64 {
+                #shared_symbol_name.precise_sum(
+                    (0..percpu::percpu_area_num()).map(|cpu_id| unsafe { self.remote_ref_raw(cpu_id) }),
+                )
+            }
+
+            /// Returns the batch threshold.
+            #[inline]
+            pub fn batch(&self) -> i64 {
+                #shared_symbol_name.batch()
+            }
+
+            /// Sets the batch threshold, the local count on each CPU is flushed into the shared count when its
+            /// absolute value reaches it.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `batch` is not positive.
+            pub fn set_batch(&self, batch: i64) {
+                #shared_symbol_name.set_batch(batch)
+            }
+        };
+        (
+            Some(format_ident!("PercpuCounterBatchedShared")),
+            counter_methods,
+        )
+    } else {
+        (None, quote! {})
+    };
+    let shared_symbol = shared_ty.map(|shared_ty| {
+        quote! {
+            #(#attrs)*
+            static #shared_symbol_name: percpu::__priv::#shared_ty = percpu::__priv::#shared_ty::new();
+        }
+    });
+
+    // Generate methods for `percpu::PerCpuRefCell<T>`. The guards keep preemption disabled and the cell borrowed until
+    // they are dropped.
+    let refcell_methods = if let Some(cell_ty) = percpu_type_arg(ty, "PerCpuRefCell") {
+        quote! {
+            /// Borrows the per-CPU data on the current CPU, returns a guard that dereferences to it. Preemption will
+            /// be disabled until the guard is dropped.
+            ///
+            /// # Panics
+            ///
+            /// Panics if the per-CPU data is mutably borrowed on the current CPU.
+            #[inline]
+            #[track_caller]
+            pub fn borrow_current(&self) -> percpu::PerCpuBorrow<'_, #cell_ty> {
+                match self.try_borrow_current() {
+                    Some(borrow) => borrow,
+                    None => panic!(concat!(
+                        "the per-CPU data `",
+                        stringify!(#name),
+                        "` is already mutably borrowed on the current CPU"
+                    )),
+                }
+            }
+
+            /// Mutably borrows the per-CPU data on the current CPU, returns a guard that mutably dereferences to it.
+            /// Preemption will be disabled until the guard is dropped.
+            ///
+            /// # Panics
+            ///
+            /// Panics if the per-CPU data is borrowed on the current CPU.
+            #[inline]
+            #[track_caller]
+            pub fn borrow_mut_current(&self) -> percpu::PerCpuBorrowMut<'_, #cell_ty> {
+                match self.try_borrow_mut_current() {
+                    Some(borrow) => borrow,
+                    None => panic!(concat!(
+                        "the per-CPU data `",
+                        stringify!(#name),
+                        "` is already borrowed on the current CPU"
+                    )),
+                }
+            }
+
+            /// Borrows the per-CPU data on the current CPU like [`borrow_current`](Self::borrow_current), or returns
+            /// `None` if it is mutably borrowed.
+            #[inline]
+            pub fn try_borrow_current(&self) -> Option<percpu::PerCpuBorrow<'_, #cell_ty>> {
+                unsafe { percpu::PerCpuBorrow::new(|| self.current_ref_raw()) }
+            }
+
+            /// Mutably borrows the per-CPU data on the current CPU like
+            /// [`borrow_mut_current`](Self::borrow_mut_current), or returns `None` if it is borrowed.
+            #[inline]
+            pub fn try_borrow_mut_current(&self) -> Option<percpu::PerCpuBorrowMut<'_, #cell_ty>> {
+                unsafe { percpu::PerCpuBorrowMut::new(|| self.current_ref_raw()) }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // Generate methods for `percpu::PerCpuOnce<T>`. The value can not be changed after it is set, since there is no
+    // accessor that hands out `&mut PerCpuOnce<T>`, so the references are not guarded.
+    let once_methods = if let Some(once_ty) = percpu_type_arg(ty, "PerCpuOnce") {
+        quote! {
+            /// Sets the per-CPU data on the current CPU, returns `Err(val)` if it has already been set. Preemption
+            /// will be disabled during the call.
+            pub fn set_current(&self, val: #once_ty) -> Result<(), #once_ty> {
+                #no_preempt_guard
+                unsafe { self.current_ref_raw() }.set(val)
+            }
+
+            /// Returns the reference of the per-CPU data on the current CPU, or `None` if it has not been set.
+            /// Preemption will be disabled during the call.
+            pub fn get_current(&self) -> Option<&#once_ty>
+            where
+                for<'a> #once_ty: Sync,
+            {
+                #no_preempt_guard
+                unsafe { self.current_ref_raw() }.get()
+            }
+
+            /// Returns the reference of the per-CPU data on the current CPU, setting it with `f` first if it has not
+            /// been set. Preemption will be disabled during the call.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `f` accesses the per-CPU data recursively.
+            pub fn get_or_init_current<F>(&self, f: F) -> &#once_ty
+            where
+                F: FnOnce() -> #once_ty,
+                for<'a> #once_ty: Sync,
+            {
+                #no_preempt_guard
+                unsafe { self.current_ref_raw() }.get_or_init(f)
+            }
+
+            /// Returns whether the per-CPU data on the current CPU has been set. Preemption will be disabled during
+            /// the call.
+            pub fn is_set_current(&self) -> bool {
+                #no_preempt_guard
+                unsafe { self.current_ref_raw() }.is_set()
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let lazy_methods = if args.lazy {
+        quote! {
+            /// Initializes the lazy per-CPU data on the current CPU with `f`, instead of the initialization
+            /// expression. Preemption will be disabled during the call.
+            ///
+            /// # Panics
+            ///
+            /// Panics if the per-CPU data on the current CPU has already been initialized.
+            pub fn init_current<F>(&self, f: F)
+            where
+                F: FnOnce() -> #value_ty,
+            {
+                #no_preempt_guard
+                unsafe { self.current_ref_raw() }.init(f);
+            }
+
+            /// Returns whether the lazy per-CPU data on the current CPU has been initialized. Preemption will be
+            /// disabled during the call.
+            pub fn is_init_current(&self) -> bool {
+                #no_preempt_guard
+                unsafe { self.current_ref_raw() }.is_init()
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // Zero-initialized per-CPU data is placed in `.percpu.bss`, which is cleared instead of copied during
+    // initialization. On Windows, the grouped sections `.percpu$b` and `.percpu$d` are merged into `.percpu` in that
+    // order by the linker.
+    let (section, coff_section) = if is_zero_expr(init_expr) {
+        (".percpu.bss", ".percpu$b")
+    } else {
+        (".percpu", ".percpu$d")
+    };
+    // Hot (or cold) per-CPU data is placed in the subsection `.percpu.hot` (or `.percpu.cold`), which is placed before
+    // (or after) other per-CPU data by the linker script. Read-mostly data is placed in `.percpu.read_mostly`, which
+    // is surrounded by cache line boundaries.
+    let section = match args.placement {
+        Some(placement) => format!("{section}.{}", placement.name()),
+        None => section.to_string(),
+    };
+    // Per-CPU data with the known alignment is placed in the subsection `.percpu.alignN` (or `.percpu.bss.alignN`),
+    // so that the linker can sort the subsections by alignment with `SORT_BY_ALIGNMENT` to minimize the padding. Data
+    // in the same section of an object file can not be reordered by the linker.
+    let section = match args.align.or_else(|| check::obvious_align(ty)) {
+        Some(align) => format!("{section}.align{align}"),
+        None => section,
+    };
+    // Per-CPU data in a user-named subsection is kept together, so it is neither split into the zero-initialized part
+    // nor sorted by alignment.
+    let section = args.section.clone().unwrap_or(section);
+
+    // For aligned per-CPU data, the data is wrapped in a `#[repr(C, align(N))]` struct, whose address is the same as
+    // the data.
+    let (storage_ty, storage_init, aligned_def) = if let Some(align) = args.align {
+        let align = proc_macro2::Literal::usize_unsuffixed(align);
+        let aligned_ty = format_ident!("__PERCPU_{}_ALIGNED", name);
+        (
+            quote!(#aligned_ty),
+            quote!(#aligned_ty(#init_expr)),
+            quote! {
+                #[repr(C, align(#align))]
+                #[allow(non_camel_case_types)]
+                struct #aligned_ty(#ty);
+            },
+        )
+    } else {
+        (quote!(#ty), quote!(#init_expr), quote! {})
+    };
+    let thread_local = arch::gen_thread_local(inner_symbol_name, &storage_ty, &storage_init);
+    // Custom sections are not loaded into the linear memory on WebAssembly, so the per-CPU data (and the tables
+    // below) are left in the default sections there.
+    let inner_symbol = quote! {
+        #aligned_def
+
+        #[cfg_attr(not(any(target_os = "macos", target_os = "windows", target_family = "wasm")), link_section = #section)]
+        #[cfg_attr(target_os = "windows", link_section = #coff_section)]
+        #(#inner_attrs)*
+        #[allow(dead_code)] // unused if the per-CPU data is thread-local
+        static #inner_symbol_name: percpu::__priv::PercpuStorage<#storage_ty> =
+            percpu::__priv::PercpuStorage::new(#storage_init);
+        #inner_alias
+        #thread_local
+    };
+
+    // The borrow states are zero-initialized per-CPU data in the subsection `.percpu.bss.borrow`, which count the
+    // shared borrows of the per-CPU data on the current CPU, or are `-1` while it is mutably borrowed.
+    let (borrow_symbol, borrow_check, shared_borrow_check) = if cfg!(feature = "debug-borrow-check")
+    {
+        let borrow_symbol_name = format_ident!("{}_BORROW", inner_symbol_name);
+        let borrow_offset = arch::gen_offset(&borrow_symbol_name);
+        let thread_local = arch::gen_thread_local(&borrow_symbol_name, &quote!(isize), &quote!(0));
+        (
+            quote! {
+                #[cfg_attr(not(any(target_os = "macos", target_os = "windows", target_family = "wasm")), link_section = ".percpu.bss.borrow")]
+                #[cfg_attr(target_os = "windows", link_section = ".percpu$b")]
+                #(#attrs)*
+                #[allow(dead_code)] // unused if the per-CPU data is thread-local
+                static #borrow_symbol_name: percpu::__priv::PercpuStorage<isize> = percpu::__priv::PercpuStorage::new(0);
+                #thread_local
+            },
+            quote! { percpu::__priv::BorrowGuard::new(#borrow_offset, stringify!(#name)) },
+            quote! { percpu::__priv::BorrowGuard::new_shared(#borrow_offset, stringify!(#name)) },
+        )
+    } else {
+        (quote! {}, quote! {}, quote! {})
+    };
+    let borrow_guard = if cfg!(feature = "debug-borrow-check") {
+        quote! { let _borrow = #borrow_check; }
+    } else {
+        quote! {}
+    };
+    let current_mut = if cfg!(feature = "debug-borrow-check") {
+        quote! { percpu::PerCpuRefMut::new_checked(|| #borrow_check, || self.current_ref_mut_raw()) }
+    } else {
+        quote! { percpu::PerCpuRefMut::new(|| self.current_ref_mut_raw()) }
+    };
+    let current_ref = if cfg!(feature = "debug-borrow-check") {
+        quote! { percpu::PerCpuRef::new_checked(|| #shared_borrow_check, || self.current_ref_raw()) }
+    } else {
+        quote! { percpu::PerCpuRef::new(|| self.current_ref_raw()) }
+    };
+
+    let with_current_irqsave = if cfg!(feature = "irq") {
+        quote! {
+            /// Manipulate the per-CPU data on the current CPU in the given closure.
+            /// Local IRQs will be disabled during the call.
+            pub fn with_current_irqsave<F, T>(&self, f: F) -> T
+            where
+                F: FnOnce(&mut #ty) -> T,
+            {
+                #irqsave_guard
+                #borrow_guard
+                f(unsafe { self.current_ref_mut_raw() })
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // Register the destructor of the per-CPU data in the `percpu_dtors` section, which is run by `percpu::deinit`.
+    let dtor_symbol_name = format_ident!("__PERCPU_{}_DTOR", name);
+    let dtor_symbol = quote! {
+        #[cfg_attr(not(any(target_os = "macos", target_os = "windows", target_family = "wasm")), link_section = "percpu_dtors")]
+        #[cfg_attr(target_os = "macos", link_section = "__DATA,__percpu_dtors")]
+        #[used]
+        #(#attrs)*
+        static #dtor_symbol_name: percpu::__priv::PercpuDtor = if ::core::mem::needs_drop::<#ty>() {
+            unsafe fn dtor(cpu_id: usize) {
+                ::core::ptr::drop_in_place((percpu::percpu_area_base(cpu_id) + #name.offset()) as *mut #ty)
+            }
+            Some(dtor)
+        } else {
+            None
+        };
+    };
+
+    // Record the per-CPU data in a user-named subsection in the `percpu_subsections` section, which is looked up by
+    // `percpu::percpu_area_range_of`.
+    let subsection_symbol = args.section.as_ref().map(|section| {
+        let subsection_symbol_name = format_ident!("__PERCPU_{}_SUBSECTION", name);
+        quote! {
+            #[cfg_attr(
+                not(any(target_os = "macos", target_os = "windows", target_family = "wasm")),
+                link_section = "percpu_subsections"
+            )]
+            #[cfg_attr(target_os = "macos", link_section = "__DATA,__percpu_subsec")]
+            #[used]
+            #(#attrs)*
+            static #subsection_symbol_name: percpu::__priv::PercpuSubsectionVar =
+                percpu::__priv::PercpuSubsectionVar::new(
+                    #section,
+                    ::core::mem::size_of::<#storage_ty>(),
+                    || #name.offset(),
+                );
+        }
+    });
+
+    // Record the information of the per-CPU data in the `percpu_layout` section, which is listed by `percpu::layout`.
+    let info_symbol = if cfg!(feature = "introspect") {
+        let info_symbol_name = format_ident!("__PERCPU_{}_INFO", name);
+        quote! {
+            #[cfg_attr(not(any(target_os = "macos", target_os = "windows", target_family = "wasm")), link_section = "percpu_layout")]
+            #[cfg_attr(target_os = "macos", link_section = "__DATA,__percpu_layout")]
+            #[used]
+            #(#attrs)*
+            static #info_symbol_name: percpu::PercpuVarInfo = percpu::PercpuVarInfo::new(
+                stringify!(#name),
+                stringify!(#value_ty),
+                ::core::mem::size_of::<#ty>(),
+                || #name.offset(),
+            );
+        }
+    } else {
+        quote! {}
+    };
+
+    // The hit counters are zero-initialized per-CPU data in the subsection `.percpu.bss.profile`, and are registered in
+    // the `percpu_profile` section, which is listed by `percpu::profile_report`.
+    let profile_symbol = if profile_enabled() {
+        let hits_symbol_name = profile_symbol_name(inner_symbol_name);
+        let profile_info_name = format_ident!("__PERCPU_{}_PROFILE", name);
+        let hits_offset = arch::gen_offset(&hits_symbol_name);
+        let thread_local = arch::gen_thread_local(&hits_symbol_name, &quote!(usize), &quote!(0));
+        quote! {
+            #[cfg_attr(not(any(target_os = "macos", target_os = "windows", target_family = "wasm")), link_section = ".percpu.bss.profile")]
+            #[cfg_attr(target_os = "windows", link_section = ".percpu$b")]
+            #(#attrs)*
+            #[allow(dead_code)] // unused if the per-CPU data is thread-local
+            static #hits_symbol_name: percpu::__priv::PercpuStorage<usize> = percpu::__priv::PercpuStorage::new(0);
+            #thread_local
+
+            #[cfg_attr(not(any(target_os = "macos", target_os = "windows", target_family = "wasm")), link_section = "percpu_profile")]
+            #[cfg_attr(target_os = "macos", link_section = "__DATA,__percpu_profile")]
+            #[used]
+            #(#attrs)*
+            static #profile_info_name: percpu::PercpuProfile =
+                percpu::PercpuProfile::new(stringify!(#name), || #hits_offset);
+        }
+    } else {
+        quote! {}
+    };
+    let profile_hit = gen_profile_hit(inner_symbol_name);
+
+    let offset = arch::gen_offset(inner_symbol_name);
+    let current_ptr = arch::gen_current_ptr(inner_symbol_name, ty);
+
+    // The offset is resolved by the linker, so it can not be an integer in const contexts, but a pointer can.
+    let offset_ptr_method = if cfg!(feature = "const-offset") {
+        quote! {
+            /// Returns a pointer whose address is the offset relative to the per-CPU data area base, which can be
+            /// used in const contexts, e.g., static jump tables.
+            ///
+            /// It is the address of the per-CPU data in the `.percpu` section, which is linked at address 0 on
+            /// bare-metal targets. Do not dereference it.
+            #[inline]
+            pub const fn offset_ptr(&self) -> *const #ty {
+                ::core::ptr::addr_of!(#inner_symbol_name).cast::<#ty>()
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // The accessors that hand out `&mut T`, which are not generated for the types only accessed by shared references.
+    let (mut_methods, current_mut_method, percpu_mut_impl) = if shared_only {
+        (quote! {}, quote! {}, quote! {})
+    } else {
+        let mut_methods = quote! {
+            /// Manipulate the per-CPU data on the current CPU in the given closure.
+            /// Preemption will be disabled during the call.
+            pub fn with_current<F, T>(&self, f: F) -> T
+            where
+                F: FnOnce(&mut #ty) -> T,
+            {
+                #no_preempt_guard
+                #borrow_guard
+                f(unsafe { self.current_ref_mut_raw() })
+            }
+
+            #with_current_irqsave
+
+            /// Updates the per-CPU data on the current CPU in the given closure, and returns its result, just like
+            /// [`with_current`](Self::with_current). Preemption will be disabled during the call.
+            #[inline]
+            pub fn update_current<F, R>(&self, f: F) -> R
+            where
+                F: FnOnce(&mut #ty) -> R,
+            {
+                self.with_current(f)
+            }
+
+            /// Replaces the per-CPU data on the current CPU with `val`, and returns the old value. Preemption will be
+            /// disabled during the call.
+            #[inline]
+            pub fn replace_current(&self, val: #ty) -> #ty {
+                self.with_current(|old| ::core::mem::replace(old, val))
+            }
+
+            /// Takes the per-CPU data on the current CPU, leaving the default value in its place. Preemption will be
+            /// disabled during the call.
+            ///
+            /// It is only available for types that implement `Default`.
+            #[inline]
+            pub fn take_current(&self) -> #ty
+            where
+                for<'a> #ty: Default,
+            {
+                self.with_current(::core::mem::take)
+            }
+        };
+        let current_mut_method = quote! {
+            /// Returns a guard that mutably dereferences to the per-CPU data on the current CPU.
+            /// Preemption will be disabled until the guard is dropped.
+            ///
+            /// # Safety
+            ///
+            /// No other reference to the per-CPU data on the current CPU (e.g., from another `current_mut()` or
+            /// [`current()`](Self::current), or inside [`with_current`](Self::with_current)) may exist while the
+            /// guard is alive. With the `debug-borrow-check` feature, a nested mutable access panics instead.
+            #[inline]
+            pub unsafe fn current_mut(&self) -> percpu::PerCpuRefMut<'_, #ty> {
+                #current_mut
+            }
+        };
+        let percpu_mut_impl = quote! {
+            impl percpu::PerCpuMut<#ty> for #struct_name {
+                #[inline]
+                fn with_current<F, R>(&self, f: F) -> R
+                where
+                    F: FnOnce(&mut #ty) -> R,
+                {
+                    Self::with_current(self, f)
+                }
+            }
+        };
+        (mut_methods, current_mut_method, percpu_mut_impl)
+    };
+
+    // Atomic per-CPU data can be shared by all CPUs safely, since no accessor hands out `&mut` of it (see
+    // `is_shared_only_type`), so plain references are returned instead of guards.
+    let (current_method, atomic_methods) = if is_atomic_type(ty) {
+        let current_method = quote! {
+            /// Returns the reference of the atomic per-CPU data on the current CPU.
+            ///
+            /// Preemption is not disabled, so it may be the data on another CPU if the caller is migrated. It is
+            /// still safe since all accesses are atomic.
+            #[inline]
+            pub fn current(&self) -> &#ty {
+                unsafe { &*{ #current_ptr } }
+            }
+        };
+        let atomic_methods = (!args.no_remote).then(|| {
+            quote! {
+                /// Returns the reference of the atomic per-CPU data on the given CPU.
+                ///
+                /// # Panics
+                ///
+                /// Panics if `cpu_id` is not less than [`percpu_area_num()`](percpu::percpu_area_num).
+                #[inline]
+                pub fn remote(&self, cpu_id: impl Into<percpu::CpuId>) -> &#ty {
+                    let cpu_id = cpu_id.into().get();
+                    assert!(cpu_id < percpu::percpu_area_num(), "invalid CPU ID: {}", cpu_id);
+                    unsafe { self.remote_ref_raw(cpu_id) }
+                }
+            }
+        });
+        (current_method, atomic_methods)
+    } else if percpu_type_arg(ty, "PerCpuRefCell").is_some() {
+        // The cell must be borrowed by `borrow_current` or `borrow_mut_current`, which track the borrow state.
+        (quote! {}, None)
+    } else {
+        // The guard may alias the `&mut T` of the other accessors, so it is only safe for the types only accessed by
+        // shared references.
+        let current_method = if shared_only {
+            quote! {
+                /// Returns a guard that dereferences to the per-CPU data on the current CPU.
+                /// Preemption will be disabled until the guard is dropped.
+                #[inline]
+                pub fn current(&self) -> percpu::PerCpuRef<'_, #ty> {
+                    unsafe { percpu::PerCpuRef::new(|| self.current_ref_raw()) }
+                }
+            }
+        } else {
+            quote! {
+                /// Returns a guard that dereferences to the per-CPU data on the current CPU.
+                /// Preemption will be disabled until the guard is dropped.
+                ///
+                /// # Safety
+                ///
+                /// The per-CPU data on the current CPU must not be mutated while the guard is alive, e.g., by
+                /// [`with_current`](Self::with_current), [`replace_current`](Self::replace_current) or `write_current`.
+                /// With the `debug-borrow-check` feature, a nested mutable access by the former panics instead.
+                #[inline]
+                pub unsafe fn current(&self) -> percpu::PerCpuRef<'_, #ty> {
+                    #current_ref
+                }
+            }
+        };
+        (current_method, None)
+    };
+
+    let no_preempt_guard_doc = args.no_preempt_guard.then(|| {
+        quote! {
+            ///
+            /// The accessors of the current CPU do not disable preemption, so it must only be accessed with preemption
+            /// or IRQs disabled, which is asserted with the `debug-preempt-check` feature.
+        }
+    });
+
+    let remote_mut_method = (!shared_only).then(|| {
+        quote! {
+            /// Returns the mutable reference of the per-CPU static variable on the given CPU, while all other CPUs
+            /// are parked by `percpu::with_all_cpus_parked`, which provides the token.
+            ///
+            /// # Panics
+            ///
+            /// Panics if the CPU ID is not less than `percpu::percpu_area_num()`, or it is the current CPU, whose
+            /// data may be borrowed by the current task (e.g., in `with_current`).
+            #[inline]
+            pub fn remote_mut<'t>(
+                &self,
+                _token: &'t mut percpu::ParkedCpus,
+                cpu_id: impl Into<percpu::CpuId>,
+            ) -> &'t mut #ty {
+                let cpu_id = cpu_id.into().get();
+                assert!(cpu_id < percpu::percpu_area_num(), "invalid CPU ID: {}", cpu_id);
+                assert!(cpu_id != percpu::current_cpu_id(), "the current CPU can not be accessed by `remote_mut`");
+                unsafe { &mut *(self.remote_ptr(cpu_id) as *mut #ty) }
+            }
+        }
+    });
+
+    let remote_methods = (!args.no_remote).then(|| {
+        quote! {
+                /// Returns the raw pointer of this per-CPU static variable on the given CPU.
+                ///
+                /// # Safety
+                ///
+                /// Caller must ensure that
+                /// - the CPU ID is valid, and
+                /// - data races will not happen.
+                ///
+                /// The CPU ID is either a `percpu::CpuId` or a `usize`, and is checked against
+                /// `percpu::percpu_area_num()` with debug assertions, except in the naive mode.
+                #[inline]
+                pub unsafe fn remote_ptr(&self, cpu_id: impl Into<percpu::CpuId>) -> *const #ty {
+                    let cpu_id = cpu_id.into().get();
+                    #remote_check
+                    let base = percpu::percpu_area_base(cpu_id);
+                    let offset = #offset;
+                    (base + offset) as *const #ty
+                }
+
+                /// Returns the reference of the per-CPU static variable on the given CPU.
+                ///
+                /// # Safety
+                ///
+                /// Caller must ensure that
+                /// - the CPU ID is valid, and
+                /// - data races will not happen.
+                #[inline]
+                pub unsafe fn remote_ref_raw(&self, cpu_id: impl Into<percpu::CpuId>) -> &#ty {
+                    let cpu_id = cpu_id.into().get();
+                    &*self.remote_ptr(cpu_id)
+                }
+
+                /// Returns the mutable reference of the per-CPU static variable on the given CPU.
+                ///
+                /// # Safety
+                ///
+                /// Caller must ensure that
+                /// - the CPU ID is valid, and
+                /// - data races will not happen.
+                #[inline]
+                #[allow(clippy::mut_from_ref)]
+                pub unsafe fn remote_ref_mut_raw(&self, cpu_id: impl Into<percpu::CpuId>) -> &mut #ty {
+                    let cpu_id = cpu_id.into().get();
+                    &mut *(self.remote_ptr(cpu_id) as *mut #ty)
+                }
+
+                #remote_mut_method
+
+                /// Returns an iterator over the CPU IDs and the references of the per-CPU static variable on all CPUs,
+                /// in the order of CPU IDs.
+                ///
+                /// # Safety
+                ///
+                /// Caller must ensure that data races will not happen on any CPU while iterating.
+                #[inline]
+                pub unsafe fn iter_remote(&self) -> impl Iterator<Item = (usize, &#ty)> + '_ {
+                    (0..percpu::percpu_area_num()).map(move |cpu_id| (cpu_id, self.remote_ref_raw(cpu_id)))
+                }
+
+                /// Calls `f` with the CPU ID and the reference of the per-CPU static variable on each CPU, in the order
+                /// of CPU IDs.
+                ///
+                /// # Safety
+                ///
+                /// Caller must ensure that data races will not happen on any CPU during the call.
+                pub unsafe fn for_each<F>(&self, mut f: F)
+                where
+                    F: FnMut(usize, &#ty),
+                {
+                    for (cpu_id, val) in self.iter_remote() {
+                        f(cpu_id, val);
+                    }
+                }
+
+                /// Folds the per-CPU static variable on all CPUs into an accumulator, in the order of CPU IDs.
+                ///
+                /// # Safety
+                ///
+                /// Caller must ensure that data races will not happen on any CPU during the call.
+                pub unsafe fn fold<B, F>(&self, init: B, mut f: F) -> B
+                where
+                    F: FnMut(B, usize, &#ty) -> B,
+                {
+                    self.iter_remote().fold(init, |acc, (cpu_id, val)| f(acc, cpu_id, val))
+                }
+        }
+    });
+    // The data of `no_remote` per-CPU static variables on other CPUs is only accessed by the destructor, so the trait
+    // method is poisoned for other CPUs with debug assertions.
+    let trait_remote_ptr = if args.no_remote {
+        quote! {
+            let base = percpu::percpu_area_base(cpu_id);
+            debug_assert_eq!(
+                base,
+                percpu::get_local_thread_pointer(),
+                "remote access to the CPU-private per-CPU data `{}`",
+                stringify!(#name),
+            );
+            (base + Self::offset(self)) as *const #ty
+        }
+    } else {
+        quote! { Self::remote_ptr(self, cpu_id) }
+    };
+    Ok(quote! {
+        #inner_symbol
+        #offset_sym
+        #shared_symbol
+        #dtor_symbol
+        #info_symbol
+        #subsection_symbol
+        #profile_symbol
+        #borrow_symbol
+
+        #builtin_check
+
+        #[doc = concat!("Wrapper struct for the per-CPU data [`", stringify!(#name), "`]")]
+        #no_preempt_guard_doc
+        #[allow(non_camel_case_types)]
+        #vis struct #struct_name {}
+
+        #(#attrs)*
+        #vis static #name: #struct_name = #struct_name {};
+
+        #export_c_fns
+
+        #debug_impl
+
+        impl percpu::PerCpu<#ty> for #struct_name {
+            #[inline]
+            fn offset(&self) -> usize {
+                Self::offset(self)
+            }
+
+            #[inline]
+            unsafe fn current_ptr(&self) -> *const #ty {
+                Self::current_ptr(self)
+            }
+
+            #[inline]
+            unsafe fn remote_ptr(&self, cpu_id: usize) -> *const #ty {
+                #trait_remote_ptr
+            }
+
+            #percpu_trait_methods
+        }
+
+        #percpu_mut_impl
+
+        impl #struct_name {
+            /// Returns the offset relative to the per-CPU data area base.
+            #[inline]
+            pub fn offset(&self) -> usize {
+                #offset
+            }
+
+            /// Returns the handle of this per-CPU static variable, which can be resolved on any CPU without knowing
+            /// the wrapper struct.
+            #[inline]
+            pub fn percpu_ptr(&self) -> percpu::PerCpuPtr<#ty> {
+                unsafe { percpu::PerCpuPtr::from_offset(self.offset()) }
+            }
+
+            #offset_ptr_method
+
+            /// Returns the raw pointer of this per-CPU static variable on the current CPU.
+            ///
+            /// # Safety
+            ///
+            /// Caller must ensure that preemption is disabled on the current CPU.
+            #[inline]
+            pub unsafe fn current_ptr(&self) -> *const #ty {
+                #preempt_check
+                #profile_hit
+                #current_ptr
+            }
+
+            /// Returns the reference of the per-CPU static variable on the current CPU.
+            ///
+            /// # Safety
+            ///
+            /// Caller must ensure that preemption is disabled on the current CPU.
+            #[inline]
+            pub unsafe fn current_ref_raw(&self) -> &#ty {
+                &*self.current_ptr()
+            }
+
+            /// Returns the mutable reference of the per-CPU static variable on the current CPU.
+            ///
+            /// # Safety
+            ///
+            /// Caller must ensure that preemption is disabled on the current CPU.
+            #[inline]
+            #[allow(clippy::mut_from_ref)]
+            pub unsafe fn current_ref_mut_raw(&self) -> &mut #ty {
+                &mut *(self.current_ptr() as *mut #ty)
+            }
+
+            #mut_methods
+
+            #current_method
+
+            #current_mut_method
+
+            #remote_methods
+
+            #read_write_methods
+            #counter_methods
+            #flag_methods
+            #work_queue_methods
+            #epoch_methods
+            #array_methods
+            #atomic_methods
+            #shared_methods
+            #lazy_methods
+            #once_methods
+            #refcell_methods
+        }
+    })
 }
 
 #[doc(hidden)]
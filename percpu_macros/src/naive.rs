@@ -1,23 +1,59 @@
-//! For single CPU use, we just make the per-CPU data a global variable.
+//! For single CPU use, we just make the per-CPU data a global variable, or a thread-local variable on hosted targets.
 
-use quote::quote;
+use quote::{format_ident, quote};
 use syn::{Ident, Type};
 
-pub fn gen_offset(symbol: &Ident) -> proc_macro2::TokenStream {
+use crate::RmwOp;
+
+/// The thread-local storage of the per-CPU data on hosted targets.
+fn thread_local_name(symbol: &Ident) -> Ident {
+    format_ident!("{}_TLS", symbol)
+}
+
+/// Generate the thread-local storage of the per-CPU data on hosted targets, so that each thread has its own copy of
+/// the per-CPU data, as if each thread runs on its own CPU. The inner symbol is used on bare-metal targets.
+pub fn gen_thread_local(
+    symbol: &Ident,
+    storage_ty: &proc_macro2::TokenStream,
+    storage_init: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let tls = thread_local_name(symbol);
     quote! {
-        unsafe { ::core::ptr::addr_of!(#symbol) as usize }
+        #[cfg(not(target_os = "none"))]
+        percpu::__priv::thread_local! {
+            static #tls: ::core::cell::UnsafeCell<#storage_ty> =
+                const { ::core::cell::UnsafeCell::new(#storage_init) };
+        }
     }
 }
 
-pub fn gen_current_ptr(symbol: &Ident, _ty: &Type) -> proc_macro2::TokenStream {
+/// Generate a code block that returns the address of the per-CPU data of the current thread.
+fn gen_addr(symbol: &Ident) -> proc_macro2::TokenStream {
+    let tls = thread_local_name(symbol);
+    quote! {
+        {
+            #[cfg(target_os = "none")]
+            { ::core::ptr::addr_of!(#symbol) as usize }
+            #[cfg(not(target_os = "none"))]
+            { #tls.with(|cell| cell.get() as usize) }
+        }
+    }
+}
+
+pub fn gen_offset(symbol: &Ident) -> proc_macro2::TokenStream {
+    gen_addr(symbol)
+}
+
+pub fn gen_current_ptr(symbol: &Ident, ty: &Type) -> proc_macro2::TokenStream {
+    let addr = gen_addr(symbol);
     quote! {
-        unsafe { ::core::ptr::addr_of!(#symbol) }
+        (#addr) as *const #ty
     }
 }
 
-pub fn gen_read_current_raw(_symbol: &Ident, _ty: &Type) -> proc_macro2::TokenStream {
+pub fn gen_read_current_raw(_symbol: &Ident, ty: &Type) -> proc_macro2::TokenStream {
     quote! {
-        *self.current_ptr()
+        *(self.current_ptr() as *const #ty)
     }
 }
 
@@ -26,3 +62,96 @@ pub fn gen_write_current_raw(_symbol: &Ident, val: &Ident, ty: &Type) -> proc_ma
         *(self.current_ptr() as *mut #ty) = #val
     }
 }
+
+pub fn gen_rmw_current(
+    op: RmwOp,
+    _symbol: &Ident,
+    val: &Ident,
+    ty: &Type,
+    guard: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let assign = op.assign(quote!(*ptr), val);
+    quote! {
+        #guard
+        unsafe {
+            let ptr = self.current_ptr() as *mut #ty;
+            #assign;
+        }
+    }
+}
+
+pub fn gen_fetch_add_current(
+    _symbol: &Ident,
+    val: &Ident,
+    ty: &Type,
+    guard: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    quote! {
+        #guard
+        unsafe {
+            let ptr = self.current_ptr() as *mut #ty;
+            let value = *ptr;
+            *ptr = value.wrapping_add(#val);
+            value
+        }
+    }
+}
+
+pub fn gen_swap_current(
+    _symbol: &Ident,
+    val: &Ident,
+    ty: &Type,
+    guard: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    quote! {
+        #guard
+        unsafe { (self.current_ptr() as *mut #ty).replace(#val) }
+    }
+}
+
+pub fn gen_compare_exchange_current(
+    _symbol: &Ident,
+    old: &Ident,
+    new: &Ident,
+    ty: &Type,
+    guard: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    quote! {
+        #guard
+        unsafe {
+            let ptr = self.current_ptr() as *mut #ty;
+            let value = *ptr;
+            if value == #old {
+                *ptr = #new;
+                Ok(value)
+            } else {
+                Err(value)
+            }
+        }
+    }
+}
+
+pub fn gen_read_current_at(
+    _symbol: &Ident,
+    index: &Ident,
+    elem_ty: &Type,
+    guard: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    quote! {
+        #guard
+        unsafe { (self.current_ptr() as *const #elem_ty).add(#index).read() }
+    }
+}
+
+pub fn gen_write_current_at(
+    _symbol: &Ident,
+    index: &Ident,
+    val: &Ident,
+    elem_ty: &Type,
+    guard: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    quote! {
+        #guard
+        unsafe { (self.current_ptr() as *mut #elem_ty).add(#index).write(#val) }
+    }
+}